@@ -0,0 +1,107 @@
+// Log of the values that changed when the open file was rewritten by another program, e.g.
+// acquisition software updating calibrated settings. Documents are compared in their JSON
+// form so that every format is diffed the same way.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use eframe::egui;
+use serde_json::Value as JsonValue;
+
+use crate::folder_search::preview;
+
+/// Oldest entries are dropped beyond this.
+const MAX_LOG_ENTRIES: usize = 1000;
+
+/// A value that differs between two versions of the document.
+pub(crate) struct ValueChange {
+    /// Local time the change was found, `HH:MM:SS`.
+    pub(crate) time: String,
+    pub(crate) path: Vec<String>,
+    /// `None` when the key was added.
+    pub(crate) old: Option<String>,
+    /// `None` when the key was removed.
+    pub(crate) new: Option<String>,
+}
+
+/// The key paths whose values differ between `old` and `new`, stamped with the current time.
+pub(crate) fn diff(old: &JsonValue, new: &JsonValue) -> Vec<ValueChange> {
+    let mut changes = vec![];
+    diff_value(Some(old), Some(new), &mut vec![], &clock_time(), &mut changes);
+    changes
+}
+
+fn diff_value(old: Option<&JsonValue>, new: Option<&JsonValue>, path: &mut Vec<String>, time: &str, changes: &mut Vec<ValueChange>) {
+    match (old, new) {
+        (Some(JsonValue::Object(old)), Some(JsonValue::Object(new))) => {
+            for (key, value) in old {
+                path.push(key.clone());
+                diff_value(Some(value), new.get(key), path, time, changes);
+                path.pop();
+            }
+            for (key, value) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                path.push(key.clone());
+                diff_value(None, Some(value), path, time, changes);
+                path.pop();
+            }
+        }
+        (Some(JsonValue::Array(old)), Some(JsonValue::Array(new))) => {
+            for i in 0..old.len().max(new.len()) {
+                path.push(i.to_string());
+                diff_value(old.get(i), new.get(i), path, time, changes);
+                path.pop();
+            }
+        }
+        (old, new) if old != new => changes.push(ValueChange {
+            time: time.to_string(),
+            path: path.clone(),
+            old: old.map(preview),
+            new: new.map(preview),
+        }),
+        _ => {}
+    }
+}
+
+/// Appends `changes` to `log`, dropping the oldest entries past the limit.
+pub(crate) fn append(log: &mut Vec<ValueChange>, changes: Vec<ValueChange>) {
+    log.extend(changes);
+    if log.len() > MAX_LOG_ENTRIES {
+        log.drain(..log.len() - MAX_LOG_ENTRIES);
+    }
+}
+
+/// The local time of day, `HH:MM:SS`.
+pub(crate) fn clock_time() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    #[cfg(unix)]
+    {
+        let time = secs as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if !unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+            return format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec);
+        }
+    }
+    let day_secs = secs % 86_400;
+    format!("{:02}:{:02}:{:02} UTC", day_secs / 3600, day_secs / 60 % 60, day_secs % 60)
+}
+
+/// Draws the log, newest first. Returns the key path of a clicked entry.
+pub(crate) fn render_change_log(ui: &mut egui::Ui, log: &mut Vec<ValueChange>) -> Option<Vec<String>> {
+    let mut clicked = None;
+    ui.horizontal(|ui| {
+        ui.label(format!("{} change(s) made outside the editor", log.len()));
+        if ui.button("Clear").clicked() {
+            log.clear();
+        }
+    });
+    ui.separator();
+    egui::ScrollArea::vertical().max_height(400.0).auto_shrink([false, true]).show(ui, |ui| {
+        for change in log.iter().rev() {
+            let (old, new) = (change.old.as_deref().unwrap_or("(none)"), change.new.as_deref().unwrap_or("(removed)"));
+            let text = egui::RichText::new(format!("{}  {}: {old} → {new}", change.time, change.path.join("."))).monospace();
+            if ui.selectable_label(false, text).on_hover_text("Show in the tree").clicked() {
+                clicked = Some(change.path.clone());
+            }
+        }
+    });
+    clicked
+}
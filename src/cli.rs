@@ -0,0 +1,183 @@
+// Headless subcommands: read and edit settings files from scripts without opening a window.
+
+use std::io::Write;
+
+use crate::convert;
+use crate::document::{detect_file_type, file_type_for, parse_key_path, supported_extensions, FileType, ParsedDocument};
+use crate::file_lock;
+use crate::preferences::Preferences;
+use crate::schema;
+
+const EXIT_OK: i32 = 0;
+/// The file is invalid: it does not parse, fails its schema, or `fmt --check` would change it.
+const EXIT_INVALID: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+const EXIT_NOT_FOUND: i32 = 4;
+
+const USAGE: &str = "\
+Usage:
+  yaml_viewer [FILE]                           open FILE in the editor
+  yaml_viewer -                                open stdin in the editor as an untitled buffer
+  yaml_viewer --new                            open an empty untitled buffer
+  yaml_viewer get <FILE> <PATH>                print the value at PATH
+  yaml_viewer set <FILE> <PATH> <VALUE>        set the value at PATH, creating parent mappings
+  yaml_viewer delete <FILE> <PATH>             remove the entry at PATH
+  yaml_viewer validate <FILE> [--schema <SCHEMA>]
+                                               check that FILE parses and matches a JSON Schema
+  yaml_viewer fmt <FILE> [--check]             rewrite FILE the way the editor saves it
+  yaml_viewer convert <FILE> <OUTPUT> [--strict]
+                                               write FILE in the format of OUTPUT's extension
+
+PATH is dot separated (`global.gates.0`); write `\\.` for a dot inside a key and `+` to append
+to a list. VALUE is read in the file's own syntax and falls back to a plain string.
+SCHEMA may be in any supported format; `pattern` and `format` keywords are not checked.
+`convert` warns about values the target format cannot hold; with --strict they fail the command.
+
+Exit codes: 0 success, 1 invalid file, 2 usage error, 3 read/write error, 4 path not found.";
+
+const SUBCOMMANDS: [&str; 6] = ["get", "set", "delete", "validate", "fmt", "convert"];
+
+/// Runs a subcommand and returns its exit code, or `None` when the arguments should open the editor.
+pub(crate) fn run(args: &[String]) -> Option<i32> {
+    let command = args.first()?.as_str();
+    if matches!(command, "help" | "-h" | "--help") {
+        println!("{USAGE}");
+        return Some(EXIT_OK);
+    }
+    if !SUBCOMMANDS.contains(&command) {
+        return None;
+    }
+    let args: Vec<&str> = args[1..].iter().map(String::as_str).collect();
+    // Scripts patch files that other programs write too; lock them the way the editor does.
+    file_lock::set_mode(Preferences::load().file_locking);
+    let result = match (command, args.as_slice()) {
+        ("get", [file, path]) => get(file, path),
+        ("set", [file, path, value]) => set(file, path, value),
+        ("delete", [file, path]) => delete(file, path),
+        ("validate", [file]) => validate(file, None),
+        ("validate", [file, "--schema", schema]) | ("validate", ["--schema", schema, file]) => validate(file, Some(schema)),
+        ("fmt", [file]) => fmt(file, false),
+        ("fmt", [file, "--check"]) | ("fmt", ["--check", file]) => fmt(file, true),
+        ("convert", [file, output]) => convert(file, output, false),
+        ("convert", [file, output, "--strict"]) | ("convert", ["--strict", file, output]) => convert(file, output, true),
+        _ => Err((EXIT_USAGE, format!("invalid arguments for `{command}`\n\n{USAGE}"))),
+    };
+    Some(match result {
+        Ok(()) => EXIT_OK,
+        Err((code, message)) => {
+            eprintln!("error: {message}");
+            code
+        }
+    })
+}
+
+type CliResult = Result<(), (i32, String)>;
+
+fn read(file: &str) -> Result<String, (i32, String)> {
+    file_lock::read_to_string(file, file_lock::BACKGROUND_WAIT).map_err(|err| (EXIT_IO, format!("{file}: {err}")))
+}
+
+/// Reads and parses `file`, telling its format from the extension or, failing that, the content.
+fn load(file: &str) -> Result<ParsedDocument, (i32, String)> {
+    let content = read(file)?;
+    parse_as(file, &content, file_type_for(file, &content))
+}
+
+fn parse_as(file: &str, content: &str, file_type: FileType) -> Result<ParsedDocument, (i32, String)> {
+    if file_type == FileType::Unknown {
+        return Err((EXIT_USAGE, unknown_type(file)));
+    }
+    ParsedDocument::parse(content, file_type)
+        .map_err(|err| (EXIT_INVALID, format!("{file}: invalid {}: {err}", file_type.label())))
+}
+
+fn unknown_type(file: &str) -> String {
+    format!("{file}: cannot tell the file type from its name or content; supported: .{}", supported_extensions().join(", ."))
+}
+
+fn write(file: &str, text: &str) -> CliResult {
+    file_lock::write(file, text, file_lock::BACKGROUND_WAIT).map_err(|err| (EXIT_IO, format!("{file}: {err}")))
+}
+
+fn save(file: &str, doc: &mut ParsedDocument) -> CliResult {
+    let text = doc.serialize().map_err(|err| (EXIT_INVALID, format!("{file}: {err}")))?;
+    write(file, &text)
+}
+
+fn get(file: &str, path: &str) -> CliResult {
+    let doc = load(file)?;
+    match doc.get(&parse_key_path(path)) {
+        Some(text) => {
+            // A closed pipe (`| head`) is not an error worth reporting.
+            let _ = writeln!(std::io::stdout(), "{}", text.trim_end_matches('\n'));
+            Ok(())
+        }
+        None => Err((EXIT_NOT_FOUND, format!("{path} not found in {file}"))),
+    }
+}
+
+fn set(file: &str, path: &str, value: &str) -> CliResult {
+    let mut doc = load(file)?;
+    doc.set(&parse_key_path(path), value).map_err(|err| (EXIT_NOT_FOUND, err))?;
+    save(file, &mut doc)
+}
+
+fn delete(file: &str, path: &str) -> CliResult {
+    let mut doc = load(file)?;
+    doc.delete(&parse_key_path(path)).map_err(|err| (EXIT_NOT_FOUND, err))?;
+    save(file, &mut doc)
+}
+
+fn validate(file: &str, schema_file: Option<&str>) -> CliResult {
+    let doc = load(file)?;
+    let Some(schema_file) = schema_file else {
+        return Ok(());
+    };
+    let schema_content = read(schema_file)?;
+    let schema_type = match file_type_for(schema_file, &schema_content) {
+        FileType::Unknown => FileType::Json,
+        file_type => file_type,
+    };
+    let schema = parse_as(schema_file, &schema_content, schema_type)
+        .map_err(|(code, message)| (if code == EXIT_INVALID { EXIT_USAGE } else { code }, message))?
+        .to_json();
+    let errors = schema::validate(&doc.to_json(), &schema);
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let mut stdout = std::io::stdout().lock();
+    for error in &errors {
+        let _ = writeln!(stdout, "{error}");
+    }
+    Err((EXIT_INVALID, format!("{file}: {} schema violation(s)", errors.len())))
+}
+
+fn fmt(file: &str, check: bool) -> CliResult {
+    let content = read(file)?;
+    let mut doc = parse_as(file, &content, file_type_for(file, &content))?;
+    let formatted = doc.serialize().map_err(|err| (EXIT_INVALID, format!("{file}: {err}")))?;
+    if formatted == content {
+        return Ok(());
+    }
+    if check {
+        return Err((EXIT_INVALID, format!("{file} is not formatted")));
+    }
+    write(file, &formatted)
+}
+
+fn convert(file: &str, output: &str, strict: bool) -> CliResult {
+    let target = detect_file_type(output);
+    if target == FileType::Unknown {
+        return Err((EXIT_USAGE, unknown_type(output)));
+    }
+    let mut doc = load(file)?;
+    let (text, issues) = convert::convert_to_text(&mut doc, target).map_err(|err| (EXIT_INVALID, format!("{file}: {err}")))?;
+    for issue in &issues {
+        eprintln!("warning: {issue}");
+    }
+    if strict && !issues.is_empty() {
+        return Err((EXIT_INVALID, format!("{file}: {} value(s) cannot be written as {}", issues.len(), target.label())));
+    }
+    write(output, &text)
+}
@@ -0,0 +1,358 @@
+// Moves a document between YAML, JSON and TOML, listing everything the target cannot hold.
+
+use serde_json::Value as JsonValue;
+use toml::Value as TomlValue;
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::document::{FileType, ParsedDocument};
+use crate::ini_document::{IniDialect, IniDocument};
+use crate::render_base_types::{self, Scalar};
+use crate::yaml_document::{self, YamlDocument, YamlMeta};
+use crate::{render_json, render_toml, render_yaml};
+
+/// Format-neutral document tree; map keys are always strings, as in JSON and TOML.
+enum Node {
+    Scalar(Scalar),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>),
+}
+
+/// A converted document together with "path: message" lines for every value that was
+/// changed or dropped on the way.
+pub(crate) struct Conversion {
+    pub(crate) document: ParsedDocument,
+    pub(crate) issues: Vec<String>,
+}
+
+/// Collects conversion issues, addressed like schema errors (`$` is the document root).
+#[derive(Default)]
+struct Issues(Vec<String>);
+
+impl Issues {
+    fn note(&mut self, path: &[String], message: impl AsRef<str>) {
+        let mut location = "$".to_string();
+        for segment in path {
+            location.push('.');
+            location.push_str(segment);
+        }
+        self.0.push(format!("{location}: {}", message.as_ref()));
+    }
+}
+
+/// Converts `doc` to `target`, which must be a different format than the document's own.
+/// Fails only when the target cannot hold the document at all (e.g. TOML with a list at the top).
+pub(crate) fn convert(doc: &ParsedDocument, target: FileType) -> Result<Conversion, String> {
+    let mut issues = Issues::default();
+    let node = read_document(doc, &mut issues);
+    let document = match target {
+        FileType::Yaml => ParsedDocument::Yaml(Box::new(YamlDocument {
+            root: node_to_yaml(node, &mut vec![], &mut issues),
+            meta: YamlMeta::default(),
+        })),
+        FileType::Json => ParsedDocument::Json(node_to_json(node, &mut vec![], &mut issues)),
+        FileType::Toml => match node {
+            Node::Map(_) => ParsedDocument::Toml(node_to_toml(node, &mut vec![], &mut issues).unwrap_or(TomlValue::Table(Default::default()))),
+            _ => return Err("a TOML document must be a table at the top level".to_string()),
+        },
+        FileType::Json5 | FileType::Jsonc => {
+            let value = node_to_json(node, &mut vec![], &mut issues);
+            let text = serde_json::to_string_pretty(&value).map_err(|err| err.to_string())? + "\n";
+            ParsedDocument::parse(&text, target)?
+        }
+        FileType::Ini | FileType::Env => match node {
+            Node::Map(entries) => {
+                let dialect = if target == FileType::Ini { IniDialect::Ini } else { IniDialect::Env };
+                ParsedDocument::Ini(node_to_ini(entries, dialect, &mut issues))
+            }
+            _ => return Err(format!("an {} file must be a mapping at the top level", target.label())),
+        },
+        FileType::Unknown => return Err("unknown target format".to_string()),
+    };
+    Ok(Conversion { document, issues: issues.0 })
+}
+
+/// The document as a JSON value, e.g. for schema validation. Aliases and merge keys are
+/// resolved, non-string keys become their text and datetimes become strings.
+pub(crate) fn to_json(doc: &ParsedDocument) -> JsonValue {
+    let mut issues = Issues::default();
+    let node = read_document(doc, &mut issues);
+    node_to_json(node, &mut vec![], &mut issues)
+}
+
+/// `doc` written as `target`, with the conversion issues; no issues when the format is unchanged.
+pub(crate) fn convert_to_text(doc: &mut ParsedDocument, target: FileType) -> Result<(String, Vec<String>), String> {
+    if doc.file_type() == target {
+        return doc.serialize().map(|text| (text, vec![]));
+    }
+    let mut conversion = convert(doc, target)?;
+    let text = conversion.document.serialize()?;
+    Ok((text, conversion.issues))
+}
+
+/// `path` with its extension replaced by the preferred one for `target`.
+pub(crate) fn path_with_extension(path: &str, target: FileType) -> String {
+    match target.extensions().first() {
+        Some(extension) => std::path::Path::new(path).with_extension(extension).to_string_lossy().into_owned(),
+        None => path.to_string(),
+    }
+}
+
+fn read_document(doc: &ParsedDocument, issues: &mut Issues) -> Node {
+    match doc {
+        ParsedDocument::Yaml(doc) => read_yaml(&doc.root, &doc.meta, &mut vec![], issues),
+        ParsedDocument::Json(value) => read_json(value, &mut vec![], issues),
+        ParsedDocument::Json5(doc) => read_json(&doc.root, &mut vec![], issues),
+        ParsedDocument::Toml(value) => read_toml(value),
+        ParsedDocument::Ini(doc) => read_ini(doc),
+    }
+}
+
+fn read_yaml(value: &Yaml, meta: &YamlMeta, path: &mut Vec<String>, issues: &mut Issues) -> Node {
+    if let Some(tag) = meta.tags.get(path.as_slice()) {
+        issues.note(path, format!("tag {tag} dropped"));
+    }
+    match value {
+        Yaml::Alias(id) => {
+            issues.note(path, format!("alias *{} expanded into a copy", meta.anchor_name(*id)));
+            match meta.anchored_values.get(id) {
+                Some(anchored) => read_yaml(anchored, meta, path, issues),
+                None => Node::Scalar(Scalar::Null),
+            }
+        }
+        Yaml::Array(items) => Node::List(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    path.push(i.to_string());
+                    let node = read_yaml(item, meta, path, issues);
+                    path.pop();
+                    node
+                })
+                .collect(),
+        ),
+        Yaml::Hash(map) => {
+            let mut entries: Vec<(String, Node)> = vec![];
+            let mut add = |key: &Yaml, value: &Yaml, path: &mut Vec<String>, issues: &mut Issues| {
+                let name = yaml_document::key_segment(key);
+                if entries.iter().any(|(existing, _)| *existing == name) {
+                    return false;
+                }
+                path.push(name.clone());
+                if !matches!(key, Yaml::String(_)) {
+                    issues.note(path, format!("{} key written as the string \"{name}\"", render_yaml::yaml_node_type(key).map_or("complex", |t| t.label())));
+                }
+                let node = read_yaml(value, meta, path, issues);
+                path.pop();
+                entries.push((name, node));
+                true
+            };
+            for (k, v) in map {
+                if yaml_document::is_merge_key(k) {
+                    continue;
+                }
+                if !add(k, v, path, issues) {
+                    issues.note(path, format!("duplicate key \"{}\" dropped", yaml_document::key_segment(k)));
+                }
+            }
+            if let Some(merge) = map.get(&Yaml::String(yaml_document::MERGE_KEY.to_string())) {
+                let mut merged = false;
+                for (_, source) in yaml_document::merge_sources(merge, meta) {
+                    for (k, v) in &source {
+                        merged |= add(k, v, path, issues);
+                    }
+                }
+                if merged {
+                    issues.note(path, "merge key expanded into the inherited fields");
+                }
+            }
+            Node::Map(entries)
+        }
+        Yaml::BadValue => Node::Scalar(Scalar::Null),
+        scalar => Node::Scalar(render_yaml::yaml_to_scalar(scalar).unwrap_or(Scalar::Null)),
+    }
+}
+
+fn read_json(value: &JsonValue, path: &mut Vec<String>, issues: &mut Issues) -> Node {
+    match value {
+        JsonValue::Array(items) => Node::List(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    path.push(i.to_string());
+                    let node = read_json(item, path, issues);
+                    path.pop();
+                    node
+                })
+                .collect(),
+        ),
+        JsonValue::Object(map) => Node::Map(
+            map.iter()
+                .map(|(k, v)| {
+                    path.push(k.clone());
+                    let node = read_json(v, path, issues);
+                    path.pop();
+                    (k.clone(), node)
+                })
+                .collect(),
+        ),
+        JsonValue::Number(n) if n.is_u64() && n.as_i64().is_none() => {
+            issues.note(path, format!("{n} is too large for a 64-bit integer, written as a float"));
+            Node::Scalar(Scalar::Float(render_base_types::format_float(n.as_f64().unwrap_or_default())))
+        }
+        scalar => Node::Scalar(render_json::json_to_scalar(scalar).unwrap_or(Scalar::Null)),
+    }
+}
+
+fn read_toml(value: &TomlValue) -> Node {
+    match value {
+        TomlValue::Array(items) => Node::List(items.iter().map(read_toml).collect()),
+        TomlValue::Table(table) => Node::Map(table.iter().map(|(k, v)| (k.clone(), read_toml(v))).collect()),
+        scalar => Node::Scalar(render_toml::toml_to_scalar(scalar).unwrap_or(Scalar::Null)),
+    }
+}
+
+fn read_ini(doc: &IniDocument) -> Node {
+    let mut entries = vec![];
+    for section in doc.sections() {
+        let values: Vec<(String, Node)> = section
+            .entries
+            .iter()
+            .filter_map(|&i| doc.entry(i))
+            .map(|entry| (entry.key.clone(), Node::Scalar(Scalar::String(entry.value.clone()))))
+            .collect();
+        match section.name {
+            Some(name) => entries.push((name, Node::Map(values))),
+            None => entries.extend(values),
+        }
+    }
+    Node::Map(entries)
+}
+
+/// Runs `f` on each child of a list or map with the child's path segment pushed.
+fn children<T>(entries: Vec<(String, Node)>, path: &mut Vec<String>, mut f: impl FnMut(Node, &mut Vec<String>) -> T) -> Vec<(String, T)> {
+    entries
+        .into_iter()
+        .map(|(key, node)| {
+            path.push(key.clone());
+            let value = f(node, path);
+            path.pop();
+            (key, value)
+        })
+        .collect()
+}
+
+fn indexed(items: Vec<Node>) -> Vec<(String, Node)> {
+    items.into_iter().enumerate().map(|(i, item)| (i.to_string(), item)).collect()
+}
+
+fn node_to_yaml(node: Node, path: &mut Vec<String>, issues: &mut Issues) -> Yaml {
+    match node {
+        Node::Scalar(Scalar::Float(text)) => match render_base_types::parse_float_input(&text) {
+            Some(f) => Yaml::Real(render_base_types::format_float_like(&text, f)),
+            None => Yaml::String(text),
+        },
+        Node::Scalar(Scalar::Datetime(text)) => {
+            issues.note(path, "datetime written as a string");
+            Yaml::String(text)
+        }
+        Node::Scalar(scalar) => render_yaml::yaml_from_scalar(scalar),
+        Node::List(items) => Yaml::Array(children(indexed(items), path, |n, p| node_to_yaml(n, p, issues)).into_iter().map(|(_, v)| v).collect()),
+        Node::Map(entries) => {
+            let mut map = Hash::new();
+            for (key, value) in children(entries, path, |n, p| node_to_yaml(n, p, issues)) {
+                map.insert(Yaml::String(key), value);
+            }
+            Yaml::Hash(map)
+        }
+    }
+}
+
+fn node_to_json(node: Node, path: &mut Vec<String>, issues: &mut Issues) -> JsonValue {
+    match node {
+        Node::Scalar(Scalar::Datetime(text)) => {
+            issues.note(path, "datetime written as a string");
+            JsonValue::String(text)
+        }
+        Node::Scalar(scalar) => render_json::json_from_scalar(scalar).unwrap_or_else(|err| {
+            issues.note(path, format!("{err}, written as null"));
+            JsonValue::Null
+        }),
+        Node::List(items) => JsonValue::Array(children(indexed(items), path, |n, p| node_to_json(n, p, issues)).into_iter().map(|(_, v)| v).collect()),
+        Node::Map(entries) => JsonValue::Object(children(entries, path, |n, p| node_to_json(n, p, issues)).into_iter().collect()),
+    }
+}
+
+/// `None` for values TOML cannot hold; the caller drops them from their table or array.
+fn node_to_toml(node: Node, path: &mut Vec<String>, issues: &mut Issues) -> Option<TomlValue> {
+    match node {
+        Node::Scalar(Scalar::Null) => {
+            issues.note(path, "TOML has no null value, entry dropped");
+            None
+        }
+        Node::Scalar(Scalar::Float(text)) => match render_base_types::parse_float_input(&text) {
+            Some(f) => Some(TomlValue::Float(f)),
+            None => Some(TomlValue::String(text)),
+        },
+        Node::Scalar(scalar) => match render_toml::toml_from_scalar(scalar.clone()) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                issues.note(path, format!("{err}, written as a string"));
+                Some(TomlValue::String(match scalar {
+                    Scalar::String(s) | Scalar::Datetime(s) => s,
+                    _ => String::new(),
+                }))
+            }
+        },
+        Node::List(items) => Some(TomlValue::Array(
+            children(indexed(items), path, |n, p| node_to_toml(n, p, issues)).into_iter().filter_map(|(_, v)| v).collect(),
+        )),
+        Node::Map(entries) => Some(TomlValue::Table(
+            children(entries, path, |n, p| node_to_toml(n, p, issues))
+                .into_iter()
+                .filter_map(|(k, v)| Some((k, v?)))
+                .collect(),
+        )),
+    }
+}
+
+/// INI holds scalars at the top and in one level of sections; .env only at the top.
+/// Anything deeper is written as its JSON text.
+fn node_to_ini(entries: Vec<(String, Node)>, dialect: IniDialect, issues: &mut Issues) -> IniDocument {
+    let mut doc = IniDocument::new(dialect);
+    let mut sections = vec![];
+    for (key, node) in entries {
+        match node {
+            Node::Map(values) if dialect == IniDialect::Ini => sections.push((key, values)),
+            node => {
+                let value = ini_value(node, std::slice::from_ref(&key), issues);
+                doc.add_entry(None, &key, &value);
+            }
+        }
+    }
+    for (name, values) in sections {
+        for (key, node) in values {
+            let value = ini_value(node, &[name.clone(), key.clone()], issues);
+            doc.add_entry(Some(&name), &key, &value);
+        }
+    }
+    doc
+}
+
+fn ini_value(node: Node, path: &[String], issues: &mut Issues) -> String {
+    match node {
+        Node::Scalar(Scalar::Null) => {
+            issues.note(path, "null written as an empty value");
+            String::new()
+        }
+        Node::Scalar(Scalar::Boolean(b)) => b.to_string(),
+        Node::Scalar(Scalar::Integer(i)) => i.to_string(),
+        Node::Scalar(Scalar::Float(s) | Scalar::String(s) | Scalar::Datetime(s)) => s,
+        node => {
+            issues.note(path, "nested value written as JSON text");
+            node_to_json(node, &mut path.to_vec(), &mut Issues::default()).to_string()
+        }
+    }
+}
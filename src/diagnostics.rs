@@ -0,0 +1,90 @@
+// Failed reads, writes and conversions, collected from anywhere in the process (including
+// the file watcher and the config files) and shown in the status bar and the diagnostics
+// window, so that no failure passes silently.
+
+use std::sync::Mutex;
+
+use eframe::egui;
+
+use crate::change_log::clock_time;
+
+/// Oldest entries are dropped beyond this.
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone)]
+pub(crate) struct Diagnostic {
+    /// Local time it was reported, `HH:MM:SS`.
+    pub(crate) time: String,
+    pub(crate) severity: Severity,
+    pub(crate) message: String,
+}
+
+impl Diagnostic {
+    fn color(&self) -> egui::Color32 {
+        match self.severity {
+            Severity::Warning => egui::Color32::YELLOW,
+            Severity::Error => egui::Color32::RED,
+        }
+    }
+
+    fn text(&self) -> String {
+        format!("{}  ⚠️ {}", self.time, self.message)
+    }
+}
+
+static LOG: Mutex<Vec<Diagnostic>> = Mutex::new(Vec::new());
+
+fn report(severity: Severity, message: String) {
+    let mut log = LOG.lock().unwrap();
+    log.push(Diagnostic { time: clock_time(), severity, message });
+    if log.len() > MAX_ENTRIES {
+        let excess = log.len() - MAX_ENTRIES;
+        log.drain(..excess);
+    }
+}
+
+pub(crate) fn error(message: impl Into<String>) {
+    report(Severity::Error, message.into());
+}
+
+pub(crate) fn warning(message: impl Into<String>) {
+    report(Severity::Warning, message.into());
+}
+
+pub(crate) fn latest() -> Option<Diagnostic> {
+    LOG.lock().unwrap().last().cloned()
+}
+
+pub(crate) fn count() -> usize {
+    LOG.lock().unwrap().len()
+}
+
+/// Draws the latest diagnostic on one line.
+pub(crate) fn render_latest(ui: &mut egui::Ui) {
+    if let Some(latest) = latest() {
+        ui.colored_label(latest.color(), latest.text()).on_hover_text("Open the diagnostics log for earlier ones");
+    }
+}
+
+/// Draws the whole log, newest first.
+pub(crate) fn render_log(ui: &mut egui::Ui) {
+    let mut log = LOG.lock().unwrap();
+    ui.horizontal(|ui| {
+        ui.label(format!("{} problem(s) since the window opened", log.len()));
+        if ui.button("Clear").clicked() {
+            log.clear();
+        }
+    });
+    ui.separator();
+    egui::ScrollArea::vertical().max_height(400.0).auto_shrink([false, true]).show(ui, |ui| {
+        for diagnostic in log.iter().rev() {
+            ui.colored_label(diagnostic.color(), egui::RichText::new(diagnostic.text()).monospace());
+        }
+    });
+}
@@ -0,0 +1,654 @@
+// Format-independent access to a parsed settings file, shared by the GUI and the command line.
+
+use std::path::Path;
+
+use serde_json::Value as JsonValue;
+use toml::Value as TomlValue;
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+use crate::convert;
+use crate::ini_document::{self, IniDialect, IniDocument};
+use crate::json5_document::{self, Json5Document, JsonDialect};
+use crate::yaml_document::{self, YamlDocument, YamlMeta};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum FileType {
+    Yaml,
+    Json,
+    Toml,
+    Json5,
+    Jsonc,
+    Ini,
+    Env,
+    Unknown,
+}
+
+impl FileType {
+    /// Every format the editor can open.
+    pub(crate) const ALL: [FileType; 7] =
+        [FileType::Yaml, FileType::Json, FileType::Toml, FileType::Json5, FileType::Jsonc, FileType::Ini, FileType::Env];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            FileType::Yaml => "YAML",
+            FileType::Json => "JSON",
+            FileType::Toml => "TOML",
+            FileType::Json5 => "JSON5",
+            FileType::Jsonc => "JSONC",
+            FileType::Ini => "INI",
+            FileType::Env => ".env",
+            FileType::Unknown => "",
+        }
+    }
+
+    /// File extensions for this format, the preferred one first.
+    pub(crate) fn extensions(self) -> &'static [&'static str] {
+        match self {
+            FileType::Yaml => &["yaml", "yml"],
+            FileType::Json => &["json"],
+            FileType::Toml => &["toml"],
+            FileType::Json5 => &["json5"],
+            FileType::Jsonc => &["jsonc"],
+            FileType::Ini => &["ini", "cfg"],
+            FileType::Env => &["env"],
+            FileType::Unknown => &[],
+        }
+    }
+}
+
+/// Every extension the editor opens, for file dialogs and messages.
+pub(crate) fn supported_extensions() -> Vec<&'static str> {
+    FileType::ALL.iter().flat_map(|t| t.extensions().iter().copied()).collect()
+}
+
+pub(crate) fn detect_file_type(path: &str) -> FileType {
+    let path = Path::new(path);
+    // `.env`, `.env.local`, `.env.production`, ...
+    let file_name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+    if file_name == ".env" || file_name.starts_with(".env.") {
+        return FileType::Env;
+    }
+    let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase();
+    FileType::ALL
+        .into_iter()
+        .find(|t| t.extensions().contains(&extension.as_str()))
+        .unwrap_or(FileType::Unknown)
+}
+
+/// The format of `path`: from its name when that is conclusive, otherwise from `content`.
+pub(crate) fn file_type_for(path: &str, content: &str) -> FileType {
+    match detect_file_type(path) {
+        FileType::Unknown => sniff_file_type(content),
+        file_type => file_type,
+    }
+}
+
+/// Guesses the format of `content` by trying the parsers from the strictest to the most lenient.
+/// YAML reads almost any text as a plain string, so it only counts when it finds a mapping or list.
+pub(crate) fn sniff_file_type(content: &str) -> FileType {
+    let trimmed = content.trim_start_matches('\u{feff}').trim();
+    if trimmed.is_empty() {
+        return FileType::Unknown;
+    }
+    if serde_json::from_str::<JsonValue>(trimmed).is_ok() {
+        return FileType::Json;
+    }
+    let first_code = trimmed.lines().map(str::trim).find(|line| !line.starts_with("//") && !line.starts_with("/*") && !line.is_empty());
+    if first_code.is_some_and(|line| line.starts_with('{') || line.starts_with('[')) {
+        if json5_document::load_json5(trimmed, JsonDialect::Jsonc).is_ok() {
+            return FileType::Jsonc;
+        }
+        if json5_document::load_json5(trimmed, JsonDialect::Json5).is_ok() {
+            return FileType::Json5;
+        }
+    }
+    if trimmed.parse::<TomlValue>().is_ok() {
+        return FileType::Toml;
+    }
+    // `[section]` also reads as a YAML flow list, so INI goes first when it has sections.
+    let ini_sections = ini_document::load_ini(trimmed, IniDialect::Ini).map(|doc| doc.sections());
+    if let Ok(sections) = &ini_sections {
+        if sections.len() > 1 && sections.iter().any(|s| !s.entries.is_empty()) {
+            return FileType::Ini;
+        }
+    }
+    if matches!(yaml_document::load_yaml(trimmed), Ok(Some(doc)) if matches!(doc.root, Yaml::Hash(_) | Yaml::Array(_))) {
+        return FileType::Yaml;
+    }
+    if ini_document::load_ini(trimmed, IniDialect::Env).is_ok() {
+        return FileType::Env;
+    }
+    match ini_sections {
+        Ok(sections) if sections.iter().any(|s| !s.entries.is_empty()) => FileType::Ini,
+        _ => FileType::Unknown,
+    }
+}
+
+pub(crate) enum ParsedDocument {
+    Yaml(Box<YamlDocument>),
+    Json(JsonValue),
+    Toml(TomlValue),
+    /// JSON5 and JSONC, edited in place so comments survive.
+    Json5(Box<Json5Document>),
+    /// INI and .env files.
+    Ini(IniDocument),
+}
+
+impl ParsedDocument {
+    /// Parses `content` as `file_type`. An empty YAML file is a document holding `null`.
+    pub(crate) fn parse(content: &str, file_type: FileType) -> Result<Self, String> {
+        match file_type {
+            FileType::Yaml => match yaml_document::load_yaml(content) {
+                Ok(Some(doc)) => Ok(ParsedDocument::Yaml(Box::new(doc))),
+                Ok(None) => Ok(ParsedDocument::Yaml(Box::new(YamlDocument { root: Yaml::Null, meta: YamlMeta::default() }))),
+                Err(err) => Err(err.to_string()),
+            },
+            FileType::Json => serde_json::from_str(content).map(ParsedDocument::Json).map_err(|err| err.to_string()),
+            FileType::Toml => content.parse::<TomlValue>().map(ParsedDocument::Toml).map_err(|err| err.to_string()),
+            FileType::Json5 => json5_document::load_json5(content, JsonDialect::Json5).map(|doc| ParsedDocument::Json5(Box::new(doc))),
+            FileType::Jsonc => json5_document::load_json5(content, JsonDialect::Jsonc).map(|doc| ParsedDocument::Json5(Box::new(doc))),
+            FileType::Ini => ini_document::load_ini(content, IniDialect::Ini).map(ParsedDocument::Ini),
+            FileType::Env => ini_document::load_ini(content, IniDialect::Env).map(ParsedDocument::Ini),
+            FileType::Unknown => Err("Unknown file type.".to_string()),
+        }
+    }
+
+    pub(crate) fn file_type(&self) -> FileType {
+        match self {
+            ParsedDocument::Yaml(_) => FileType::Yaml,
+            ParsedDocument::Json(_) => FileType::Json,
+            ParsedDocument::Toml(_) => FileType::Toml,
+            ParsedDocument::Json5(doc) => match doc.dialect {
+                JsonDialect::Json5 => FileType::Json5,
+                JsonDialect::Jsonc => FileType::Jsonc,
+            },
+            ParsedDocument::Ini(doc) => match doc.dialect {
+                IniDialect::Ini => FileType::Ini,
+                IniDialect::Env => FileType::Env,
+            },
+        }
+    }
+
+    /// Serializes the document the way the editor writes it to disk.
+    pub(crate) fn serialize(&mut self) -> Result<String, String> {
+        match self {
+            ParsedDocument::Yaml(doc) => {
+                doc.refresh_anchored_values();
+                Ok(yaml_document::emit_yaml(doc))
+            }
+            ParsedDocument::Json(value) => serde_json::to_string_pretty(value).map_err(|err| err.to_string()),
+            ParsedDocument::Toml(value) => toml::to_string_pretty(value).map_err(|err| err.to_string()),
+            ParsedDocument::Json5(doc) => Ok(json5_document::emit_json5(doc)),
+            ParsedDocument::Ini(doc) => Ok(ini_document::emit_ini(doc)),
+        }
+    }
+
+    /// The value at `path`: scalars as plain text, collections in the document's own format.
+    pub(crate) fn get(&self, path: &[String]) -> Option<String> {
+        match self {
+            // Keys inherited through `<<` and entries behind aliases are found like a YAML reader would.
+            ParsedDocument::Yaml(doc) => {
+                let resolved = yaml_document::resolve(&doc.root, &doc.meta);
+                yaml_document::lookup(&resolved, path).map(|value| match value {
+                    Yaml::String(s) => s.clone(),
+                    other => yaml_document::emit_value_at(other, path, &doc.meta),
+                })
+            }
+            ParsedDocument::Json(value) => json_get(value, path),
+            ParsedDocument::Json5(doc) => json_get(&doc.root, path),
+            ParsedDocument::Toml(value) => toml_lookup(value, path).map(|value| match value {
+                TomlValue::String(s) => s.clone(),
+                TomlValue::Table(table) => toml::to_string_pretty(table).unwrap_or_default(),
+                other => other.to_string(),
+            }),
+            ParsedDocument::Ini(doc) if path.is_empty() => Some(ini_document::emit_ini(doc)),
+            ParsedDocument::Ini(doc) => {
+                let (section, key) = ini_path(doc, path).ok()?;
+                match doc.find(section, key) {
+                    Some(i) => doc.entry(i).map(|entry| entry.value.clone()),
+                    // A bare section name lists its entries.
+                    None if section.is_none() => {
+                        let sections = doc.sections();
+                        let found = sections.iter().find(|s| s.name.as_deref() == Some(key))?;
+                        Some(
+                            found
+                                .entries
+                                .iter()
+                                .filter_map(|&i| doc.entry(i))
+                                .map(|entry| format!("{} = {}", entry.key, entry.value))
+                                .collect::<Vec<_>>()
+                                .join("\n"),
+                        )
+                    }
+                    None => None,
+                }
+            }
+        }
+    }
+
+    /// Sets the value at `path`, creating missing parent mappings. `text` is read the way it
+    /// would be written in the document's format and falls back to a plain string.
+    pub(crate) fn set(&mut self, path: &[String], text: &str) -> Result<(), String> {
+        let Some((last, parents)) = path.split_last() else {
+            return Err("cannot replace the whole document".to_string());
+        };
+        match self {
+            ParsedDocument::Yaml(doc) => {
+                let value = match yaml_document::load_yaml(text) {
+                    Ok(Some(parsed)) if !matches!(parsed.root, Yaml::Alias(_) | Yaml::BadValue) => parsed.root,
+                    _ => Yaml::String(text.to_string()),
+                };
+                yaml_set(&mut doc.root, parents, last, value)?;
+                // The anchors, tags and styles of the replaced value are gone with it.
+                doc.meta.forget_path(path);
+                Ok(())
+            }
+            ParsedDocument::Json(root) => {
+                let value = serde_json::from_str(text).unwrap_or_else(|_| JsonValue::String(text.to_string()));
+                json_set(root, parents, last, value)
+            }
+            ParsedDocument::Json5(doc) => {
+                let value = json5_document::parse_json5_value(text).unwrap_or_else(|_| JsonValue::String(text.to_string()));
+                json_set(&mut doc.root, parents, last, value)
+            }
+            ParsedDocument::Ini(doc) => {
+                let (section, key) = ini_path(doc, path)?;
+                match doc.find(section, key) {
+                    Some(i) => doc.entry_mut(i).unwrap().value = text.to_string(),
+                    None => doc.add_entry(section, key, text),
+                }
+                Ok(())
+            }
+            ParsedDocument::Toml(root) => toml_set(root, parents, last, toml_value_from_text(text)),
+        }
+    }
+
+    /// Gives the value at `path` the value it has in `saved`, another parse of the same file in
+    /// the same format, or removes it when `saved` has nothing there. Values are copied as they
+    /// are, so a string that reads like a number stays a string.
+    pub(crate) fn revert_from(&mut self, saved: &ParsedDocument, path: &[String]) -> Result<(), String> {
+        let Some((last, parents)) = path.split_last() else {
+            return Err("cannot revert the whole document".to_string());
+        };
+        let reverted = match (&mut *self, saved) {
+            (ParsedDocument::Yaml(doc), ParsedDocument::Yaml(saved)) => {
+                yaml_document::lookup(&saved.root, path).cloned().map(|value| {
+                    yaml_set(&mut doc.root, parents, last, value)?;
+                    doc.meta.forget_path(path);
+                    doc.meta.copy_path(&saved.meta, path);
+                    Ok(())
+                })
+            }
+            (ParsedDocument::Json(root), ParsedDocument::Json(saved)) => {
+                json_lookup(saved, path).cloned().map(|value| json_set(root, parents, last, value))
+            }
+            (ParsedDocument::Json5(doc), ParsedDocument::Json5(saved)) => {
+                json_lookup(&saved.root, path).cloned().map(|value| json_set(&mut doc.root, parents, last, value))
+            }
+            (ParsedDocument::Toml(root), ParsedDocument::Toml(saved)) => {
+                toml_lookup(saved, path).cloned().map(|value| toml_set(root, parents, last, value))
+            }
+            (ParsedDocument::Ini(doc), ParsedDocument::Ini(saved)) => {
+                let (section, key) = ini_path(saved, path)?;
+                saved.find(section, key).and_then(|i| saved.entry(i)).map(|entry| {
+                    match doc.find(section, key) {
+                        Some(i) => doc.entry_mut(i).unwrap().value = entry.value.clone(),
+                        None => doc.add_entry(section, key, &entry.value),
+                    }
+                    Ok(())
+                })
+            }
+            _ => return Err("the saved version is in another format".to_string()),
+        };
+        reverted.unwrap_or_else(|| self.delete(path))
+    }
+
+    /// Removes the entry at `path`; `Err` when there is nothing there.
+    pub(crate) fn delete(&mut self, path: &[String]) -> Result<(), String> {
+        let Some((last, parents)) = path.split_last() else {
+            return Err("cannot delete the whole document".to_string());
+        };
+        let not_found = || format!("{} not found", path.join("."));
+        let removed = match self {
+            // Anchors, tags and styles are recorded by path, so they follow the entries that move.
+            ParsedDocument::Yaml(doc) => match yaml_lookup_mut(&mut doc.root, parents) {
+                Some(Yaml::Hash(map)) => {
+                    let key = map.keys().find(|k| yaml_document::key_segment(k) == *last).cloned();
+                    let removed = key.and_then(|key| map.remove(&key)).is_some();
+                    doc.meta.forget_path(path);
+                    removed
+                }
+                Some(Yaml::Array(items)) => match list_index(items.len(), last) {
+                    Ok(i) => {
+                        items.remove(i);
+                        doc.meta.remove_list_item(parents, i);
+                        true
+                    }
+                    Err(_) => false,
+                },
+                _ => false,
+            },
+            ParsedDocument::Json(root) => json_delete(root, parents, last),
+            ParsedDocument::Json5(doc) => json_delete(&mut doc.root, parents, last),
+            ParsedDocument::Ini(doc) => match ini_path(doc, path).ok().and_then(|(section, key)| doc.find(section, key)) {
+                Some(i) => {
+                    doc.remove_entry(i);
+                    true
+                }
+                None => false,
+            },
+            ParsedDocument::Toml(root) => match toml_lookup_mut(root, parents) {
+                Some(TomlValue::Table(table)) => table.remove(last).is_some(),
+                Some(TomlValue::Array(items)) => remove_list_item(items, last),
+                _ => false,
+            },
+        };
+        if removed { Ok(()) } else { Err(not_found()) }
+    }
+
+    /// The document as a JSON value, e.g. for schema validation. YAML aliases and merge keys
+    /// are resolved and non-string keys become their text; TOML datetimes become strings.
+    pub(crate) fn to_json(&self) -> JsonValue {
+        convert::to_json(self)
+    }
+}
+
+/// Splits a dotted key path such as `global.v_setpoints.0`; `\.` keeps a literal dot in a key.
+pub(crate) fn parse_key_path(text: &str) -> Vec<String> {
+    if text.is_empty() || text == "." {
+        return vec![];
+    }
+    let mut segments = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => segments.last_mut().unwrap().extend(chars.next()),
+            '.' => segments.push(String::new()),
+            c => segments.last_mut().unwrap().push(c),
+        }
+    }
+    segments
+}
+
+fn list_index(len: usize, segment: &str) -> Result<usize, String> {
+    match segment.parse::<usize>() {
+        Ok(i) if i < len => Ok(i),
+        _ => Err(format!("{segment} is not an index of a list with {len} items")),
+    }
+}
+
+fn list_item_mut<'a, T>(items: &'a mut [T], segment: &str) -> Result<&'a mut T, String> {
+    let i = list_index(items.len(), segment)?;
+    Ok(&mut items[i])
+}
+
+/// Replaces a list item, or appends when `segment` is the list length (or `+`).
+fn set_list_item<T>(items: &mut Vec<T>, segment: &str, value: T) -> Result<(), String> {
+    if segment == "+" || segment.parse::<usize>() == Ok(items.len()) {
+        items.push(value);
+    } else {
+        *list_item_mut(items, segment)? = value;
+    }
+    Ok(())
+}
+
+fn remove_list_item<T>(items: &mut Vec<T>, segment: &str) -> bool {
+    match list_index(items.len(), segment) {
+        Ok(i) => {
+            items.remove(i);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn yaml_child_or_insert<'a>(node: &'a mut Yaml, segment: &str) -> Result<&'a mut Yaml, String> {
+    if let Yaml::Null = node {
+        *node = Yaml::Hash(Hash::new());
+    }
+    match node {
+        Yaml::Hash(map) => {
+            let key = map
+                .keys()
+                .find(|k| yaml_document::key_segment(k) == segment)
+                .cloned()
+                .unwrap_or_else(|| Yaml::String(segment.to_string()));
+            Ok(map.entry(key).or_insert_with(|| Yaml::Hash(Hash::new())))
+        }
+        Yaml::Array(items) => list_item_mut(items, segment),
+        _ => Err(format!("{segment} is inside a scalar")),
+    }
+}
+
+fn yaml_set(root: &mut Yaml, parents: &[String], last: &str, value: Yaml) -> Result<(), String> {
+    let mut node = root;
+    for segment in parents {
+        node = yaml_child_or_insert(node, segment)?;
+    }
+    match node {
+        Yaml::Hash(map) => {
+            match map.iter_mut().find(|(k, _)| yaml_document::key_segment(k) == last) {
+                Some((_, existing)) => *existing = value,
+                None => {
+                    map.insert(Yaml::String(last.to_string()), value);
+                }
+            }
+            Ok(())
+        }
+        Yaml::Array(items) => set_list_item(items, last, value),
+        Yaml::Null => {
+            let mut map = Hash::new();
+            map.insert(Yaml::String(last.to_string()), value);
+            *node = Yaml::Hash(map);
+            Ok(())
+        }
+        _ => Err(format!("{} is not a mapping or list", parents.join("."))),
+    }
+}
+
+fn yaml_lookup_mut<'a>(root: &'a mut Yaml, path: &[String]) -> Option<&'a mut Yaml> {
+    path.iter().try_fold(root, |node, segment| match node {
+        Yaml::Hash(map) => map.iter_mut().find(|(k, _)| yaml_document::key_segment(k) == *segment).map(|(_, v)| v),
+        Yaml::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+        _ => None,
+    })
+}
+
+/// Splits `path` into an optional section and a key; .env files have no sections.
+fn ini_path<'a>(doc: &IniDocument, path: &'a [String]) -> Result<(Option<&'a str>, &'a str), String> {
+    match (path, doc.dialect) {
+        ([key], _) => Ok((None, key)),
+        ([section, key], IniDialect::Ini) => Ok((Some(section), key)),
+        (_, IniDialect::Ini) => Err("INI paths are `key` or `section.key`".to_string()),
+        (_, IniDialect::Env) => Err(".env paths are a single key".to_string()),
+    }
+}
+
+fn json_get(root: &JsonValue, path: &[String]) -> Option<String> {
+    json_lookup(root, path).map(|value| match value {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Object(_) | JsonValue::Array(_) => serde_json::to_string_pretty(value).unwrap_or_default(),
+        other => other.to_string(),
+    })
+}
+
+fn json_set(root: &mut JsonValue, parents: &[String], last: &str, value: JsonValue) -> Result<(), String> {
+    let mut node = root;
+    for segment in parents {
+        node = match node {
+            JsonValue::Object(map) => map.entry(segment.clone()).or_insert_with(|| JsonValue::Object(Default::default())),
+            JsonValue::Array(items) => list_item_mut(items, segment)?,
+            _ => return Err(format!("{segment} is inside a scalar")),
+        };
+    }
+    match node {
+        JsonValue::Object(map) => {
+            map.insert(last.to_string(), value);
+            Ok(())
+        }
+        JsonValue::Array(items) => set_list_item(items, last, value),
+        _ => Err(format!("{} is not an object or array", parents.join("."))),
+    }
+}
+
+fn json_delete(root: &mut JsonValue, parents: &[String], last: &str) -> bool {
+    match json_lookup_mut(root, parents) {
+        Some(JsonValue::Object(map)) => map.remove(last).is_some(),
+        Some(JsonValue::Array(items)) => remove_list_item(items, last),
+        _ => false,
+    }
+}
+
+fn json_lookup<'a>(root: &'a JsonValue, path: &[String]) -> Option<&'a JsonValue> {
+    path.iter().try_fold(root, |node, segment| match node {
+        JsonValue::Object(map) => map.get(segment),
+        JsonValue::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+fn json_lookup_mut<'a>(root: &'a mut JsonValue, path: &[String]) -> Option<&'a mut JsonValue> {
+    path.iter().try_fold(root, |node, segment| match node {
+        JsonValue::Object(map) => map.get_mut(segment),
+        JsonValue::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+        _ => None,
+    })
+}
+
+fn toml_lookup<'a>(root: &'a TomlValue, path: &[String]) -> Option<&'a TomlValue> {
+    path.iter().try_fold(root, |node, segment| match node {
+        TomlValue::Table(table) => table.get(segment),
+        TomlValue::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+fn toml_set(root: &mut TomlValue, parents: &[String], last: &str, value: TomlValue) -> Result<(), String> {
+    let mut node = root;
+    for segment in parents {
+        node = match node {
+            TomlValue::Table(table) => table.entry(segment.clone()).or_insert_with(|| TomlValue::Table(Default::default())),
+            TomlValue::Array(items) => list_item_mut(items, segment)?,
+            _ => return Err(format!("{segment} is inside a scalar")),
+        };
+    }
+    match node {
+        TomlValue::Table(table) => {
+            table.insert(last.to_string(), value);
+            Ok(())
+        }
+        TomlValue::Array(items) => set_list_item(items, last, value),
+        _ => Err(format!("{} is not a table or array", parents.join("."))),
+    }
+}
+
+fn toml_lookup_mut<'a>(root: &'a mut TomlValue, path: &[String]) -> Option<&'a mut TomlValue> {
+    path.iter().try_fold(root, |node, segment| match node {
+        TomlValue::Table(table) => table.get_mut(segment),
+        TomlValue::Array(items) => segment.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+        _ => None,
+    })
+}
+
+/// Reads `text` as a TOML value (`1`, `"a"`, `[1, 2]`, `{ x = 1 }`), or a plain string if it is not one.
+fn toml_value_from_text(text: &str) -> TomlValue {
+    format!("value = {text}")
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| TomlValue::String(text.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MERGED: &str = "\
+base: &base
+  ramp: 5
+  mode: slow
+dev:
+  <<: *base
+  mode: fast
+seq: &items
+- x: 1
+- x: 2
+copy: *items
+";
+
+    fn get(path: &str) -> Option<String> {
+        ParsedDocument::parse(MERGED, FileType::Yaml).unwrap().get(&parse_key_path(path))
+    }
+
+    #[test]
+    fn get_yaml_finds_keys_inherited_through_merge() {
+        assert_eq!(get("dev.ramp").as_deref(), Some("5"));
+        assert_eq!(get("dev.mode").as_deref(), Some("fast"));
+        assert_eq!(get("dev.missing"), None);
+    }
+
+    #[test]
+    fn get_yaml_follows_aliases() {
+        assert_eq!(get("copy.1.x").as_deref(), Some("2"));
+        assert_eq!(get("copy").as_deref(), Some("- x: 1\n- x: 2"));
+    }
+
+    #[test]
+    fn get_yaml_collection_shows_merged_entries() {
+        let dev = get("dev").unwrap();
+        assert!(!dev.contains("<<"), "{dev}");
+        assert_eq!(load_yaml_value(&dev), load_yaml_value("mode: fast\nramp: 5"));
+    }
+
+    #[test]
+    fn get_yaml_keeps_block_scalar_style() {
+        let doc = ParsedDocument::parse("notes:\n  text: |\n    one\n    two\n", FileType::Yaml).unwrap();
+        assert_eq!(doc.get(&parse_key_path("notes")).as_deref(), Some("text: |\n  one\n  two"));
+    }
+
+    const ANCHORED_LIST: &str = "list:\n  - a\n  - &x |\n    block\n    text\n  - *x\n";
+
+    fn edited_yaml(source: &str, edit: impl FnOnce(&mut ParsedDocument)) -> String {
+        let mut doc = ParsedDocument::parse(source, FileType::Yaml).unwrap();
+        edit(&mut doc);
+        doc.serialize().unwrap()
+    }
+
+    #[test]
+    fn delete_yaml_list_item_moves_anchors_and_styles() {
+        let written = edited_yaml(ANCHORED_LIST, |doc| doc.delete(&parse_key_path("list.0")).unwrap());
+        assert_eq!(written, "list:\n  - &x |\n    block\n    text\n  - *x\n");
+    }
+
+    #[test]
+    fn delete_anchored_yaml_value_writes_aliases_out() {
+        let written = edited_yaml(ANCHORED_LIST, |doc| doc.delete(&parse_key_path("list.1")).unwrap());
+        assert_eq!(load_yaml_value(&written), load_yaml_value("list: [a, \"block\\ntext\\n\"]"));
+        let written = edited_yaml("base: &b\n  x: 1\nuse: *b\n", |doc| doc.delete(&parse_key_path("base")).unwrap());
+        assert_eq!(written, "use:\n  x: 1\n");
+    }
+
+    #[test]
+    fn set_yaml_drops_the_replaced_value_style() {
+        let written = edited_yaml(ANCHORED_LIST, |doc| doc.set(&parse_key_path("list.1"), "plain").unwrap());
+        assert_eq!(load_yaml_value(&written), load_yaml_value("list: [a, plain, \"block\\ntext\\n\"]"));
+    }
+
+    #[test]
+    fn revert_yaml_restores_style_and_moves_anchors() {
+        let saved = ParsedDocument::parse(ANCHORED_LIST, FileType::Yaml).unwrap();
+        let written = edited_yaml(ANCHORED_LIST, |doc| {
+            doc.set(&parse_key_path("list.1"), "changed").unwrap();
+            doc.revert_from(&saved, &parse_key_path("list.1")).unwrap();
+        });
+        assert_eq!(written, ANCHORED_LIST);
+        let added = "list:\n  - new\n  - a\n  - &x |\n    block\n    text\n  - *x\n";
+        let written = edited_yaml(added, |doc| doc.revert_from(&saved, &parse_key_path("list.3")).unwrap());
+        assert_eq!(load_yaml_value(&written), load_yaml_value("list: [new, a, \"block\\ntext\\n\"]"));
+    }
+
+    fn load_yaml_value(text: &str) -> Yaml {
+        yaml_document::load_yaml(text).unwrap().unwrap().root
+    }
+}
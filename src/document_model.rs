@@ -0,0 +1,218 @@
+// The open document as versioned text plus the parsed tree of one of its versions. Parsing
+// runs on a background thread and only when the text or its format changed, so frames that
+// change nothing cost neither a copy of the text nor a parse.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+
+use serde_json::Value as JsonValue;
+
+use crate::change_log::{self, ValueChange};
+use crate::convert;
+use crate::diagnostics;
+use crate::document::{FileType, ParsedDocument};
+
+/// Text of the open file, shared with the file watcher. `version` goes up with every change.
+#[derive(Default)]
+pub(crate) struct VersionedText {
+    pub(crate) text: String,
+    pub(crate) version: u64,
+}
+
+impl VersionedText {
+    pub(crate) fn new(text: String) -> Self {
+        Self { text, version: 1 }
+    }
+
+    pub(crate) fn replace(&mut self, text: String) {
+        self.text = text;
+        self.version += 1;
+    }
+}
+
+/// The parse of one version of the text.
+pub(crate) struct ParseSnapshot {
+    pub(crate) version: u64,
+    pub(crate) file_type: FileType,
+    pub(crate) result: Result<ParsedDocument, String>,
+    /// A tree edited in place that stands in for `version` until that text is parsed.
+    pub(crate) provisional: bool,
+}
+
+impl ParseSnapshot {
+    pub(crate) fn parse(version: u64, text: &str, file_type: FileType) -> Self {
+        Self { version, file_type, result: ParsedDocument::parse(text, file_type), provisional: false }
+    }
+
+    /// Keeps this tree, just edited, as the snapshot of `version`, the text it was written as.
+    pub(crate) fn follow_edit(&mut self, version: u64) {
+        self.version = version;
+        self.provisional = true;
+    }
+
+    /// Whether this snapshot was parsed from `version` read as `file_type`.
+    pub(crate) fn is_current(&self, version: u64, file_type: FileType) -> bool {
+        self.version == version && self.file_type == file_type
+    }
+}
+
+/// Tree edits held back for review, to be written to the file in one go or dropped.
+pub(crate) struct StagedEdits {
+    /// The edited tree, shown in place of the file's own.
+    pub(crate) document: ParsedDocument,
+    pub(crate) file_type: FileType,
+    /// Version of the text the edits were made on.
+    pub(crate) base_version: u64,
+    /// The edits against the snapshot of version `diffed.0`; `None` after another edit.
+    diffed: Option<(u64, Vec<ValueChange>)>,
+}
+
+impl StagedEdits {
+    pub(crate) fn new(document: ParsedDocument, file_type: FileType, base_version: u64) -> Self {
+        Self { document, file_type, base_version, diffed: None }
+    }
+
+    /// Called after every further edit to the staged tree.
+    pub(crate) fn edited(&mut self) {
+        self.diffed = None;
+    }
+
+    /// What applying the edits would change in the document `base` was parsed from.
+    pub(crate) fn changes(&mut self, base: &ParseSnapshot) -> &[ValueChange] {
+        if self.diffed.as_ref().is_none_or(|(version, _)| *version != base.version) {
+            let old = base.result.as_ref().map_or(JsonValue::Null, convert::to_json);
+            let changes = change_log::diff(&old, &convert::to_json(&self.document));
+            self.diffed = Some((base.version, changes));
+        }
+        self.diffed.as_ref().map_or(&[], |(_, changes)| changes)
+    }
+}
+
+/// What the modified markers compare the document with: the file as it was opened or, when
+/// saving by hand, as it was last saved or reloaded.
+pub(crate) struct Baseline {
+    /// Version of the text this was taken from.
+    pub(crate) version: u64,
+    pub(crate) text: String,
+    /// `text` parsed in the format it was last compared in; `None` inside when it does not parse.
+    parsed: Option<(FileType, Option<ParsedDocument>)>,
+    /// Version and format of the snapshot `changes` were worked out against.
+    compared: Option<(u64, FileType)>,
+    changes: Vec<ValueChange>,
+}
+
+impl Baseline {
+    pub(crate) fn new(version: u64, text: String) -> Self {
+        Self { version, text, parsed: None, compared: None, changes: vec![] }
+    }
+
+    fn document(&mut self, file_type: FileType) -> Option<&ParsedDocument> {
+        if self.parsed.as_ref().is_none_or(|(parsed_type, _)| *parsed_type != file_type) {
+            self.parsed = Some((file_type, ParsedDocument::parse(&self.text, file_type).ok()));
+        }
+        self.parsed.as_ref().and_then(|(_, document)| document.as_ref())
+    }
+
+    /// Compares `current` with the baseline unless it already was. Returns whether `changes`
+    /// were worked out again.
+    pub(crate) fn compare(&mut self, current: &ParseSnapshot) -> bool {
+        let key = (current.version, current.file_type);
+        if self.compared == Some(key) {
+            return false;
+        }
+        self.compared = Some(key);
+        let old = self.document(current.file_type).map(convert::to_json);
+        self.changes = match (old, &current.result) {
+            (Some(old), Ok(new)) => change_log::diff(&old, &convert::to_json(new)),
+            // Nothing to compare; marking everything would tell nobody anything.
+            _ => vec![],
+        };
+        true
+    }
+
+    /// The values that differ, as of the last `compare`.
+    pub(crate) fn changes(&self) -> &[ValueChange] {
+        &self.changes
+    }
+
+    /// Gives the changed values at and below `path` in `document` their baseline values.
+    pub(crate) fn revert(&mut self, document: &mut ParsedDocument, path: &[String]) -> Result<(), String> {
+        let changed: Vec<(Vec<String>, bool)> = self
+            .changes
+            .iter()
+            .filter(|change| change.path.starts_with(path))
+            .map(|change| (change.path.clone(), change.old.is_some()))
+            .collect();
+        let Some(saved) = self.document(document.file_type()) else {
+            return Err("the saved version cannot be read".to_string());
+        };
+        // Removing added list items from the back first keeps the indices of the others valid.
+        for (path, _) in changed.iter().rev().filter(|(_, existed)| !existed) {
+            document.revert_from(saved, path)?;
+        }
+        for (path, _) in changed.iter().filter(|(_, existed)| *existed) {
+            document.revert_from(saved, path)?;
+        }
+        Ok(())
+    }
+}
+
+struct ParseRequest {
+    version: u64,
+    text: String,
+    file_type: FileType,
+}
+
+/// Parses text on a background thread; only the newest request waiting is parsed.
+pub(crate) struct ParseWorker {
+    requests: Sender<ParseRequest>,
+    finished: Arc<Mutex<Option<ParseSnapshot>>>,
+    /// Version and format of the last request, so that one change is only sent once.
+    requested: Option<(u64, FileType)>,
+}
+
+impl ParseWorker {
+    /// Starts the worker; `ctx` is repainted whenever a parse finishes.
+    pub(crate) fn new(ctx: &egui::Context) -> Self {
+        let (requests, incoming) = mpsc::channel();
+        let finished = Arc::new(Mutex::new(None));
+        let (output, ctx) = (finished.clone(), ctx.clone());
+        std::thread::spawn(move || parse_loop(incoming, output, ctx));
+        Self { requests, finished, requested: None }
+    }
+
+    /// Queues `text` for parsing unless this version and format were already requested.
+    pub(crate) fn request(&mut self, version: u64, text: &str, file_type: FileType) {
+        if self.requested == Some((version, file_type)) {
+            return;
+        }
+        self.requested = Some((version, file_type));
+        if self.requests.send(ParseRequest { version, text: text.to_string(), file_type }).is_err() {
+            diagnostics::error("the background parser stopped; the tree is no longer updated");
+        }
+    }
+
+    /// Marks a snapshot that was parsed on the UI thread as requested, so it is not parsed again.
+    pub(crate) fn mark_parsed(&mut self, version: u64, file_type: FileType) {
+        self.requested = Some((version, file_type));
+    }
+
+    /// The latest finished parse, if one arrived since the last call.
+    pub(crate) fn take_finished(&self) -> Option<ParseSnapshot> {
+        self.finished.lock().unwrap().take()
+    }
+}
+
+fn parse_loop(incoming: Receiver<ParseRequest>, output: Arc<Mutex<Option<ParseSnapshot>>>, ctx: egui::Context) {
+    while let Ok(mut request) = incoming.recv() {
+        // Skip versions that were superseded while the last one was being parsed.
+        while let Ok(newer) = incoming.try_recv() {
+            request = newer;
+        }
+        let snapshot = ParseSnapshot::parse(request.version, &request.text, request.file_type);
+        *output.lock().unwrap() = Some(snapshot);
+        ctx.request_repaint();
+    }
+}
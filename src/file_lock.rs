@@ -0,0 +1,286 @@
+// Advisory locking of the open file, so that this editor and other programs writing the same
+// file (e.g. experiment control software) never read or write it half-way through each
+// other's writes. The lock only coordinates with programs that take the same kind of lock:
+// `flock` on the file itself, or a `<file>.lock` file created next to it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::diagnostics;
+use crate::preferences::LockMode;
+use crate::session;
+
+/// How long the window waits for another program to let go; the UI is blocked meanwhile.
+pub(crate) const UI_WAIT: Duration = Duration::from_secs(1);
+/// How long the file watcher and the command-line subcommands wait, off the UI thread.
+pub(crate) const BACKGROUND_WAIT: Duration = Duration::from_secs(10);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+/// Lock files older than this whose process has exited were left behind by a program that crashed.
+const STALE_LOCK_FILE: Duration = Duration::from_secs(60);
+
+static MODE: AtomicU8 = AtomicU8::new(LockMode::Off as u8);
+
+/// Sets how every later read and write of this process is locked.
+pub(crate) fn set_mode(mode: LockMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+fn mode() -> LockMode {
+    match MODE.load(Ordering::Relaxed) {
+        m if m == LockMode::Flock as u8 => LockMode::Flock,
+        m if m == LockMode::LockFile as u8 => LockMode::LockFile,
+        _ => LockMode::Off,
+    }
+}
+
+pub(crate) fn is_enabled() -> bool {
+    mode() != LockMode::Off
+}
+
+/// Held while the file is read or written.
+enum Guard {
+    /// Nothing to release, or the lock goes away with the file handle.
+    Handle,
+    /// Removed again on drop.
+    LockFile(PathBuf),
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if let Guard::LockFile(lock_path) = self {
+            if let Err(err) = fs::remove_file(&*lock_path) {
+                diagnostics::error(format!("cannot remove {}: {err}", lock_path.display()));
+            }
+        }
+    }
+}
+
+/// Reads `path`, waiting up to `wait` for another program's write to finish.
+pub(crate) fn read(path: &str, wait: Duration) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let _guard = lock(&file, path, false, wait)?;
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub(crate) fn read_to_string(path: &str, wait: Duration) -> io::Result<String> {
+    String::from_utf8(read(path, wait)?).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+/// Replaces the contents of `path`, waiting up to `wait` for other programs to let go of it.
+pub(crate) fn write(path: &str, contents: &str, wait: Duration) -> io::Result<()> {
+    if !is_enabled() {
+        return fs::write(path, contents);
+    }
+    // Truncating only once the lock is held keeps readers from seeing an emptied file.
+    let mut file = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+    let _guard = lock(&file, path, true, wait)?;
+    file.set_len(0)?;
+    file.write_all(contents.as_bytes())
+}
+
+/// Whether a read or write gave up because another program kept the file locked.
+pub(crate) fn is_locked(err: &io::Error) -> bool {
+    err.kind() == ErrorKind::WouldBlock
+}
+
+/// Whether another program holds the lock on `path` right now.
+pub(crate) fn held_elsewhere(path: &str) -> bool {
+    match mode() {
+        LockMode::Off => false,
+        #[cfg(unix)]
+        LockMode::Flock => File::open(path).is_ok_and(|file| try_flock(&file, true).is_ok_and(|acquired| !acquired)),
+        // A stale lock file is taken over by the next write, so it holds nothing up.
+        _ => {
+            let lock_path = lock_file_path(path);
+            lock_path.exists() && !is_stale(&lock_path)
+        }
+    }
+}
+
+/// Takes the lock, retrying until `wait` has passed.
+fn lock(file: &File, path: &str, exclusive: bool, wait: Duration) -> io::Result<Guard> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let guard = match mode() {
+            LockMode::Off => Some(Guard::Handle),
+            #[cfg(unix)]
+            LockMode::Flock => try_flock(file, exclusive)?.then_some(Guard::Handle),
+            // Without `flock`, lock files are the only convention left.
+            _ => try_lock_file(path, exclusive)?,
+        };
+        if let Some(guard) = guard {
+            return Ok(guard);
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(ErrorKind::WouldBlock, "locked by another program"));
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+/// Returns false while another process holds a conflicting lock.
+#[cfg(unix)]
+fn try_flock(file: &File, exclusive: bool) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let operation = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+fn lock_file_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{path}.lock"))
+}
+
+/// Returns `None` while another program's lock file exists.
+fn try_lock_file(path: &str, exclusive: bool) -> io::Result<Option<Guard>> {
+    let lock_path = lock_file_path(path);
+    match OpenOptions::new().write(true).create_new(true).open(&lock_path) {
+        Ok(mut file) => {
+            // Tells whoever finds the lock file who made it.
+            let _ = writeln!(file, "{}", std::process::id());
+            Ok(Some(Guard::LockFile(lock_path)))
+        }
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            if is_stale(&lock_path) {
+                diagnostics::warning(format!("removing {}, which was left behind", lock_path.display()));
+                let _ = fs::remove_file(&lock_path);
+            }
+            Ok(None)
+        }
+        // Files in a folder we cannot write to can still be read, as long as nobody is writing.
+        Err(err) if !exclusive && err.kind() == ErrorKind::PermissionDenied => {
+            Ok((!lock_path.exists()).then_some(Guard::Handle))
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn is_stale(lock_path: &Path) -> bool {
+    let old = fs::metadata(lock_path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.elapsed().is_ok_and(|age| age > STALE_LOCK_FILE));
+    // A slow save keeps its lock longer than a minute; only age decides for lock files without our pid.
+    old && !fs::read_to_string(lock_path)
+        .ok()
+        .and_then(|text| text.trim().parse::<u32>().ok())
+        .is_some_and(session::is_running)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::SystemTime;
+
+    /// The lock mode is process wide, so tests that set it take turns.
+    static MODE_LOCK: Mutex<()> = Mutex::new(());
+
+    fn temp_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("yaml_viewer_lock_{}_{name}", std::process::id()));
+        fs::write(&path, "old").unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn leave_lock_file(path: &str, pid: u32) -> PathBuf {
+        let lock_path = lock_file_path(path);
+        fs::write(&lock_path, format!("{pid}\n")).unwrap();
+        let long_ago = SystemTime::now() - STALE_LOCK_FILE * 2;
+        File::options().write(true).open(&lock_path).unwrap().set_modified(long_ago).unwrap();
+        lock_path
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stale_lock_file_is_not_held_elsewhere() {
+        let _turn = MODE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        set_mode(LockMode::LockFile);
+        let path = temp_file("held");
+        let lock_path = lock_file_path(&path);
+        fs::write(&lock_path, format!("{}\n", std::process::id())).unwrap();
+        assert!(held_elsewhere(&path));
+        leave_lock_file(&path, i32::MAX as u32);
+        assert!(!held_elsewhere(&path));
+        set_mode(LockMode::Off);
+        fs::remove_file(lock_path).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn old_lock_file_of_running_process_is_kept() {
+        let path = temp_file("running");
+        let lock_path = leave_lock_file(&path, std::process::id());
+        assert!(!is_stale(&lock_path));
+        fs::remove_file(lock_path).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn old_lock_file_of_exited_process_is_stale() {
+        let path = temp_file("exited");
+        // Above any pid_max, so no process has it.
+        let lock_path = leave_lock_file(&path, i32::MAX as u32);
+        assert!(is_stale(&lock_path));
+        fs::remove_file(lock_path).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Writes `path` while `hold` keeps it locked for a while, and returns how long the write waited.
+    fn write_while_held(mode: LockMode, path: &str, hold: impl FnOnce() -> Box<dyn FnOnce() + Send>) -> Duration {
+        let _turn = MODE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        set_mode(mode);
+        let release = hold();
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            release();
+        });
+        let start = Instant::now();
+        write(path, "new", Duration::from_secs(5)).unwrap();
+        let waited = start.elapsed();
+        releaser.join().unwrap();
+        set_mode(LockMode::Off);
+        waited
+    }
+
+    #[test]
+    fn lock_file_held_elsewhere_delays_write() {
+        let path = temp_file("lock_file");
+        let lock_path = lock_file_path(&path);
+        let waited = write_while_held(LockMode::LockFile, &path, || {
+            fs::write(&lock_path, "1\n").unwrap();
+            Box::new(move || fs::remove_file(lock_path).unwrap())
+        });
+        assert!(waited >= Duration::from_millis(300), "wrote after {waited:?}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flock_held_elsewhere_delays_write() {
+        let path = temp_file("flock");
+        let waited = write_while_held(LockMode::Flock, &path, || {
+            // `flock` locks belong to the open file, so a second handle conflicts even within this process.
+            let other = File::open(&path).unwrap();
+            assert!(try_flock(&other, true).unwrap());
+            Box::new(move || drop(other))
+        });
+        assert!(waited >= Duration::from_millis(300), "wrote after {waited:?}");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+// Reloads the open file when it changes on disk. The parent directory is watched rather than
+// the file itself: editors such as vim save by writing a new file and renaming it over the
+// old one, which ends a watch on the file but shows up as ordinary events in its directory.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use eframe::egui;
+use notify::event::{AccessKind, AccessMode};
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use crate::diagnostics;
+use crate::file_lock;
+use crate::document_model::VersionedText;
+
+/// Bursts of events closer together than this are handled once, after the last one.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Watches `path` until the returned watcher is dropped, e.g. when another file is opened.
+pub(crate) fn init_file_watcher(tx: mpsc::Sender<Event>, path: &str) -> Option<RecommendedWatcher> {
+    // Untitled buffers have nothing to watch.
+    if path.is_empty() {
+        return None;
+    }
+    let path = PathBuf::from(path);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf();
+    let name = path.file_name()?.to_os_string();
+    let watcher = RecommendedWatcher::new(
+        move |res: Result<Event, _>| {
+            let Ok(event) = res else {
+                return;
+            };
+            // Reading the file, including our own reload, must not count as a change.
+            let read_only = matches!(event.kind, EventKind::Access(kind) if kind != AccessKind::Close(AccessMode::Write));
+            if !read_only && event.paths.iter().any(|p| p.file_name() == Some(name.as_os_str())) {
+                // A full channel already has a reload pending.
+                let _ = tx.try_send(event);
+            }
+        },
+        Config::default(),
+    )
+    .and_then(|mut watcher| watcher.watch(&dir, RecursiveMode::NonRecursive).map(|()| watcher));
+    watcher
+        .map_err(|err| diagnostics::warning(format!("changes to {} made elsewhere will not be shown: {err}", path.display())))
+        .ok()
+}
+
+/// Reloads `content` from `file_path` after each burst of events and sets `missing` while
+/// the file is gone, i.e. deleted or moved away. `ctx` is repainted after every check.
+pub(crate) fn spawn_file_watcher(
+    mut rx: mpsc::Receiver<Event>,
+    file_path: Arc<Mutex<String>>,
+    content: Arc<Mutex<VersionedText>>,
+    missing: Arc<AtomicBool>,
+    ctx: egui::Context,
+) {
+    tokio::spawn(async move {
+        while rx.recv().await.is_some() {
+            // A save through a temporary file removes and recreates the file in quick
+            // succession; wait for it to settle so the file is read once, complete.
+            tokio::time::sleep(DEBOUNCE).await;
+            while rx.try_recv().is_ok() {}
+
+            let path = file_path.lock().unwrap().clone();
+            if path.is_empty() {
+                continue;
+            }
+            // Waiting for the writer's lock blocks this thread; let the runtime move other tasks off it.
+            match tokio::task::block_in_place(|| file_lock::read_to_string(&path, file_lock::BACKGROUND_WAIT)) {
+                Ok(new_content) => {
+                    missing.store(false, Ordering::Relaxed);
+                    let mut lock = content.lock().unwrap();
+                    if lock.text != new_content {
+                        lock.replace(new_content);
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    if !missing.swap(true, Ordering::Relaxed) {
+                        diagnostics::warning(format!("{path} was deleted or moved"));
+                    }
+                }
+                Err(err) => diagnostics::warning(format!("cannot reload {path}: {err}")),
+            }
+            ctx.request_repaint();
+        }
+    });
+}
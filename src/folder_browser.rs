@@ -0,0 +1,198 @@
+// Side panel listing the settings files under a project folder. The folder is watched
+// recursively so that files created, removed or renamed elsewhere show up straight away.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use eframe::egui;
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::diagnostics;
+use crate::document::{detect_file_type, FileType};
+use crate::folder_search::FolderSearch;
+
+/// Stops a scan of a huge tree (e.g. a home directory picked by mistake) from freezing the UI.
+const MAX_LISTED_FILES: usize = 5000;
+
+#[derive(Default)]
+struct DirNode {
+    dirs: BTreeMap<String, DirNode>,
+    /// File names in this directory, sorted.
+    files: Vec<String>,
+}
+
+pub(crate) struct FolderBrowser {
+    pub(crate) root: PathBuf,
+    tree: DirNode,
+    file_count: usize,
+    truncated: bool,
+    filter: String,
+    searching: bool,
+    search: FolderSearch,
+    /// Set by the watcher when files were added, removed or renamed.
+    stale: Arc<AtomicBool>,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl FolderBrowser {
+    /// Lists the settings files under `root` and watches it for changes; `ctx` is repainted
+    /// when the listing has to be refreshed.
+    pub(crate) fn open(root: PathBuf, ctx: &egui::Context) -> Self {
+        let stale = Arc::new(AtomicBool::new(true));
+        let watcher = {
+            let stale = stale.clone();
+            let ctx = ctx.clone();
+            RecommendedWatcher::new(
+                move |res: Result<Event, notify::Error>| {
+                    // Edits inside a file do not change the listing.
+                    if res.is_ok_and(|event| !matches!(event.kind, EventKind::Access(_) | EventKind::Modify(notify::event::ModifyKind::Data(_)))) {
+                        stale.store(true, Ordering::Relaxed);
+                        ctx.request_repaint();
+                    }
+                },
+                Config::default(),
+            )
+            .and_then(|mut watcher| watcher.watch(&root, RecursiveMode::Recursive).map(|()| watcher))
+            .map_err(|err| diagnostics::warning(format!("files added under {} will not be listed: {err}", root.display())))
+            .ok()
+        };
+        Self {
+            root,
+            tree: DirNode::default(),
+            file_count: 0,
+            truncated: false,
+            filter: String::new(),
+            searching: false,
+            search: FolderSearch::default(),
+            stale,
+            _watcher: watcher,
+        }
+    }
+
+    fn rescan(&mut self) {
+        self.tree = DirNode::default();
+        self.file_count = 0;
+        self.truncated = false;
+        let root = self.root.clone();
+        self.scan_dir(&root, &mut vec![]);
+    }
+
+    fn scan_dir(&mut self, dir: &Path, components: &mut Vec<String>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+            if kind.is_dir() {
+                // Skips `.git` and other tool directories.
+                if !name.starts_with('.') {
+                    components.push(name);
+                    self.scan_dir(&entry.path(), components);
+                    components.pop();
+                }
+            } else if detect_file_type(&name) != FileType::Unknown {
+                if self.file_count == MAX_LISTED_FILES {
+                    self.truncated = true;
+                    return;
+                }
+                self.file_count += 1;
+                let node = components.iter().fold(&mut self.tree, |node, dir| node.dirs.entry(dir.clone()).or_default());
+                node.files.push(name);
+            }
+        }
+    }
+
+    /// Draws the listing or the folder search. Returns the file that was clicked, with the
+    /// key path of the clicked search hit (empty for a file in the listing).
+    pub(crate) fn show(&mut self, ui: &mut egui::Ui, current_path: &str) -> Option<(String, Vec<String>)> {
+        if self.stale.swap(false, Ordering::Relaxed) {
+            self.rescan();
+        }
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.searching, false, "📄 Files");
+            ui.selectable_value(&mut self.searching, true, "🔎 Search in folder");
+        });
+        if self.searching {
+            let (tree, root) = (&self.tree, &self.root);
+            return self.search.show(ui, root, || {
+                let mut files = vec![];
+                collect_matches(tree, root, "", &mut files);
+                files
+            });
+        }
+        self.show_listing(ui, current_path).map(|path| (path, vec![]))
+    }
+
+    fn show_listing(&mut self, ui: &mut egui::Ui, current_path: &str) -> Option<String> {
+        ui.horizontal(|ui| {
+            ui.label("🔍");
+            ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("filter files"));
+        });
+        if self.truncated {
+            ui.colored_label(egui::Color32::YELLOW, format!("⚠️ Only the first {MAX_LISTED_FILES} files are listed"));
+        } else if self.file_count == 0 {
+            ui.label("No settings files in this folder.");
+        }
+        ui.separator();
+
+        let mut clicked = None;
+        let current = Path::new(current_path);
+        egui::ScrollArea::vertical().id_salt("folder_browser_scroll").auto_shrink([false; 2]).show(ui, |ui| {
+            let filter = self.filter.trim().to_lowercase();
+            if filter.is_empty() {
+                render_dir(ui, &self.tree, &self.root, current, &mut clicked);
+            } else {
+                // A flat list of matching relative paths is easier to scan than a filtered tree.
+                let mut matches = vec![];
+                collect_matches(&self.tree, &self.root, &filter, &mut matches);
+                for path in matches {
+                    let relative = path.strip_prefix(&self.root).unwrap_or(&path).display().to_string();
+                    file_button(ui, relative, &path, current, &mut clicked);
+                }
+            }
+        });
+        clicked
+    }
+}
+
+fn render_dir(ui: &mut egui::Ui, node: &DirNode, dir: &Path, current: &Path, clicked: &mut Option<String>) {
+    for (name, child) in &node.dirs {
+        let path = dir.join(name);
+        egui::CollapsingHeader::new(format!("📁 {name}"))
+            .id_salt(("folder_browser_dir", &path))
+            .default_open(current.starts_with(&path))
+            .show(ui, |ui| render_dir(ui, child, &path, current, clicked));
+    }
+    for name in &node.files {
+        file_button(ui, name.clone(), &dir.join(name), current, clicked);
+    }
+}
+
+fn collect_matches(node: &DirNode, dir: &Path, filter: &str, matches: &mut Vec<PathBuf>) {
+    for (name, child) in &node.dirs {
+        collect_matches(child, &dir.join(name), filter, matches);
+    }
+    for name in &node.files {
+        let path = dir.join(name);
+        if path.to_string_lossy().to_lowercase().contains(filter) {
+            matches.push(path);
+        }
+    }
+}
+
+fn file_button(ui: &mut egui::Ui, label: String, path: &Path, current: &Path, clicked: &mut Option<String>) {
+    let selected = path == current;
+    if ui.selectable_label(selected, format!("📄 {label}")).clicked() && !selected {
+        *clicked = path.to_str().map(str::to_string);
+    }
+}
@@ -0,0 +1,256 @@
+// "Search in folder": looks for a key path or value pattern in every settings file under the
+// folder browser's root. Files are parsed on a background thread and hits are listed per file
+// as they come in.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use eframe::egui;
+use serde_json::Value as JsonValue;
+
+use crate::convert;
+use crate::document::{file_type_for, ParsedDocument};
+
+const MAX_HITS_PER_FILE: usize = 200;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchMode {
+    /// Dot-separated key paths, e.g. `gates.*.duration`.
+    KeyPath,
+    /// Scalar values.
+    Value,
+}
+
+struct SearchHit {
+    path: Vec<String>,
+    preview: String,
+}
+
+struct FileHits {
+    file: PathBuf,
+    hits: Vec<SearchHit>,
+}
+
+#[derive(Default)]
+struct SearchResults {
+    files: Vec<FileHits>,
+    /// Files that could not be read or parsed.
+    skipped: usize,
+    scanned: usize,
+    total: usize,
+    done: bool,
+}
+
+/// Case-insensitive text pattern: a substring, or a whole-text glob when it contains `*` or `?`.
+struct Pattern {
+    text: String,
+    glob: bool,
+}
+
+impl Pattern {
+    fn new(query: &str) -> Self {
+        Self { text: query.to_lowercase(), glob: query.contains(['*', '?']) }
+    }
+
+    fn matches(&self, candidate: &str) -> bool {
+        let candidate = candidate.to_lowercase();
+        if self.glob {
+            let pattern: Vec<char> = self.text.chars().collect();
+            glob_match(&pattern, &candidate.chars().collect::<Vec<_>>())
+        } else {
+            candidate.contains(&self.text)
+        }
+    }
+}
+
+/// Matches `text` against a pattern where `*` is any run of characters and `?` any one character.
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and retry.
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+pub(crate) struct FolderSearch {
+    query: String,
+    mode: SearchMode,
+    results: Arc<Mutex<SearchResults>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Default for FolderSearch {
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+            mode: SearchMode::KeyPath,
+            results: Arc::new(Mutex::new(SearchResults { done: true, ..Default::default() })),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl FolderSearch {
+    /// Starts searching `files`, abandoning any search that is still running.
+    fn start(&mut self, files: Vec<PathBuf>, ctx: &egui::Context) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.cancelled = Arc::new(AtomicBool::new(false));
+        self.results = Arc::new(Mutex::new(SearchResults { total: files.len(), ..Default::default() }));
+
+        let (results, cancelled, ctx) = (self.results.clone(), self.cancelled.clone(), ctx.clone());
+        let (pattern, mode) = (Pattern::new(self.query.trim()), self.mode);
+        std::thread::spawn(move || {
+            for file in files {
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let hits = search_file(&file, &pattern, mode);
+                let mut results = results.lock().unwrap();
+                results.scanned += 1;
+                match hits {
+                    Some(hits) if !hits.is_empty() => results.files.push(FileHits { file, hits }),
+                    Some(_) => {}
+                    None => results.skipped += 1,
+                }
+                drop(results);
+                ctx.request_repaint();
+            }
+            results.lock().unwrap().done = true;
+            ctx.request_repaint();
+        });
+    }
+
+    /// Draws the search form and the hits; returns the file and key path of a clicked hit.
+    pub(crate) fn show(&mut self, ui: &mut egui::Ui, root: &Path, files: impl FnOnce() -> Vec<PathBuf>) -> Option<(String, Vec<String>)> {
+        let mut submit = false;
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.mode, SearchMode::KeyPath, "Key path");
+            ui.selectable_value(&mut self.mode, SearchMode::Value, "Value");
+        });
+        ui.horizontal(|ui| {
+            let hint = match self.mode {
+                SearchMode::KeyPath => "e.g. gates.*.duration",
+                SearchMode::Value => "e.g. 1e-9 or *qubit*",
+            };
+            let response = ui.add(egui::TextEdit::singleline(&mut self.query).hint_text(hint));
+            submit = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            submit |= ui.button("🔍").on_hover_text("Search every file in the folder").clicked();
+        });
+        if submit && !self.query.trim().is_empty() {
+            self.start(files(), ui.ctx());
+        }
+
+        let results = self.results.lock().unwrap();
+        let hit_count: usize = results.files.iter().map(|file| file.hits.len()).sum();
+        if !results.done {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label(format!("{} / {} files", results.scanned, results.total));
+            });
+        } else if results.total > 0 {
+            ui.label(format!("{hit_count} hit(s) in {} file(s)", results.files.len()));
+        }
+        if results.skipped > 0 {
+            ui.colored_label(egui::Color32::YELLOW, format!("⚠️ {} file(s) could not be parsed", results.skipped))
+                .on_hover_text("Open them to see the parse error");
+        }
+        ui.separator();
+
+        let mut clicked = None;
+        egui::ScrollArea::vertical().id_salt("folder_search_scroll").auto_shrink([false; 2]).show(ui, |ui| {
+            for file in &results.files {
+                let relative = file.file.strip_prefix(root).unwrap_or(&file.file).display().to_string();
+                egui::CollapsingHeader::new(format!("📄 {relative} ({})", file.hits.len()))
+                    .id_salt(("folder_search_file", &file.file))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for hit in &file.hits {
+                            let text = egui::RichText::new(format!("{} = {}", hit.path.join("."), hit.preview)).monospace();
+                            if ui.selectable_label(false, text).clicked() {
+                                clicked = file.file.to_str().map(|path| (path.to_string(), hit.path.clone()));
+                            }
+                        }
+                        if file.hits.len() == MAX_HITS_PER_FILE {
+                            ui.label(format!("Only the first {MAX_HITS_PER_FILE} hits are shown"));
+                        }
+                    });
+            }
+        });
+        clicked
+    }
+}
+
+/// The hits in one file, or `None` when it cannot be read or parsed.
+fn search_file(file: &Path, pattern: &Pattern, mode: SearchMode) -> Option<Vec<SearchHit>> {
+    let path = file.to_str()?;
+    let content = std::fs::read_to_string(file).ok()?;
+    let doc = ParsedDocument::parse(&content, file_type_for(path, &content)).ok()?;
+    let mut hits = vec![];
+    search_value(&convert::to_json(&doc), &mut vec![], pattern, mode, &mut hits);
+    Some(hits)
+}
+
+fn search_value(value: &JsonValue, path: &mut Vec<String>, pattern: &Pattern, mode: SearchMode, hits: &mut Vec<SearchHit>) {
+    let children: Vec<(String, &JsonValue)> = match value {
+        JsonValue::Object(map) => map.iter().map(|(key, child)| (key.clone(), child)).collect(),
+        JsonValue::Array(items) => items.iter().enumerate().map(|(i, child)| (i.to_string(), child)).collect(),
+        _ => {
+            if mode == SearchMode::Value && !path.is_empty() && pattern.matches(&scalar_text(value)) {
+                hits.push(SearchHit { path: path.clone(), preview: preview(value) });
+            }
+            return;
+        }
+    };
+    for (key, child) in children {
+        if hits.len() >= MAX_HITS_PER_FILE {
+            return;
+        }
+        path.push(key);
+        if mode == SearchMode::KeyPath && pattern.matches(&path.join(".")) {
+            hits.push(SearchHit { path: path.clone(), preview: preview(child) });
+        }
+        search_value(child, path, pattern, mode, hits);
+        path.pop();
+    }
+}
+
+fn scalar_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+pub(crate) fn preview(value: &JsonValue) -> String {
+    const MAX_PREVIEW_CHARS: usize = 60;
+    let text = match value {
+        JsonValue::Object(map) => format!("{{{} entries}}", map.len()),
+        JsonValue::Array(items) => format!("[{} items]", items.len()),
+        other => other.to_string(),
+    };
+    if text.chars().count() > MAX_PREVIEW_CHARS {
+        text.chars().take(MAX_PREVIEW_CHARS).collect::<String>() + "…"
+    } else {
+        text
+    }
+}
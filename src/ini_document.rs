@@ -0,0 +1,375 @@
+// INI and .env files as a list of lines. Comments, blank lines and untouched entries are
+// written back exactly as they were read; only edited entries are reformatted.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum IniDialect {
+    /// `[section]` headers, `key = value` or `key: value`, `;` and `#` comments.
+    Ini,
+    /// `KEY=value` with optional `export`, quoted values and trailing `# comments`.
+    Env,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct IniEntry {
+    pub(crate) key: String,
+    pub(crate) value: String,
+}
+
+/// How an entry was written, reused when it is rewritten after an edit.
+#[derive(Clone, Debug, Default)]
+struct EntryLayout {
+    indent: String,
+    export: bool,
+    separator: String,
+    quote: Option<char>,
+    comment: String,
+}
+
+enum Line {
+    /// Blank lines and comments.
+    Text(String),
+    Section { text: String, name: String },
+    Entry { text: String, entry: IniEntry, original: IniEntry, layout: EntryLayout },
+}
+
+pub(crate) struct IniDocument {
+    pub(crate) dialect: IniDialect,
+    lines: Vec<Line>,
+}
+
+/// The entries of one section, by line index; `name` is `None` for entries before the first header.
+pub(crate) struct IniSection {
+    pub(crate) name: Option<String>,
+    pub(crate) entries: Vec<usize>,
+}
+
+pub(crate) fn load_ini(source: &str, dialect: IniDialect) -> Result<IniDocument, String> {
+    let mut lines = vec![];
+    let mut raw_lines = source.split_inclusive('\n').enumerate();
+    while let Some((number, raw)) = raw_lines.next() {
+        let line = raw.trim_end_matches(['\n', '\r']);
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || (dialect == IniDialect::Ini && trimmed.starts_with(';')) {
+            lines.push(Line::Text(raw.to_string()));
+            continue;
+        }
+        if dialect == IniDialect::Ini && trimmed.starts_with('[') {
+            let name = trimmed
+                .strip_prefix('[')
+                .and_then(|rest| rest.split_once(']'))
+                .map(|(name, _)| name.trim().to_string())
+                .ok_or_else(|| format!("unterminated section header at line {}", number + 1))?;
+            lines.push(Line::Section { text: raw.to_string(), name });
+            continue;
+        }
+        let mut text = raw.to_string();
+        let (entry, layout) = match dialect {
+            IniDialect::Ini => parse_ini_entry(line),
+            IniDialect::Env => {
+                // A double-quoted value may continue over several lines.
+                let mut logical = line.to_string();
+                while open_double_quote(&logical) {
+                    let Some((_, next)) = raw_lines.next() else {
+                        return Err(format!("unterminated quoted value at line {}", number + 1));
+                    };
+                    text.push_str(next);
+                    logical = text.trim_end_matches(['\n', '\r']).to_string();
+                }
+                parse_env_entry(&logical)
+            }
+        }
+        .ok_or_else(|| format!("expected `key=value` at line {}", number + 1))?;
+        lines.push(Line::Entry { text, original: entry.clone(), entry, layout });
+    }
+    Ok(IniDocument { dialect, lines })
+}
+
+fn parse_ini_entry(line: &str) -> Option<(IniEntry, EntryLayout)> {
+    let split = line.find(['=', ':'])?;
+    let key = line[..split].trim();
+    if key.is_empty() {
+        return None;
+    }
+    let separator_start = line[..split].trim_end().len();
+    let value_start = split + 1 + (line[split + 1..].len() - line[split + 1..].trim_start().len());
+    // Whitespace after the separator counts, so `key = ; comment` has an empty value.
+    let spaced = value_start > split + 1;
+    let value_end = value_start + inline_comment_start(&line[value_start..], spaced).unwrap_or(line.len() - value_start);
+    let layout = EntryLayout {
+        indent: line[..line.len() - line.trim_start().len()].to_string(),
+        separator: line[separator_start..value_start].to_string(),
+        comment: line[line[..value_end].trim_end().len().max(value_start)..].trim_end().to_string(),
+        ..Default::default()
+    };
+    Some((IniEntry { key: key.to_string(), value: line[value_start..value_end].trim_end().to_string() }, layout))
+}
+
+/// Where a `; comment` or `# comment` after an INI value starts; `after_space` tells whether
+/// whitespace came right before `value`. Like Python's `configparser`, only a marker after
+/// whitespace counts, so `path=a;b` keeps its `;`; so does a quoted `"a ; b"`.
+fn inline_comment_start(value: &str, mut after_space: bool) -> Option<usize> {
+    let mut quoted = false;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' | '#' if after_space && !quoted => return Some(i),
+            _ => {}
+        }
+        after_space = c.is_whitespace();
+    }
+    None
+}
+
+/// Whether `line` ends inside a double-quoted .env value.
+fn open_double_quote(line: &str) -> bool {
+    let Some((_, value)) = line.split_once('=') else {
+        return false;
+    };
+    let value = value.trim_start();
+    let Some(body) = value.strip_prefix('"') else {
+        return false;
+    };
+    let mut escaped = false;
+    for c in body.chars() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return false,
+            _ => escaped = false,
+        }
+    }
+    true
+}
+
+fn parse_env_entry(line: &str) -> Option<(IniEntry, EntryLayout)> {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let mut rest = line.trim_start();
+    let export = rest.starts_with("export ");
+    if export {
+        rest = rest["export ".len()..].trim_start();
+    }
+    let (key, value) = rest.split_once('=')?;
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return None;
+    }
+    let value = value.trim_start();
+    let (value, quote, comment) = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let body = &value[1..];
+            let mut text = String::new();
+            let mut chars = body.char_indices();
+            let mut end = body.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    c if c == quote => {
+                        end = i + 1;
+                        break;
+                    }
+                    '\\' if quote == '"' => match chars.next().map(|(_, c)| c) {
+                        Some('n') => text.push('\n'),
+                        Some('r') => text.push('\r'),
+                        Some('t') => text.push('\t'),
+                        Some(other @ ('"' | '\\' | '$')) => text.push(other),
+                        Some(other) => {
+                            text.push('\\');
+                            text.push(other);
+                        }
+                        None => text.push('\\'),
+                    },
+                    c => text.push(c),
+                }
+            }
+            (text, Some(quote), body[end..].to_string())
+        }
+        _ => match value.find(" #") {
+            Some(i) => (value[..i].trim_end().to_string(), None, value[i..].to_string()),
+            None => (value.trim_end().to_string(), None, String::new()),
+        },
+    };
+    let layout = EntryLayout { indent: indent.to_string(), export, separator: "=".to_string(), quote, comment };
+    Some((IniEntry { key: key.to_string(), value }, layout))
+}
+
+impl IniDocument {
+    pub(crate) fn new(dialect: IniDialect) -> Self {
+        Self { dialect, lines: vec![] }
+    }
+
+    pub(crate) fn sections(&self) -> Vec<IniSection> {
+        let mut sections = vec![IniSection { name: None, entries: vec![] }];
+        for (i, line) in self.lines.iter().enumerate() {
+            match line {
+                Line::Section { name, .. } => sections.push(IniSection { name: Some(name.clone()), entries: vec![] }),
+                Line::Entry { .. } => sections.last_mut().unwrap().entries.push(i),
+                Line::Text(_) => {}
+            }
+        }
+        sections
+    }
+
+    pub(crate) fn entry(&self, index: usize) -> Option<&IniEntry> {
+        match self.lines.get(index) {
+            Some(Line::Entry { entry, .. }) => Some(entry),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn entry_mut(&mut self, index: usize) -> Option<&mut IniEntry> {
+        match self.lines.get_mut(index) {
+            Some(Line::Entry { entry, .. }) => Some(entry),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn remove_entry(&mut self, index: usize) {
+        if matches!(self.lines.get(index), Some(Line::Entry { .. })) {
+            self.lines.remove(index);
+        }
+    }
+
+    /// Adds an entry at the end of `section`, creating the section when it does not exist yet.
+    pub(crate) fn add_entry(&mut self, section: Option<&str>, key: &str, value: &str) {
+        let entry = IniEntry { key: key.to_string(), value: value.to_string() };
+        let layout = EntryLayout {
+            separator: if self.dialect == IniDialect::Ini { " = " } else { "=" }.to_string(),
+            ..Default::default()
+        };
+        let line = Line::Entry { text: String::new(), original: IniEntry { key: String::new(), value: String::new() }, entry, layout };
+        let sections = self.sections();
+        match sections.iter().position(|s| s.name.as_deref() == section) {
+            Some(position) => {
+                let at = match sections[position].entries.last() {
+                    Some(&last) => last + 1,
+                    // An empty section: right after its header, or at the top for the root.
+                    None => match section {
+                        Some(name) => {
+                            self.lines.iter().position(|l| matches!(l, Line::Section { name: n, .. } if n == name)).unwrap_or(0) + 1
+                        }
+                        None => 0,
+                    },
+                };
+                self.end_line_before(at);
+                self.lines.insert(at, line);
+            }
+            None => {
+                self.add_section(section.unwrap_or_default());
+                self.lines.push(line);
+            }
+        }
+    }
+
+    /// Appends an empty `[name]` section, separated from what comes before by a blank line.
+    pub(crate) fn add_section(&mut self, name: &str) {
+        self.end_line_before(self.lines.len());
+        if !self.lines.is_empty() {
+            self.lines.push(Line::Text("\n".to_string()));
+        }
+        self.lines.push(Line::Section { text: format!("[{name}]\n"), name: name.to_string() });
+    }
+
+    /// Makes sure the line in front of `at` ends with a newline before something is inserted there.
+    fn end_line_before(&mut self, at: usize) {
+        if let Some(Line::Text(text) | Line::Section { text, .. } | Line::Entry { text, .. }) = at.checked_sub(1).and_then(|i| self.lines.get_mut(i)) {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+        }
+    }
+
+    /// Index of the last entry called `key` in `section`; later duplicates win, as in most readers.
+    pub(crate) fn find(&self, section: Option<&str>, key: &str) -> Option<usize> {
+        self.sections()
+            .into_iter()
+            .filter(|s| s.name.as_deref() == section)
+            .flat_map(|s| s.entries)
+            .rfind(|&i| self.entry(i).is_some_and(|e| e.key == key))
+    }
+}
+
+pub(crate) fn emit_ini(doc: &IniDocument) -> String {
+    let mut out = String::new();
+    for (i, line) in doc.lines.iter().enumerate() {
+        match line {
+            Line::Text(text) | Line::Section { text, .. } => out.push_str(text),
+            Line::Entry { text, entry, original, .. } if entry == original => out.push_str(text),
+            Line::Entry { text, entry, layout, .. } => {
+                out.push_str(&format_entry(doc.dialect, entry, layout));
+                // New entries (no original text) always end their line.
+                if text.ends_with('\n') || text.is_empty() || i + 1 < doc.lines.len() {
+                    out.push('\n');
+                }
+            }
+        }
+    }
+    out
+}
+
+fn format_entry(dialect: IniDialect, entry: &IniEntry, layout: &EntryLayout) -> String {
+    let value = match dialect {
+        IniDialect::Ini => entry.value.clone(),
+        IniDialect::Env => env_value(&entry.value, layout.quote),
+    };
+    let export = if layout.export { "export " } else { "" };
+    format!("{}{export}{}{}{value}{}", layout.indent, entry.key, layout.separator, layout.comment)
+}
+
+/// Quotes a .env value when it needs it, keeping the original quote style where possible.
+fn env_value(value: &str, quote: Option<char>) -> String {
+    let needs_quotes = value.contains(|c: char| c.is_whitespace() || matches!(c, '#' | '"' | '\'' | '\\'));
+    match quote {
+        Some('\'') if !value.contains(['\'', '\n']) => format!("'{value}'"),
+        Some(_) => double_quoted(value),
+        None if needs_quotes => double_quoted(value),
+        None => value.to_string(),
+    }
+}
+
+fn double_quoted(value: &str) -> String {
+    let mut out = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(line: &str) -> (String, String) {
+        let (entry, layout) = parse_ini_entry(line).unwrap();
+        (entry.value, layout.comment)
+    }
+
+    #[test]
+    fn inline_comment_is_not_part_of_the_value() {
+        assert_eq!(value("port = 8080 ; default"), ("8080".to_string(), " ; default".to_string()));
+        assert_eq!(value("host=db   # primary"), ("db".to_string(), "   # primary".to_string()));
+        assert_eq!(value("path = a;b"), ("a;b".to_string(), String::new()));
+        assert_eq!(value("title = \"a ; b\" ; quoted"), ("\"a ; b\"".to_string(), " ; quoted".to_string()));
+        assert_eq!(value("empty = ; nothing"), (String::new(), "; nothing".to_string()));
+    }
+
+    #[test]
+    fn inline_comment_survives_an_edit() {
+        let mut doc = load_ini("[server]\nport = 8080 ; default\nhost = db\n", IniDialect::Ini).unwrap();
+        let port = doc.find(Some("server"), "port").unwrap();
+        assert_eq!(doc.entry(port).unwrap().value, "8080");
+        doc.entry_mut(port).unwrap().value = "9090".to_string();
+        assert_eq!(emit_ini(&doc), "[server]\nport = 9090 ; default\nhost = db\n");
+    }
+
+    #[test]
+    fn untouched_lines_are_written_back_as_read() {
+        let source = "; settings\n[a]\nx=1 ; one\n\n# end\n";
+        assert_eq!(emit_ini(&load_ini(source, IniDialect::Ini).unwrap()), source);
+    }
+}
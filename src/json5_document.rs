@@ -0,0 +1,549 @@
+// JSON5 and JSON-with-comments documents. The tree is a plain `serde_json::Value` so the JSON
+// renderer can edit it; saving splices the changed values back into the original text, which
+// keeps comments, quoting and layout everywhere else.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde_json::{Map, Number, Value};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum JsonDialect {
+    /// JSON with `//` and `/* */` comments and trailing commas, as used by VS Code and tsconfig.
+    Jsonc,
+    Json5,
+}
+
+/// Where a node sits in the source. For object members `entry` starts at the key;
+/// everywhere else it equals `value`.
+#[derive(Clone, Debug)]
+struct NodeSpan {
+    value: Range<usize>,
+    entry: Range<usize>,
+}
+
+pub(crate) struct Json5Document {
+    pub(crate) root: Value,
+    pub(crate) dialect: JsonDialect,
+    source: String,
+    original: Value,
+    spans: HashMap<Vec<String>, NodeSpan>,
+}
+
+/// Parses JSON5, or JSONC when `dialect` says so, which rejects the JSON5-only syntax.
+/// `Infinity` and `NaN` have no JSON value and are kept as strings; a value that is not
+/// edited keeps its original spelling either way.
+pub(crate) fn load_json5(source: &str, dialect: JsonDialect) -> Result<Json5Document, String> {
+    let mut parser = Parser { source, pos: 0, spans: HashMap::new(), dialect };
+    parser.skip_trivia()?;
+    let start = parser.pos;
+    let root = parser.value(&mut vec![])?;
+    parser.spans.insert(vec![], NodeSpan { value: start..parser.pos, entry: start..parser.pos });
+    parser.skip_trivia()?;
+    if parser.pos < source.len() {
+        return Err(parser.error("unexpected content after the document"));
+    }
+    Ok(Json5Document { original: root.clone(), root, dialect, source: source.to_string(), spans: parser.spans })
+}
+
+/// Parses a single JSON5 value, e.g. one typed on the command line.
+pub(crate) fn parse_json5_value(text: &str) -> Result<Value, String> {
+    load_json5(text, JsonDialect::Json5).map(|doc| doc.root)
+}
+
+/// The original text with every edit made to `doc.root` applied in place.
+pub(crate) fn emit_json5(doc: &Json5Document) -> String {
+    let mut edits = vec![];
+    diff(doc, &doc.original, &doc.root, &mut vec![], &mut edits);
+    // An insertion sorts ahead of a deletion starting at the same place, so it lands in front of it.
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+    // Removing neighbouring members can claim the same comma; merge those deletions.
+    let mut merged: Vec<(Range<usize>, String)> = vec![];
+    for (range, replacement) in edits {
+        match merged.last_mut() {
+            Some((last, last_text)) if last_text.is_empty() && replacement.is_empty() && range.start < last.end => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push((range, replacement)),
+        }
+    }
+    let mut text = doc.source.clone();
+    for (range, replacement) in merged.into_iter().rev() {
+        text.replace_range(range, &replacement);
+    }
+    text
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    spans: HashMap<Vec<String>, NodeSpan>,
+    dialect: JsonDialect,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> String {
+        let before = &self.source[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        format!("{message} at line {line} column {column}")
+    }
+
+    /// Fails on syntax JSON5 allows but JSONC does not.
+    fn json5_only(&self, what: &str) -> Result<(), String> {
+        match self.dialect {
+            JsonDialect::Json5 => Ok(()),
+            JsonDialect::Jsonc => Err(self.error(&format!("{what} are only allowed in JSON5"))),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.bump() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected `{expected}`"))),
+        }
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), String> {
+        loop {
+            let rest = &self.source[self.pos..];
+            if let Some(c) = rest.chars().next().filter(|c| c.is_whitespace() || *c == '\u{feff}') {
+                self.pos += c.len_utf8();
+            } else if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                let end = comment.find("*/").ok_or_else(|| self.error("unterminated comment"))?;
+                self.pos += end + 4;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn value(&mut self, path: &mut Vec<String>) -> Result<Value, String> {
+        match self.peek() {
+            Some('{') => self.object(path),
+            Some('[') => self.array(path),
+            Some(quote @ ('"' | '\'')) => self.string(quote).map(Value::String),
+            Some(c) if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') => self.number(),
+            Some(c) if is_identifier_char(c) => {
+                let word = self.identifier();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "null" => Ok(Value::Null),
+                    "Infinity" | "NaN" => {
+                        self.json5_only("Infinity and NaN")?;
+                        Ok(Value::String(word))
+                    }
+                    _ => Err(self.error(&format!("unexpected `{word}`"))),
+                }
+            }
+            Some(c) => Err(self.error(&format!("unexpected `{c}`"))),
+            None => Err(self.error("unexpected end of document")),
+        }
+    }
+
+    fn object(&mut self, path: &mut Vec<String>) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut map = Map::new();
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some('}') {
+                self.bump();
+                return Ok(Value::Object(map));
+            }
+            let entry_start = self.pos;
+            let key = match self.peek() {
+                Some(quote @ ('"' | '\'')) => self.string(quote)?,
+                Some(c) if is_identifier_char(c) => {
+                    self.json5_only("unquoted keys")?;
+                    self.identifier()
+                }
+                _ => return Err(self.error("expected a key")),
+            };
+            self.skip_trivia()?;
+            self.expect(':')?;
+            self.skip_trivia()?;
+            let value_start = self.pos;
+            path.push(key.clone());
+            let value = self.value(path)?;
+            self.spans.insert(path.clone(), NodeSpan { value: value_start..self.pos, entry: entry_start..self.pos });
+            path.pop();
+            map.insert(key, value);
+            self.skip_trivia()?;
+            match self.bump() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(map)),
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn array(&mut self, path: &mut Vec<String>) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = vec![];
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            let start = self.pos;
+            path.push(items.len().to_string());
+            let item = self.value(path)?;
+            self.spans.insert(path.clone(), NodeSpan { value: start..self.pos, entry: start..self.pos });
+            path.pop();
+            items.push(item);
+            self.skip_trivia()?;
+            match self.bump() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(is_identifier_char) {
+            self.bump();
+        }
+        self.source[start..self.pos].to_string()
+    }
+
+    fn string(&mut self, quote: char) -> Result<String, String> {
+        if quote == '\'' {
+            self.json5_only("single-quoted strings")?;
+        }
+        self.bump();
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => return Ok(text),
+                Some('\\') => match self.bump() {
+                    Some('b') => text.push('\u{8}'),
+                    Some('f') => text.push('\u{c}'),
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('t') => text.push('\t'),
+                    Some('v') => text.push('\u{b}'),
+                    Some('0') => text.push('\0'),
+                    Some('x') => {
+                        let code = self.hex_digits(2)?;
+                        text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    Some('u') => {
+                        let high = self.hex_digits(4)?;
+                        let code = if (0xd800..0xdc00).contains(&high) && self.source[self.pos..].starts_with("\\u") {
+                            self.pos += 2;
+                            let low = self.hex_digits(4)?;
+                            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+                        } else {
+                            high
+                        };
+                        text.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    // Line continuation
+                    Some('\r') => {
+                        if self.peek() == Some('\n') {
+                            self.bump();
+                        }
+                    }
+                    Some('\n' | '\u{2028}' | '\u{2029}') => {}
+                    Some(other) => text.push(other),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    fn hex_digits(&mut self, count: usize) -> Result<u32, String> {
+        let digits = self.source.get(self.pos..self.pos + count).ok_or_else(|| self.error("invalid escape"))?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("invalid escape"))?;
+        self.pos += count;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        let negative = match self.peek() {
+            Some('-') => {
+                self.bump();
+                true
+            }
+            Some('+') => {
+                self.json5_only("leading `+` signs")?;
+                self.bump();
+                false
+            }
+            _ => false,
+        };
+        if self.peek().is_some_and(is_identifier_char) && !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            let word = self.identifier();
+            return match word.as_str() {
+                "Infinity" | "NaN" => {
+                    self.json5_only("Infinity and NaN")?;
+                    Ok(Value::String(self.source[start..self.pos].to_string()))
+                }
+                _ => Err(self.error(&format!("unexpected `{word}`"))),
+            };
+        }
+        let digits_start = self.pos;
+        if self.source[self.pos..].starts_with("0x") || self.source[self.pos..].starts_with("0X") {
+            self.json5_only("hexadecimal numbers")?;
+            self.pos += 2;
+            let hex = self.identifier();
+            let magnitude = i64::from_str_radix(&hex, 16).map_err(|_| self.error("invalid hexadecimal number"))?;
+            return Ok(Value::from(if negative { -magnitude } else { magnitude }));
+        }
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E')) {
+            let c = self.bump();
+            if matches!(c, Some('e' | 'E')) && matches!(self.peek(), Some('+' | '-')) {
+                self.bump();
+            }
+        }
+        let text = &self.source[digits_start..self.pos];
+        if text.starts_with('.') || text.ends_with('.') || text.contains(".e") || text.contains(".E") {
+            self.json5_only("numbers with a bare decimal point")?;
+        }
+        let signed = if negative { format!("-{text}") } else { text.to_string() };
+        if !text.contains(['.', 'e', 'E']) {
+            if let Ok(i) = signed.parse::<i64>() {
+                return Ok(Value::from(i));
+            }
+            if let Ok(u) = signed.parse::<u64>() {
+                return Ok(Value::from(u));
+            }
+        }
+        signed
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| self.error(&format!("invalid number `{}`", &self.source[start..self.pos])))
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '$')
+}
+
+/// Whitespace at the start of the line holding `pos`, or `None` when `pos` is not the first
+/// thing on its line (an inline `{ a: 1, b: 2 }`).
+fn line_indent(source: &str, pos: usize) -> Option<&str> {
+    let line_start = source[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let before = &source[line_start..pos];
+    before.chars().all(char::is_whitespace).then_some(before)
+}
+
+/// `value` as JSON, with continuation lines indented to sit under `indent`.
+fn format_value(value: &Value, indent: &str) -> String {
+    let text = serde_json::to_string_pretty(value).unwrap_or_default();
+    text.replace('\n', &format!("\n{indent}"))
+}
+
+/// Text inserted after `anchor` (the end of the last member or item) to add new entries.
+fn insertion(source: &str, last_entry: &NodeSpan, entries: &[String]) -> String {
+    match line_indent(source, last_entry.entry.start) {
+        Some(indent) => entries.iter().map(|entry| format!(",\n{indent}{entry}")).collect(),
+        None => entries.iter().map(|entry| format!(", {entry}")).collect(),
+    }
+}
+
+/// The range to delete to drop a member or item, including its comma and, when it had a
+/// line of its own, the whole line.
+fn removal_range(source: &str, span: &NodeSpan) -> Range<usize> {
+    let mut start = span.entry.start;
+    let mut end = span.entry.end;
+    let following = source[end..]
+        .trim_start_matches([' ', '\t'])
+        .strip_prefix(',')
+        .map(|rest| rest.trim_start_matches([' ', '\t']));
+    let before = source[..start].trim_end();
+    match following {
+        // Another entry follows: take the comma and the spaces up to it.
+        Some(rest) if !rest.starts_with([']', '}']) => end = source.len() - rest.len(),
+        // Last entry: take the separator in front of it instead, keeping any trailing comma.
+        _ if before.ends_with(',') => return before.len() - 1..end,
+        // The only entry: its trailing comma goes with it.
+        Some(rest) => end = source.len() - rest.len(),
+        None => {}
+    }
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let rest = &source[end..];
+    let line_end = rest.find('\n').map_or(source.len(), |i| end + i + 1);
+    if source[line_start..start].trim().is_empty() && source[end..line_end].trim().is_empty() {
+        start = line_start;
+        end = line_end;
+    }
+    start..end
+}
+
+fn child_span(doc: &Json5Document, path: &[String], segment: &str) -> Option<NodeSpan> {
+    let mut child = path.to_vec();
+    child.push(segment.to_string());
+    doc.spans.get(&child).cloned()
+}
+
+fn diff(doc: &Json5Document, old: &Value, new: &Value, path: &mut Vec<String>, edits: &mut Vec<(Range<usize>, String)>) {
+    if old == new {
+        return;
+    }
+    let Some(span) = doc.spans.get(path.as_slice()) else {
+        return;
+    };
+    let replace = |edits: &mut Vec<(Range<usize>, String)>| {
+        let indent = line_indent(&doc.source, span.entry.start).unwrap_or("");
+        edits.push((span.value.clone(), format_value(new, indent)));
+    };
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            let removed: Vec<&String> = old_map.keys().filter(|k| !new_map.contains_key(*k)).collect();
+            let added: Vec<(&String, &Value)> = new_map.iter().filter(|(k, _)| !old_map.contains_key(*k)).collect();
+            if old_map.is_empty() || removed.len() == old_map.len() {
+                return replace(edits);
+            }
+            for (key, old_value) in old_map {
+                if let Some(new_value) = new_map.get(key) {
+                    path.push(key.clone());
+                    diff(doc, old_value, new_value, path, edits);
+                    path.pop();
+                }
+            }
+            for key in removed {
+                if let Some(span) = child_span(doc, path, key) {
+                    edits.push((removal_range(&doc.source, &span), String::new()));
+                }
+            }
+            if !added.is_empty() {
+                // New members go after the last one that stays, as removed ones take their commas with them.
+                let kept = old_map.keys().filter(|key| new_map.contains_key(*key));
+                let Some(last) = kept.filter_map(|key| child_span(doc, path, key)).max_by_key(|span| span.entry.end) else {
+                    return replace(edits);
+                };
+                let indent = line_indent(&doc.source, last.entry.start).unwrap_or("");
+                let entries: Vec<String> = added
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", Value::String((*key).clone()), format_value(value, indent)))
+                    .collect();
+                edits.push((last.value.end..last.value.end, insertion(&doc.source, &last, &entries)));
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            if old_items.len() == new_items.len() {
+                for (i, (old_item, new_item)) in old_items.iter().zip(new_items).enumerate() {
+                    path.push(i.to_string());
+                    diff(doc, old_item, new_item, path, edits);
+                    path.pop();
+                }
+            } else if !old_items.is_empty() && new_items.len() > old_items.len() && new_items.starts_with(old_items) {
+                let Some(last) = child_span(doc, path, &(old_items.len() - 1).to_string()) else {
+                    return replace(edits);
+                };
+                let indent = line_indent(&doc.source, last.entry.start).unwrap_or("");
+                let entries: Vec<String> = new_items[old_items.len()..].iter().map(|item| format_value(item, indent)).collect();
+                edits.push((last.value.end..last.value.end, insertion(&doc.source, &last, &entries)));
+            } else if new_items.len() + 1 == old_items.len() && !new_items.is_empty() {
+                let removed = (0..old_items.len())
+                    .find(|&i| old_items[..i] == new_items[..i] && old_items[i + 1..] == new_items[i..]);
+                match removed.and_then(|i| child_span(doc, path, &i.to_string())) {
+                    Some(span) => edits.push((removal_range(&doc.source, &span), String::new())),
+                    None => replace(edits),
+                }
+            } else {
+                replace(edits);
+            }
+        }
+        _ => replace(edits),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::document::{parse_key_path, FileType, ParsedDocument};
+
+    fn edit(source: &str, change: impl FnOnce(&mut ParsedDocument) -> Result<(), String>) -> String {
+        let mut doc = ParsedDocument::parse(source, FileType::Json5).unwrap();
+        change(&mut doc).unwrap();
+        doc.serialize().unwrap()
+    }
+
+    fn delete(source: &str, path: &str) -> String {
+        edit(source, |doc| doc.delete(&parse_key_path(path)))
+    }
+
+    fn set(source: &str, path: &str, value: &str) -> String {
+        edit(source, |doc| doc.set(&parse_key_path(path), value))
+    }
+
+    const COMMENTED: &str = "\
+// Service settings
+{
+  name: 'api', // shown in logs
+  /* seconds */
+  timeout: 30,
+  retries: 3,
+}
+";
+
+    #[test]
+    fn set_keeps_comments() {
+        assert_eq!(set(COMMENTED, "timeout", "60"), COMMENTED.replace("30", "60"));
+        let added = set(COMMENTED, "port", "8080");
+        assert!(added.starts_with("// Service settings\n"), "{added}");
+        assert!(added.contains("'api', // shown in logs\n  /* seconds */\n  timeout: 30,"), "{added}");
+        assert!(added.contains("\"port\": 8080") || added.contains("port: 8080"), "{added}");
+    }
+
+    #[test]
+    fn delete_keeps_comments() {
+        assert_eq!(delete(COMMENTED, "retries"), COMMENTED.replace("  retries: 3,\n", ""));
+        // A comment after the entry stays behind rather than be lost with it.
+        assert_eq!(delete(COMMENTED, "name"), COMMENTED.replace("name: 'api', ", ""));
+    }
+
+    #[test]
+    fn swapping_keys_keeps_comments() {
+        let swap = |source: &str, old: &str, new: &str| {
+            edit(source, |doc| {
+                doc.delete(&parse_key_path(old))?;
+                doc.set(&parse_key_path(new), "5")
+            })
+        };
+        assert_eq!(swap(COMMENTED, "retries", "port"), COMMENTED.replace("retries: 3", "\"port\": 5"));
+        let middle = swap(COMMENTED, "timeout", "port");
+        assert_eq!(middle, COMMENTED.replace("  /* seconds */\n  timeout: 30,\n", "  /* seconds */\n").replace("retries: 3", "retries: 3,\n  \"port\": 5"));
+        assert_eq!(swap("{ a: 1, b: 2 }", "b", "c"), "{ a: 1, \"c\": 5 }");
+        assert_eq!(swap("{ a: 1, b: 2, }", "a", "c"), "{ b: 2, \"c\": 5, }");
+    }
+
+    #[test]
+    fn delete_inline_items() {
+        assert_eq!(delete("[1, 2, 3,]", "1"), "[1, 3,]");
+        assert_eq!(delete("[1, 2, 3,]", "2"), "[1, 2,]");
+        assert_eq!(delete("[1, 2, 3]", "0"), "[2, 3]");
+        assert_eq!(delete("[1, 2, 3]", "2"), "[1, 2]");
+        assert_eq!(delete("[1,]", "0"), "[]");
+        assert_eq!(delete("{ a: 1, b: 2 }", "a"), "{ b: 2 }");
+    }
+
+    #[test]
+    fn delete_item_on_its_own_line() {
+        assert_eq!(delete("[\n  1,\n  2,\n]\n", "1"), "[\n  1,\n]\n");
+        assert_eq!(delete("[\n  1,\n  2\n]\n", "1"), "[\n  1\n]\n");
+    }
+}
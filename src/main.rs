@@ -4,22 +4,47 @@ mod render_yaml;
 mod render_json;
 mod render_toml;
 mod render_base_types;
+mod yaml_document;
+mod json5_document;
+mod ini_document;
+mod render_ini;
+mod document;
+mod convert;
+mod schema;
+mod cli;
+mod session;
+mod preferences;
+mod folder_browser;
+mod folder_search;
+mod document_model;
+mod raw_view;
+mod file_watch;
+mod change_log;
+mod diagnostics;
+mod file_lock;
 
 use std::{
     fs,
-    sync::{Arc, Mutex},
-    path::Path,
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
 };
 
 
 use eframe::{egui, App, Frame};
-use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
-use yaml_rust::{YamlLoader, YamlEmitter};
-use serde_json;
-use toml;
+use notify::{Event, RecommendedWatcher};
 use tokio::sync::mpsc;
 use rfd::FileDialog;
-use directories::ProjectDirs;
+
+use document::{file_type_for, supported_extensions, FileType, ParsedDocument};
+use preferences::{Preferences, SaveMode};
+use session::PaneLayout;
+use folder_browser::FolderBrowser;
+use document_model::{Baseline, ParseSnapshot, ParseWorker, StagedEdits, VersionedText};
+use file_watch::{init_file_watcher, spawn_file_watcher};
 
 const WINDOW_HEIGHT: f32 = 1000.;
 const WINDOW_WIDTH: f32 = 600.;
@@ -27,72 +52,551 @@ const WINDOW_WIDTH: f32 = 600.;
 const UI_SPACE: f32 = 2.;
 const INDENT_SPACES: f32 = 24.;
 const RAW_EDITOR_WIDTH_FRACTION: f32 = 0.5;
-const CONFIG_FILE_NAME: &str = "last_opened_file.txt";
-
-fn get_config_file_path() -> Option<std::path::PathBuf> {
-    ProjectDirs::from("org", "QuantumTools", "YamlEditor").map(|proj_dirs| {
-        let dir = proj_dirs.config_dir();
-        let _ = fs::create_dir_all(dir);
-        dir.join(CONFIG_FILE_NAME)
-    })
-}
-
-fn load_saved_file_path() -> Option<String> {
-    get_config_file_path()
-        .and_then(|path| fs::read_to_string(path).ok())
-        .map(|s| s.trim().to_string())
-        .filter(|s| std::path::Path::new(s).exists())
-}
-
-fn save_file_path(path: &str) {
-    if let Some(config_path) = get_config_file_path() {
-        let _ = fs::write(config_path, path);
-    }
-}
-
-enum FileType {
-    Yaml,
-    Json,
-    Toml,
-    Unknown,
-}
-
-fn detect_file_type(path: &str) -> FileType {
-    match Path::new(path).extension().and_then(|s| s.to_str()).unwrap_or("").to_lowercase().as_str() {
-        "yaml" | "yml" => FileType::Yaml,
-        "json" => FileType::Json,
-        "toml" => FileType::Toml,
-        _ => FileType::Unknown,
-    }
-}
+/// Thickness of the draggable bar between the raw editor and the tree.
+const SPLITTER_SIZE: f32 = 8.;
+/// Neither pane can be dragged smaller than this share of the window.
+const MIN_SPLIT_FRACTION: f32 = 0.1;
+/// Command-line argument that reads the document from stdin into an untitled buffer.
+const STDIN_ARG: &str = "-";
+/// Command-line argument that opens an empty untitled buffer.
+const NEW_ARG: &str = "--new";
+/// Tint of tree rows and raw editor lines that differ from the baseline.
+const MODIFIED_COLOR: egui::Color32 = egui::Color32::from_rgb(90, 160, 255);
+/// How often the status bar checks whether another program holds the file lock.
+const LOCK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long tree edits settle before they are written, so dragging a value writes once.
+const SAVE_DELAY: Duration = Duration::from_millis(500);
 
 struct YamlEditorApp {
-    content: Arc<Mutex<String>>,
+    /// Shared with the file watcher; `text` is this window's copy of its latest version.
+    content: Arc<Mutex<VersionedText>>,
+    text: String,
+    text_version: u64,
+    /// Version of the text that was last sniffed for its format.
+    sniffed_version: u64,
+    parsed: Option<ParseSnapshot>,
+    parser: ParseWorker,
+    raw_lines: raw_view::LineIndex,
     file_path: Arc<Mutex<String>>,
     show_raw_editor: bool,
-    dark_mode: bool,
+    layout: PaneLayout,
+    preferences: Preferences,
+    /// Settings changed in the dialog that are not written yet, e.g. while a slider is dragged.
+    preferences_changed: bool,
+    show_preferences: bool,
     scroll_marker_key: Option<String>,
     search_query: String,
     search_triggered: bool,
     file_type: FileType,
+    /// Set once the format was picked by hand, which stops content sniffing.
+    file_type_forced: bool,
+    export_report: Option<ExportReport>,
+    /// Why the file could not be read. While set, the document is not shown or written back.
+    load_error: Option<String>,
+    /// Why the last write failed; cleared by the next successful one.
+    save_error: Option<String>,
+    /// Local time of the last successful write.
+    last_saved: Option<String>,
+    show_diagnostics: bool,
+    /// Shown instead of a path for buffers that have no file yet.
+    buffer_name: String,
+    /// The buffer has edits that are not on disk: it is untitled, or saving is manual.
+    unsaved: bool,
+    /// Waits for the user to save or discard unsaved edits.
+    pending_action: Option<PendingAction>,
+    close_confirmed: bool,
+    watch_tx: mpsc::Sender<Event>,
+    file_watcher: Option<RecommendedWatcher>,
+    /// Set by the file watcher while the file is deleted or moved away.
+    file_missing: Arc<AtomicBool>,
+    /// Another program changed the file while this window had unsaved edits. The edits are
+    /// kept, and not written, until the user picks which version to keep.
+    disk_conflict: bool,
+    /// Read-only mode chosen from the toolbar.
+    read_only: bool,
+    /// The file cannot be written, which forces read-only mode.
+    write_protected: bool,
+    /// Another program held the file lock when last checked, at `lock_checked`.
+    locked_elsewhere: bool,
+    lock_checked: Option<Instant>,
+    /// A write waits for another program to release the file lock.
+    save_when_unlocked: bool,
+    /// Tree edits are written at this moment, unless more follow.
+    save_due: Option<Instant>,
+    /// The document before the latest reload by the file watcher, in JSON form, and the
+    /// version it is compared with once that version is parsed.
+    external_base: Option<(u64, serde_json::Value)>,
+    change_log: Vec<change_log::ValueChange>,
+    show_change_log: bool,
+    /// Bumped when another file is opened, so that its tree does not inherit the previous
+    /// file's expanded nodes.
+    tree_generation: u64,
+    /// Depth used by the tree pane's "Expand to depth" command.
+    expand_depth: usize,
+    /// Tree edits are collected in `staged` for review instead of saved one by one.
+    stage_edits: bool,
+    staged: Option<StagedEdits>,
+    /// What the modified markers in the tree and the gutter compare the document with.
+    baseline: Baseline,
+    folder_browser: Option<FolderBrowser>,
+}
+
+/// Something that would drop unsaved edits, held back until the user decides.
+enum PendingAction {
+    Close,
+    /// A file, and the key path to jump to once it is open (empty for none).
+    Open(String, Vec<String>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Raw,
+    Tree,
+}
+
+/// Outcome of the last "Export as…", shown until dismissed.
+struct ExportReport {
+    message: String,
+    issues: Vec<String>,
+    is_error: bool,
 }
 
 impl YamlEditorApp {
-    fn new(file_path: Arc<Mutex<String>>, content: Arc<Mutex<String>>) -> Self {
-        let file_type = detect_file_type(&file_path.lock().unwrap());
+    fn new(
+        ctx: &egui::Context,
+        file_path: Arc<Mutex<String>>,
+        content: Arc<Mutex<VersionedText>>,
+        preferences: Preferences,
+        watch_tx: mpsc::Sender<Event>,
+    ) -> Self {
+        let (text, text_version) = {
+            let shared = content.lock().unwrap();
+            (shared.text.clone(), shared.version)
+        };
+        let file_type = file_type_for(&file_path.lock().unwrap(), &text);
+        let expand_depth = preferences.expand_depth.max(1);
+        let baseline = Baseline::new(text_version, text.clone());
         Self {
             content,
+            text,
+            text_version,
+            sniffed_version: text_version,
+            parsed: None,
+            parser: ParseWorker::new(ctx),
+            raw_lines: Default::default(),
             file_path,
-            show_raw_editor: false,
-            dark_mode: true,
+            show_raw_editor: preferences.show_raw_editor,
+            layout: PaneLayout { split_fraction: preferences.raw_editor_fraction, ..PaneLayout::default() },
+            preferences,
+            preferences_changed: false,
+            show_preferences: false,
             scroll_marker_key: None,
             search_query: String::new(),
             search_triggered: false,
             file_type,
+            file_type_forced: false,
+            export_report: None,
+            load_error: None,
+            save_error: None,
+            last_saved: None,
+            show_diagnostics: false,
+            buffer_name: "untitled".to_string(),
+            unsaved: false,
+            pending_action: None,
+            close_confirmed: false,
+            watch_tx,
+            file_watcher: None,
+            file_missing: Arc::new(AtomicBool::new(false)),
+            disk_conflict: false,
+            read_only: false,
+            write_protected: false,
+            locked_elsewhere: false,
+            lock_checked: None,
+            save_when_unlocked: false,
+            save_due: None,
+            external_base: None,
+            change_log: vec![],
+            show_change_log: false,
+            tree_generation: 0,
+            expand_depth,
+            stage_edits: false,
+            staged: None,
+            baseline,
+            folder_browser: None,
+        }
+    }
+
+    /// The file name to suggest in save dialogs.
+    fn suggested_path(&self) -> String {
+        let path = self.file_path.lock().unwrap();
+        if !path.is_empty() {
+            return path.clone();
+        }
+        match self.file_type.extensions().first() {
+            Some(extension) => format!("{}.{extension}", self.buffer_name),
+            None => self.buffer_name.clone(),
+        }
+    }
+
+    /// Called after every edit: writes the buffer back to its file when saving is automatic.
+    /// Otherwise, and for untitled buffers, a file that went missing or one changed by another
+    /// program meanwhile, it only remembers that there are unsaved changes.
+    fn save(&mut self, content: &str) {
+        self.publish_text(content);
+        self.save_published(content);
+    }
+
+    /// Like `save`, for a text that `publish_text` already shared.
+    fn save_published(&mut self, content: &str) {
+        self.save_due = None;
+        let untitled = self.file_path.lock().unwrap().is_empty();
+        if untitled
+            || self.file_missing.load(Ordering::Relaxed)
+            || self.disk_conflict
+            || self.preferences.save_mode == SaveMode::Manual
+        {
+            self.unsaved = true;
+            return;
+        }
+        // Rather than block on every keystroke, write once the other program is done.
+        if self.locked_elsewhere {
+            self.unsaved = true;
+            self.save_when_unlocked = true;
+            return;
+        }
+        self.save_now(content);
+    }
+
+    /// Like `save`, but waits for `SAVE_DELAY` without further edits before writing.
+    fn save_soon(&mut self, ctx: &egui::Context, content: &str) {
+        self.publish_text(content);
+        self.unsaved = true;
+        self.save_due = Some(Instant::now() + SAVE_DELAY);
+        ctx.request_repaint_after(SAVE_DELAY);
+    }
+
+    /// Writes tree edits that are still waiting for `SAVE_DELAY`.
+    fn flush_save(&mut self, content: &str) {
+        if self.save_due.is_some() {
+            self.save_published(content);
+        }
+    }
+
+    /// Shares an edited text with the file watcher under a new version.
+    fn publish_text(&mut self, content: &str) {
+        let mut shared = self.content.lock().unwrap();
+        shared.replace(content.to_string());
+        self.text_version = shared.version;
+        // Edits made before a reload is parsed would otherwise be logged as external changes.
+        self.external_base = None;
+    }
+
+    /// Writes the buffer to its file now; untitled buffers ask for a file name first.
+    /// A file that failed to load is never overwritten.
+    fn save_now(&mut self, content: &str) {
+        let path = self.file_path.lock().unwrap().clone();
+        if path.is_empty() {
+            self.save_as(content);
+            return;
+        }
+        if self.load_error.is_some() {
+            diagnostics::warning(format!("{path} was not saved because it failed to load"));
+            return;
+        }
+        if self.is_read_only() {
+            diagnostics::warning(format!("{path} was not saved because it is open read-only; use Save As… instead"));
+            return;
+        }
+        match file_lock::write(&path, content, file_lock::UI_WAIT) {
+            Ok(()) => self.saved(content),
+            Err(err) => {
+                self.save_when_unlocked = file_lock::is_locked(&err);
+                self.save_failed(format!("cannot write {path}: {err}"));
+            }
+        }
+    }
+
+    /// Whether closing the document now would lose edits.
+    fn has_pending_edits(&self) -> bool {
+        self.unsaved || self.staged.is_some()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only || self.write_protected
+    }
+
+    /// Checks again whether the file can be written, e.g. after it was opened or replaced.
+    fn check_write_protection(&mut self) {
+        let path = self.file_path.lock().unwrap().clone();
+        self.write_protected = !path.is_empty() && !is_writable(&path);
+    }
+
+    fn saved(&mut self, content: &str) {
+        // When saving by hand, the markers show what is not on disk yet.
+        if self.preferences.save_mode == SaveMode::Manual {
+            self.baseline = Baseline::new(self.text_version, content.to_string());
+        }
+        self.unsaved = false;
+        self.save_when_unlocked = false;
+        self.save_due = None;
+        self.save_error = None;
+        self.last_saved = Some(change_log::clock_time());
+        self.file_missing.store(false, Ordering::Relaxed);
+    }
+
+    /// Keeps the edits flagged as unsaved so a failed write is never mistaken for a saved one.
+    fn save_failed(&mut self, message: String) {
+        self.unsaved = true;
+        diagnostics::error(message.clone());
+        self.save_error = Some(message);
+    }
+
+    /// Writes the buffer to a new file and keeps editing that file from then on.
+    fn save_as(&mut self, content: &str) {
+        let suggested = self.suggested_path();
+        let suggested = std::path::Path::new(&suggested);
+        let mut dialog = FileDialog::new();
+        if self.file_type != FileType::Unknown {
+            dialog = dialog.add_filter(self.file_type.label(), self.file_type.extensions());
+        }
+        dialog = dialog.add_filter("Settings files", &supported_extensions());
+        if let Some(dir) = suggested.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(name) = suggested.file_name().and_then(|n| n.to_str()) {
+            dialog = dialog.set_file_name(name);
+        }
+        let Some(path_buf) = dialog.save_file() else {
+            return;
+        };
+        let Ok(path) = path_buf.into_os_string().into_string() else {
+            self.save_failed("the chosen path is not valid UTF-8".to_string());
+            return;
+        };
+        if let Err(err) = file_lock::write(&path, content, file_lock::UI_WAIT) {
+            self.save_failed(format!("cannot write {path}: {err}"));
+            return;
+        }
+
+        *self.file_path.lock().unwrap() = path.clone();
+        session::add_recent_file(&path);
+        self.saved(content);
+        self.check_write_protection();
+        if !self.file_type_forced {
+            self.file_type = file_type_for(&path, content);
+        }
+        self.file_watcher = init_file_watcher(self.watch_tx.clone(), &path);
+    }
+
+    /// Replaces the document in this window with the file at `path`.
+    fn open_file(&mut self, ctx: &egui::Context, path: String, content: &mut String) {
+        session::save_open_nodes(&self.file_path.lock().unwrap(), &render_base_types::open_nodes(ctx));
+        match load_file(&path) {
+            Ok(text) => {
+                *content = text;
+                self.load_error = None;
+            }
+            Err(err) => {
+                diagnostics::error(err.clone());
+                content.clear();
+                self.load_error = Some(err);
+            }
+        }
+        self.publish_text(content);
+        self.baseline = Baseline::new(self.text_version, content.clone());
+        render_base_types::mark_modified(ctx, std::iter::empty());
+        self.file_type = file_type_for(&path, content);
+        self.file_type_forced = false;
+        self.unsaved = false;
+        self.save_error = None;
+        self.save_due = None;
+        self.file_missing.store(false, Ordering::Relaxed);
+        self.disk_conflict = false;
+        self.change_log.clear();
+        self.staged = None;
+        render_base_types::mark_external_changes(ctx, std::iter::empty());
+        self.tree_generation += 1;
+        render_base_types::clear_expansion(ctx);
+        render_base_types::restore_open_nodes(ctx, session::load_open_nodes(&path));
+        self.file_watcher = init_file_watcher(self.watch_tx.clone(), &path);
+        session::add_recent_file(&path);
+        *self.file_path.lock().unwrap() = path;
+        self.check_write_protection();
+    }
+
+    /// Opens `path` here and jumps to the node at `node`, asking first when the current
+    /// document has unsaved edits.
+    fn request_open(&mut self, ctx: &egui::Context, path: String, node: Vec<String>, content: &mut String) {
+        if path == *self.file_path.lock().unwrap() {
+            render_base_types::set_jump_target(ctx, node);
+            return;
+        }
+        self.flush_save(content);
+        if self.has_pending_edits() {
+            self.pending_action = Some(PendingAction::Open(path, node));
+        } else {
+            self.open_file(ctx, path, content);
+            render_base_types::set_jump_target(ctx, node);
+        }
+    }
+
+    /// Shows or hides the folder browser, asking for a folder when it is opened.
+    fn toggle_folder_browser(&mut self, ctx: &egui::Context) {
+        if self.folder_browser.take().is_some() {
+            return;
+        }
+        let current = self.file_path.lock().unwrap().clone();
+        let mut dialog = FileDialog::new();
+        if let Some(dir) = std::path::Path::new(&current).parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(root) = dialog.pick_folder() {
+            self.folder_browser = Some(FolderBrowser::open(root, ctx));
+        }
+    }
+
+    fn render_folder_browser(&mut self, ctx: &egui::Context, content: &mut String) {
+        let Some(browser) = &mut self.folder_browser else {
+            return;
+        };
+        let current = self.file_path.lock().unwrap().clone();
+        let mut close = false;
+        let mut clicked = None;
+        egui::SidePanel::left("folder_browser")
+            .resizable(true)
+            .default_width(220.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let name = browser.root.file_name().map_or_else(|| browser.root.display().to_string(), |n| n.to_string_lossy().into_owned());
+                    ui.label(egui::RichText::new(format!("🗂 {name}")).strong())
+                        .on_hover_text(browser.root.display().to_string());
+                    close = ui.small_button("✖").on_hover_text("Close the folder").clicked();
+                });
+                clicked = browser.show(ui, &current);
+            });
+        if close {
+            self.folder_browser = None;
+        }
+        if let Some((path, node)) = clicked {
+            self.request_open(ctx, path, node, content);
+        }
+    }
+
+    /// Offers to write the document back, find where the file went, or close the window
+    /// while the file is deleted or moved away.
+    fn render_missing_file_banner(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, content: &mut String) {
+        if !self.file_missing.load(Ordering::Relaxed) {
+            return;
+        }
+        let path = self.file_path.lock().unwrap().clone();
+        ui.colored_label(egui::Color32::YELLOW, format!("⚠️ {path} was deleted or moved. Edits are kept here until you decide."));
+        ui.horizontal(|ui| {
+            if ui.button("💾 Recreate").on_hover_text("Write this document to the same path").clicked() {
+                self.save_now(content);
+            }
+            if ui.button("🔍 Locate…").on_hover_text("Open the file from where it was moved to").clicked() {
+                let mut dialog = FileDialog::new().add_filter("Settings files", &supported_extensions());
+                if let Some(dir) = std::path::Path::new(&path).parent().filter(|dir| dir.exists()) {
+                    dialog = dialog.set_directory(dir);
+                }
+                if let Some(located) = dialog.pick_file().and_then(|p| p.into_os_string().into_string().ok()) {
+                    self.request_open(ctx, located, vec![], content);
+                }
+            }
+            if ui.button("✖ Close").clicked() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        });
+        ui.separator();
+    }
+
+    /// Asks which version to keep after another program changed the file under unsaved edits.
+    fn render_disk_conflict_banner(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, content: &mut String) {
+        if !self.disk_conflict {
+            return;
         }
+        let path = self.file_path.lock().unwrap().clone();
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!("⚠️ {path} was changed by another program while this window had unsaved edits."),
+        );
+        ui.horizontal(|ui| {
+            let hover = if self.preferences.save_mode == SaveMode::Manual {
+                "Keep editing; the file is replaced when you save"
+            } else {
+                "Write your edits over the other program's change"
+            };
+            if ui.button("✏ Keep my edits").on_hover_text(hover).clicked() {
+                self.disk_conflict = false;
+                self.save(content);
+            }
+            if ui.button("🔄 Load from disk").on_hover_text("Drop your edits and show the file as it is now").clicked() {
+                self.reload_from_disk(ctx, content);
+            }
+        });
+        ui.separator();
     }
 
-    fn render_toolbar(&mut self, ui: &mut egui::Ui) {
+    /// Replaces the buffer and its unsaved edits with the file as it is on disk now.
+    fn reload_from_disk(&mut self, ctx: &egui::Context, content: &mut String) {
+        let path = self.file_path.lock().unwrap().clone();
+        match load_file(&path) {
+            Ok(text) => {
+                *content = text;
+                self.publish_text(content);
+                if self.preferences.save_mode == SaveMode::Manual {
+                    self.baseline = Baseline::new(self.text_version, content.clone());
+                }
+                self.unsaved = false;
+                // The edits that failed to save or waited for the lock are gone, and with them
+                // the error and the deferred save.
+                self.save_error = None;
+                self.save_when_unlocked = false;
+                self.save_due = None;
+                self.disk_conflict = false;
+                render_base_types::restore_open_nodes(ctx, render_base_types::open_nodes(ctx));
+                self.check_write_protection();
+            }
+            Err(err) => diagnostics::error(err),
+        }
+    }
+
+    /// Reads the file again after a failed load.
+    fn retry_load(&mut self, content: &mut String) {
+        let path = self.file_path.lock().unwrap().clone();
+        match load_file(&path) {
+            Ok(text) => {
+                *content = text;
+                self.publish_text(content);
+                self.baseline = Baseline::new(self.text_version, content.clone());
+                self.load_error = None;
+                self.file_missing.store(false, Ordering::Relaxed);
+                self.disk_conflict = false;
+                self.check_write_protection();
+                if !self.file_type_forced {
+                    self.file_type = file_type_for(&path, content);
+                }
+                self.file_watcher = init_file_watcher(self.watch_tx.clone(), &path);
+            }
+            Err(err) => {
+                diagnostics::error(err.clone());
+                self.load_error = Some(err);
+            }
+        }
+    }
+
+    fn render_load_error(&mut self, ui: &mut egui::Ui, content: &mut String) {
+        let Some(err) = &self.load_error else {
+            return;
+        };
+        ui.colored_label(egui::Color32::RED, format!("⚠️ {err}"));
+        ui.label("The file was not opened, so nothing is shown or written back to it.");
+        if ui.button("🔄 Retry").clicked() {
+            self.retry_load(content);
+        }
+    }
+
+    fn render_toolbar(&mut self, ui: &mut egui::Ui, content: &str) {
         ui.vertical(|ui| {
 
             // Line 1: Buttons and checkboxes
@@ -100,67 +604,556 @@ impl YamlEditorApp {
 
 
                 if ui.button("📂 Load File").clicked() {
-                    if let Some(path_buf) = FileDialog::new().add_filter("YAML", &["yaml", "toml", "json"]).pick_file() {
+                    if let Some(path_buf) = FileDialog::new().add_filter("Settings files", &supported_extensions()).pick_file() {
                         if let Ok(new_path) = path_buf.into_os_string().into_string() {
-                            let mut cmd = std::process::Command::new(std::env::current_exe().unwrap());
-                            cmd.arg(&new_path);
-
-                            #[cfg(unix)] {
-                                use std::os::unix::process::CommandExt;
-                            unsafe {
-                                cmd.pre_exec(|| {
-                                    libc::setsid(); // new session
-                                    Ok(())
-                                });
-                            }}
-                            
-                            #[cfg(windows)] {
-                                use std::os::windows::process::CommandExt;
-                                const CREATE_NEW_CONSOLE: u32 = 0x00000010;
-                                cmd.creation_flags(CREATE_NEW_CONSOLE);
-                            }
-
-                            cmd.spawn().expect("Failed to launch new instance");
+                            spawn_instance(&new_path);
                         }
                     }
                 }
+                self.render_recent_menu(ui);
+                if ui.selectable_label(self.folder_browser.is_some(), "🗂 Folder").on_hover_text("Browse the settings files in a folder").clicked() {
+                    self.toggle_folder_browser(ui.ctx());
+                }
+                if ui.button("🆕 New").on_hover_text("Open an empty untitled buffer").clicked() {
+                    spawn_instance(NEW_ARG);
+                }
+                if self.preferences.save_mode == SaveMode::Manual
+                    && ui.add_enabled(self.unsaved && !self.is_read_only(), egui::Button::new("💾 Save")).on_hover_text("Ctrl+S").clicked()
+                {
+                    self.save_now(content);
+                }
+                if ui.add_enabled(self.load_error.is_none(), egui::Button::new("💾 Save As…")).clicked() {
+                    self.save_as(content);
+                }
+                let hover = if self.write_protected {
+                    "The file cannot be written, so it is always open read-only"
+                } else {
+                    "Turn off editing in the tree and the raw editor"
+                };
+                if ui
+                    .add_enabled(!self.write_protected, egui::SelectableLabel::new(self.is_read_only(), "🔒 Read-only"))
+                    .on_hover_text(hover)
+                    .on_disabled_hover_text(hover)
+                    .clicked()
+                {
+                    self.read_only = !self.read_only;
+                }
+                self.render_format_selector(ui);
+                self.render_export_menu(ui);
                 ui.checkbox(&mut self.show_raw_editor, "📝 Show Raw Editor");
-                ui.checkbox(&mut self.dark_mode, "🌗 Dark Mode");
+                let (icon, hover) = if self.layout.stacked {
+                    ("⬍", "Raw editor above the tree; click to put them side by side")
+                } else {
+                    ("⬌", "Raw editor beside the tree; click to stack them")
+                };
+                if ui.add_enabled(self.show_raw_editor, egui::Button::new(icon)).on_hover_text(hover).clicked() {
+                    self.layout.stacked = !self.layout.stacked;
+                }
+                if ui.checkbox(&mut self.preferences.dark_mode, "🌗 Dark Mode").changed() {
+                    self.preferences.save();
+                }
+                if ui.button("⚙ Settings").clicked() {
+                    self.show_preferences = !self.show_preferences;
+                }
             });
 
             // Line 2: File label and path
             ui.horizontal(|ui| {
                 ui.label("📁 File:");
-                let path = self.file_path.lock().unwrap();
-                ui.label(egui::RichText::new(path.as_str()).monospace());
+                let path = self.file_path.lock().unwrap().clone();
+                if path.is_empty() {
+                    ui.label(egui::RichText::new(&self.buffer_name).monospace().italics());
+                    if self.unsaved {
+                        ui.colored_label(egui::Color32::YELLOW, "● not saved, use Save As…");
+                    }
+                } else {
+                    ui.label(egui::RichText::new(path).monospace());
+                    if self.unsaved {
+                        ui.colored_label(egui::Color32::YELLOW, "● unsaved changes");
+                    }
+                }
+                if self.write_protected {
+                    ui.colored_label(egui::Color32::YELLOW, "🔒 Read-only: the file is not writable")
+                        .on_hover_text("Use Save As… to keep edits in another file");
+                } else if self.read_only {
+                    ui.colored_label(egui::Color32::YELLOW, "🔒 Read-only");
+                }
+                if !self.change_log.is_empty() {
+                    let label = format!("📋 External changes ({})", self.change_log.len());
+                    if ui.selectable_label(self.show_change_log, label).clicked() {
+                        self.show_change_log = !self.show_change_log;
+                    }
+                }
             });
+        });
+    }
+
+    /// Looks every so often whether another program holds the file lock, and writes edits
+    /// that were held back once it is released.
+    fn check_file_lock(&mut self, ctx: &egui::Context, content: &str) {
+        if !file_lock::is_enabled() {
+            self.locked_elsewhere = false;
+            return;
+        }
+        if self.lock_checked.is_some_and(|checked| checked.elapsed() < LOCK_CHECK_INTERVAL) {
+            return;
+        }
+        let path = self.file_path.lock().unwrap().clone();
+        self.locked_elsewhere = !path.is_empty() && file_lock::held_elsewhere(&path);
+        self.lock_checked = Some(Instant::now());
+        ctx.request_repaint_after(LOCK_CHECK_INTERVAL);
+        // After a conflicting change on disk, only the user decides what is written.
+        if self.save_when_unlocked && !self.locked_elsewhere && !self.disk_conflict {
+            self.save_now(content);
+        }
+    }
+
+    /// Save state and the latest problem, along the bottom of the window.
+    fn render_status_bar(&mut self, ctx: &egui::Context, content: &str) {
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if self.locked_elsewhere {
+                    let text = if self.save_when_unlocked {
+                        "🔐 Locked by another program; saving once it is released"
+                    } else {
+                        "🔐 Locked by another program"
+                    };
+                    ui.colored_label(egui::Color32::YELLOW, text);
+                }
+                if let Some(err) = &self.save_error {
+                    ui.colored_label(egui::Color32::RED, format!("⚠️ Not saved: {err}"));
+                    if ui.small_button("🔄 Retry").clicked() {
+                        self.save_now(content);
+                    }
+                } else if let Some(time) = self.last_saved.as_ref().filter(|_| !self.unsaved) {
+                    ui.label(format!("✔ Saved at {time}"));
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let label = format!("🩺 Diagnostics ({})", diagnostics::count());
+                    if ui.selectable_label(self.show_diagnostics, label).clicked() {
+                        self.show_diagnostics = !self.show_diagnostics;
+                    }
+                    diagnostics::render_latest(ui);
+                });
+            });
+        });
+    }
+
+    fn render_diagnostics_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Diagnostics")
+            .open(&mut self.show_diagnostics)
+            .show(ctx, diagnostics::render_log);
+    }
+
+    /// Opens a recently used file in a new window.
+    fn render_recent_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("🕘 Recent", |ui| {
+            let current = self.file_path.lock().unwrap().clone();
+            let recent: Vec<String> = session::recent_files().into_iter().filter(|path| *path != current).collect();
+            if recent.is_empty() {
+                ui.label("No recent files");
+                return;
+            }
+            for path in recent {
+                if ui.button(&path).clicked() {
+                    ui.close_menu();
+                    spawn_instance(&path);
+                }
+            }
+            ui.separator();
+            if ui.button("Clear list").clicked() {
+                ui.close_menu();
+                session::clear_recent_files();
+            }
+        });
+    }
+
+    /// The state of this window as stored in the session file.
+    fn document_state(&self) -> session::DocumentState {
+        session::DocumentState {
+            path: self.file_path.lock().unwrap().clone(),
+            pid: std::process::id(),
+            show_raw_editor: self.show_raw_editor,
+            layout: self.layout,
+            folder: self.folder_browser.as_ref().map(|browser| browser.root.to_string_lossy().into_owned()).unwrap_or_default(),
+            search_query: self.search_query.clone(),
+        }
+    }
+
+    fn restore_document_state(&mut self, ctx: &egui::Context, state: session::DocumentState) {
+        self.show_raw_editor = state.show_raw_editor;
+        self.layout = state.layout;
+        if !state.folder.is_empty() && std::path::Path::new(&state.folder).is_dir() {
+            self.folder_browser = Some(FolderBrowser::open(state.folder.into(), ctx));
+        }
+        self.search_query = state.search_query;
+    }
+
+    /// Shows the detected format and lets the user force another one.
+    fn render_format_selector(&mut self, ui: &mut egui::Ui) {
+        let selected = match self.file_type {
+            FileType::Unknown => "Unknown",
+            file_type => file_type.label(),
+        };
+        egui::ComboBox::from_id_salt("file_format")
+            .selected_text(format!("📄 {selected}"))
+            .show_ui(ui, |ui| {
+                for file_type in FileType::ALL {
+                    if ui.selectable_label(self.file_type == file_type, file_type.label()).clicked() {
+                        self.file_type = file_type;
+                        self.file_type_forced = true;
+                    }
+                }
+            })
+            .response
+            .on_hover_text("Format used to read and write this file");
+    }
+
+    fn render_export_menu(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(self.file_type != FileType::Unknown, |ui| {
+            ui.menu_button("📤 Export as…", |ui| {
+                for target in FileType::ALL {
+                    if target != self.file_type && ui.button(target.label()).clicked() {
+                        ui.close_menu();
+                        self.export_as(target);
+                    }
+                }
+            });
+        });
+    }
+
+    /// Asks where to write the current document as `target` and records what was lost on the way.
+    fn export_as(&mut self, target: FileType) {
+        let default_path = convert::path_with_extension(&self.suggested_path(), target);
+        let default_path = std::path::Path::new(&default_path);
+        let mut dialog = FileDialog::new().add_filter(target.label(), target.extensions());
+        if let Some(dir) = default_path.parent() {
+            dialog = dialog.set_directory(dir);
+        }
+        if let Some(name) = default_path.file_name().and_then(|n| n.to_str()) {
+            dialog = dialog.set_file_name(name);
+        }
+        let Some(output) = dialog.save_file() else {
+            return;
+        };
 
+        let content = self.content.lock().unwrap().text.clone();
+        let written = ParsedDocument::parse(&content, self.file_type)
+            .and_then(|mut doc| convert::convert_to_text(&mut doc, target))
+            .and_then(|(text, issues)| fs::write(&output, text).map(|_| issues).map_err(|err| err.to_string()));
+        self.export_report = Some(match written {
+            Ok(issues) => ExportReport { message: format!("Exported to {}", output.display()), issues, is_error: false },
+            Err(err) => {
+                diagnostics::error(format!("export to {} failed: {err}", output.display()));
+                ExportReport { message: format!("Export failed: {err}"), issues: vec![], is_error: true }
+            }
         });
     }
 
+    fn render_export_report(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.export_report else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Export")
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                if report.is_error {
+                    ui.colored_label(egui::Color32::RED, format!("⚠️ {}", report.message));
+                } else {
+                    ui.label(format!("✅ {}", report.message));
+                }
+                if !report.issues.is_empty() {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("⚠️ {} value(s) could not be written exactly:", report.issues.len()),
+                    );
+                    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        for issue in &report.issues {
+                            ui.label(egui::RichText::new(issue).monospace());
+                        }
+                    });
+                }
+            });
+        if !open {
+            self.export_report = None;
+        }
+    }
+
+    fn render_change_log_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_change_log;
+        let mut clicked = None;
+        egui::Window::new("External changes")
+            .open(&mut open)
+            .show(ctx, |ui| clicked = change_log::render_change_log(ui, &mut self.change_log));
+        self.show_change_log = open && !self.change_log.is_empty();
+        if let Some(path) = clicked {
+            render_base_types::set_jump_target(ctx, path);
+        }
+    }
+
+    /// Logs and marks what changed when the file watcher reloaded the document, once the
+    /// reloaded version is parsed.
+    fn record_external_changes(&mut self, ctx: &egui::Context) {
+        let Some((version, _)) = &self.external_base else {
+            return;
+        };
+        let Some(Ok(parsed)) = self.parsed.as_ref().filter(|parsed| parsed.version == *version).map(|parsed| &parsed.result) else {
+            return;
+        };
+        let Some((_, base)) = self.external_base.take() else {
+            return;
+        };
+        let changes = change_log::diff(&base, &convert::to_json(parsed));
+        render_base_types::mark_external_changes(ctx, changes.iter().map(|change| &change.path));
+        change_log::append(&mut self.change_log, changes);
+    }
+
+    fn render_preferences_window(&mut self, ctx: &egui::Context, content: &str) {
+        let mut open = self.show_preferences;
+        egui::Window::new("Settings")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let edit = preferences::render_preferences(ui, &mut self.preferences);
+                if edit.changed {
+                    self.preferences_changed = true;
+                    self.layout.split_fraction = self.preferences.raw_editor_fraction;
+                    // Switching back to automatic saving writes what is pending.
+                    if self.preferences.save_mode == SaveMode::Auto && self.unsaved && !self.file_path.lock().unwrap().is_empty() {
+                        self.save_now(content);
+                    }
+                }
+                // Dragging a slider changes the settings every frame; they are written once it is let go.
+                if edit.finished {
+                    self.save_preferences();
+                }
+            });
+        if !open {
+            self.save_preferences();
+        }
+        self.show_preferences = open;
+    }
+
+    /// Writes the settings if the dialog changed them since they were last written.
+    fn save_preferences(&mut self) {
+        if std::mem::take(&mut self.preferences_changed) {
+            self.preferences.save();
+        }
+    }
+
+    /// Asks what to do with unsaved edits before closing the window or opening another file.
+    fn render_unsaved_confirmation(&mut self, ctx: &egui::Context, content: &mut String) {
+        if self.pending_action.is_none() {
+            return;
+        }
+        let mut proceed = false;
+        egui::Window::new("Unsaved changes")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label("This document has changes that are not saved.");
+                ui.horizontal(|ui| {
+                    if ui.button("💾 Save").clicked() {
+                        self.apply_staged(content);
+                        self.save_now(content);
+                        proceed = !self.has_pending_edits();
+                    }
+                    if ui.button("Discard").clicked() {
+                        proceed = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.pending_action = None;
+                    }
+                });
+            });
+        if !proceed {
+            return;
+        }
+        match self.pending_action.take() {
+            Some(PendingAction::Close) => {
+                self.close_confirmed = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            Some(PendingAction::Open(path, node)) => {
+                self.open_file(ctx, path, content);
+                render_base_types::set_jump_target(ctx, node);
+            }
+            None => {}
+        }
+    }
+
+    /// Records the session and the window size once the window is really closing.
+    fn on_close(&mut self, ctx: &egui::Context) {
+        self.save_preferences();
+        session::close_document(self.document_state());
+        session::save_open_nodes(&self.file_path.lock().unwrap(), &render_base_types::open_nodes(ctx));
+        if let Some(rect) = ctx.input(|i| i.viewport().inner_rect) {
+            // Reload first so that settings changed in another window are not overwritten.
+            let mut preferences = Preferences::load();
+            preferences.window_size = [rect.width(), rect.height()];
+            preferences.save();
+        }
+    }
+
     fn render_editors(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, content: &mut String) {
         let total_height = ui.available_height();
         let total_width = ui.available_width();
+        let show_raw = self.show_raw_editor && !self.layout.raw_popped_out;
+        let show_tree = !self.layout.tree_popped_out;
 
+        if !show_raw || !show_tree {
+            let size = egui::Vec2::new(total_width, total_height);
+            if show_raw {
+                self.render_raw_editor(ui, ctx, content, size);
+            } else if show_tree {
+                self.render_collapsible_view(ui, content, size);
+            } else {
+                ui.label("Both panes are open in their own windows.");
+            }
+            return;
+        }
+
+        let stacked = self.layout.stacked;
+        let (layout, total) = if stacked {
+            (egui::Layout::top_down(egui::Align::Min), total_height - SPLITTER_SIZE)
+        } else {
+            (egui::Layout::left_to_right(egui::Align::Min), total_width - SPLITTER_SIZE)
+        };
+        let pane_size = |fraction: f32| {
+            if stacked {
+                egui::Vec2::new(total_width, total * fraction)
+            } else {
+                egui::Vec2::new(total * fraction, total_height)
+            }
+        };
+        let fraction = self.layout.split_fraction;
         ui.allocate_ui_with_layout(
             egui::Vec2::new(total_width, total_height),
-            egui::Layout::left_to_right(egui::Align::Min),
+            layout,
             |ui| {
-                if self.show_raw_editor {
-                    self.render_raw_editor(ui, ctx, content, total_width, total_height);
-                    ui.separator();
-                }
-                self.render_collapsible_view(ui, content, total_width, total_height);
+                ui.spacing_mut().item_spacing = egui::Vec2::ZERO;
+                self.render_raw_editor(ui, ctx, content, pane_size(fraction));
+                self.render_splitter(ui, total);
+                self.render_collapsible_view(ui, content, pane_size(1. - fraction));
             },
         );
     }
 
-    fn render_raw_editor(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, content: &mut String, width: f32, height: f32) {
+    /// The bar between the two panes: dragging it moves the split, double-clicking resets it.
+    fn render_splitter(&mut self, ui: &mut egui::Ui, total: f32) {
+        let stacked = self.layout.stacked;
+        let size = if stacked {
+            egui::Vec2::new(ui.available_width(), SPLITTER_SIZE)
+        } else {
+            egui::Vec2::new(SPLITTER_SIZE, ui.available_height())
+        };
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click_and_drag());
+        let response = response.on_hover_text("Drag to resize, double-click to reset");
+        let active = response.hovered() || response.dragged();
+        if active {
+            ui.ctx().set_cursor_icon(if stacked {
+                egui::CursorIcon::ResizeVertical
+            } else {
+                egui::CursorIcon::ResizeHorizontal
+            });
+        }
+        if response.dragged() && total > 0.0 {
+            let delta = if stacked { response.drag_delta().y } else { response.drag_delta().x };
+            self.layout.split_fraction = (self.layout.split_fraction + delta / total).clamp(MIN_SPLIT_FRACTION, 1. - MIN_SPLIT_FRACTION);
+        }
+        if response.double_clicked() {
+            self.layout.split_fraction = self.preferences.raw_editor_fraction;
+        }
+
+        let stroke = if active {
+            ui.visuals().widgets.active.fg_stroke
+        } else {
+            ui.visuals().widgets.noninteractive.bg_stroke
+        };
+        let center = rect.center();
+        if stacked {
+            ui.painter().hline(rect.x_range(), center.y, stroke);
+        } else {
+            ui.painter().vline(center.x, rect.y_range(), stroke);
+        }
+    }
+
+    /// Title of a pane with a button that moves it into its own window or back.
+    fn render_pane_header(&mut self, ui: &mut egui::Ui, title: String, pane: Pane) {
+        ui.horizontal(|ui| {
+            ui.label(title);
+            let popped_out = match pane {
+                Pane::Raw => &mut self.layout.raw_popped_out,
+                Pane::Tree => &mut self.layout.tree_popped_out,
+            };
+            let (icon, hover) = if *popped_out {
+                ("📌", "Put back into the main window")
+            } else {
+                ("🗗", "Open in its own window")
+            };
+            if ui.small_button(icon).on_hover_text(hover).clicked() {
+                *popped_out = !*popped_out;
+            }
+        });
+    }
+
+    /// Shows the panes that were moved out of the main window, each in its own viewport.
+    fn render_popped_out_panes(&mut self, ctx: &egui::Context, content: &mut String) {
+        for pane in [Pane::Raw, Pane::Tree] {
+            let popped_out = match pane {
+                Pane::Raw => self.layout.raw_popped_out && self.show_raw_editor,
+                Pane::Tree => self.layout.tree_popped_out,
+            };
+            if !popped_out {
+                continue;
+            }
+            let title = match pane {
+                Pane::Raw => "Raw editor",
+                Pane::Tree => "Tree view",
+            };
+            let builder = egui::ViewportBuilder::default()
+                .with_title(format!("{title} — {}", self.suggested_path()))
+                .with_inner_size([WINDOW_WIDTH, WINDOW_HEIGHT]);
+            ctx.show_viewport_immediate(egui::ViewportId::from_hash_of(title), builder, |ctx, class| {
+                let render = |ui: &mut egui::Ui| {
+                    let size = ui.available_size();
+                    match pane {
+                        Pane::Raw => self.render_raw_editor(ui, ctx, content, size),
+                        Pane::Tree => self.render_collapsible_view(ui, content, size),
+                    }
+                };
+                if class == egui::ViewportClass::Embedded {
+                    // Backends without multiple windows draw the pane as a floating window instead.
+                    egui::Window::new(title).default_size([WINDOW_WIDTH * 0.5, WINDOW_HEIGHT * 0.5]).show(ctx, render);
+                } else {
+                    egui::CentralPanel::default().show(ctx, render);
+                }
+                // Closing the pane's window puts it back into the main window.
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    match pane {
+                        Pane::Raw => self.layout.raw_popped_out = false,
+                        Pane::Tree => self.layout.tree_popped_out = false,
+                    }
+                }
+            });
+        }
+    }
+
+    fn render_raw_editor(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, content: &mut String, size: egui::Vec2) {
+        let width = size.x;
         ui.allocate_ui_with_layout(
-            egui::Vec2::new(width * RAW_EDITOR_WIDTH_FRACTION, height),
+            size,
             egui::Layout::top_down(egui::Align::Min),
             |ui| {
-                ui.label("📝 Raw YAML Editor:");
+                let title = match self.file_type {
+                    FileType::Unknown => "📝 Raw Editor:".to_string(),
+                    file_type => format!("📝 Raw {} Editor:", file_type.label()),
+                };
+                self.render_pane_header(ui, title, Pane::Raw);
                 ui.horizontal(|ui| {
                     ui.label("🔍 Search:");
                     let search_input = ui.text_edit_singleline(&mut self.search_query);
@@ -173,72 +1166,80 @@ impl YamlEditorApp {
 
                 let text_edit_id = ui.make_persistent_id("raw_editor_text");
                 let scroll_area_id = egui::Id::new("raw_editor_scroll");
+                self.raw_lines.update(ctx, self.text_version, content, self.baseline.version, &self.baseline.text);
 
-                // Clone content for search operations to avoid borrowing issues
-                let content_for_search = content.clone();
-
-                // Calculate scroll offset if search was triggered
-                let mut target_scroll_offset = None;
+                // Line of the first match if search was triggered
+                let mut target_line = None;
                 if self.search_triggered {
                     if let Some(search_text) = &self.scroll_marker_key {
-                        if let Some(pos) = content_for_search.to_lowercase().find(&search_text.to_lowercase()) {
-                            let font_id = egui::TextStyle::Monospace.resolve(&ctx.style());
-                            let row_height = ctx.fonts(|f| f.row_height(&font_id));
-                            let preceding_text = &content_for_search[..pos];
-                            let line_number = preceding_text.chars().filter(|&c| c == '\n').count();
-                            let target_y = line_number as f32 * row_height;
-                            // Set target scroll offset to center the line
-                            target_scroll_offset = Some(target_y - height * 0.0);
+                        if let Some(pos) = content.to_lowercase().find(&search_text.to_lowercase()) {
+                            target_line = Some(self.raw_lines.line_of(pos));
                         }
                     }
                     self.search_triggered = false;
                 }
 
+                if self.raw_lines.is_large(content) {
+                    ui.label(
+                        egui::RichText::new(format!("Large file ({} lines): click a line to edit it", self.raw_lines.line_count()))
+                            .weak(),
+                    );
+                    if raw_view::render_lines(ui, content, &self.raw_lines, target_line, self.is_read_only()) {
+                        self.save(content);
+                        ctx.request_repaint();
+                    }
+                    return;
+                }
+
                 // Create scroll area with potential offset
                 let mut scroll_area = egui::ScrollArea::vertical()
                     .id_salt(scroll_area_id)
                     .auto_shrink([false; 2]);
 
                 // Apply scroll offset if we have a target
-                if let Some(offset) = target_scroll_offset {
-                    scroll_area = scroll_area.vertical_scroll_offset(offset.max(0.0));
+                if let Some(line) = target_line {
+                    let font_id = egui::TextStyle::Monospace.resolve(&ctx.style());
+                    let row_height = ctx.fonts(|f| f.row_height(&font_id));
+                    scroll_area = scroll_area.vertical_scroll_offset(line as f32 * row_height);
                 }
 
                 // Wrap both line number TextEdit and main editor inside the same ScrollArea for synchronized scrolling
                 scroll_area.show(ui, |ui| {
                     ui.horizontal(|ui| {
-                        let mut numbers = content
-                            .chars()
-                            .fold((1, String::new()), |(line, mut acc), c| {
-                                if c == '\n' {
-                                    acc.push_str(&format!("{:>4}\n", line));
-                                    (line + 1, acc)
-                                } else {
-                                    (line, acc)
-                                }
-                            }).1;
-
+                        let gutter = self.raw_lines.gutter_job(ui);
                         ui.add(
-                            egui::TextEdit::multiline(&mut numbers)
-                                .desired_width(40.0)
+                            egui::TextEdit::multiline(&mut self.raw_lines.gutter())
+                                .desired_width(raw_view::GUTTER_WIDTH)
                                 .font(egui::TextStyle::Monospace)
                                 .interactive(false)
                                 .frame(false)
+                                .layouter(&mut |ui, _, _| ui.fonts(|f| f.layout_job(gutter.clone())))
                         );
 
+                        if self.is_read_only() {
+                            // A `&str` buffer can be selected and copied from but not edited.
+                            ui.add(
+                                egui::TextEdit::multiline(&mut content.as_str())
+                                    .id(text_edit_id)
+                                    .font(egui::TextStyle::Monospace)
+                                    .desired_width(width - raw_view::GUTTER_WIDTH - 20.0),
+                            );
+                            return;
+                        }
+
                         let editor = egui::TextEdit::multiline(content)
                             .id(text_edit_id)
                             .font(egui::TextStyle::Monospace)
-                            .desired_width(width * RAW_EDITOR_WIDTH_FRACTION - 60.0);
+                            .desired_width(width - raw_view::GUTTER_WIDTH - 20.0);
 
                         let response = ui.add(editor);
 
                         if response.changed() {
-                            let _ = std::fs::write(&*self.file_path.lock().unwrap(), content);
+                            self.save(content);
                             ctx.request_repaint();
                         }
 
-                        if target_scroll_offset.is_some() {
+                        if target_line.is_some() {
                             ctx.memory_mut(|mem| {
                                 mem.request_focus(text_edit_id);
                             });
@@ -250,127 +1251,256 @@ impl YamlEditorApp {
     }
 
 
-    fn render_collapsible_view(&mut self, ui: &mut egui::Ui, content: &mut String, width: f32, height: f32) {
+    /// Expand all, collapse all and expand to a chosen depth.
+    fn render_tree_commands(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let ctx = ui.ctx().clone();
+            if ui.small_button("⊞ Expand all").clicked() {
+                render_base_types::set_expansion(&ctx, render_base_types::Expansion::All);
+            }
+            if ui.small_button("⊟ Collapse all").clicked() {
+                render_base_types::set_expansion(&ctx, render_base_types::Expansion::None);
+            }
+            if ui.small_button("Expand to depth").clicked() {
+                render_base_types::set_expansion(&ctx, render_base_types::Expansion::Depth(self.expand_depth));
+            }
+            ui.add(egui::DragValue::new(&mut self.expand_depth).range(1..=32));
+            ui.separator();
+            if ui
+                .selectable_label(self.stage_edits, "📥 Stage edits")
+                .on_hover_text("Collect tree edits for review and write them to the file in one go")
+                .clicked()
+            {
+                self.stage_edits = !self.stage_edits;
+            }
+        });
+    }
+
+    /// The staged tree edits as a list of changes, with buttons to apply or discard them.
+    fn render_staged_edits(&mut self, ui: &mut egui::Ui, content: &mut String) {
+        let read_only = self.is_read_only();
+        let outdated = self.staged.as_ref().is_some_and(|staged| staged.base_version != self.text_version);
+        let (Some(staged), Some(base)) = (&mut self.staged, &self.parsed) else {
+            return;
+        };
+        let changes = staged.changes(base);
+        let (mut apply, mut discard, mut clicked) = (false, false, None);
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("📥 {} staged change(s)", changes.len())).strong());
+                apply = ui.add_enabled(!read_only, egui::Button::new("✔ Apply")).on_hover_text("Write them to the file").clicked();
+                discard = ui.button("✖ Discard").clicked();
+            });
+            if outdated {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    "⚠️ The file changed since these edits were staged; applying them replaces that change.",
+                );
+            }
+            egui::ScrollArea::vertical().id_salt("staged_edits_scroll").max_height(150.0).show(ui, |ui| {
+                for change in changes {
+                    let (old, new) = (change.old.as_deref().unwrap_or("(none)"), change.new.as_deref().unwrap_or("(removed)"));
+                    let text = egui::RichText::new(format!("{}: {old} → {new}", change.path.join("."))).monospace();
+                    if ui.selectable_label(false, text).on_hover_text("Show in the tree").clicked() {
+                        clicked = Some(change.path.clone());
+                    }
+                }
+            });
+        });
+        if let Some(path) = clicked {
+            render_base_types::set_jump_target(ui.ctx(), path);
+        }
+        if apply {
+            self.apply_staged(content);
+        } else if discard {
+            self.staged = None;
+        }
+    }
+
+    /// Gives the changed values at and below the tree node at `path` their baseline values.
+    fn revert(&mut self, path: &[String], content: &mut String) {
+        if self.is_read_only() {
+            return;
+        }
+        if let Some(staged) = &mut self.staged {
+            match self.baseline.revert(&mut staged.document, path) {
+                Ok(()) => staged.edited(),
+                Err(err) => diagnostics::error(format!("cannot revert {}: {err}", path.join("."))),
+            }
+            return;
+        }
+        let Some(Ok(document)) = self
+            .parsed
+            .as_mut()
+            .filter(|parsed| parsed.is_current(self.text_version, self.file_type))
+            .map(|parsed| parsed.result.as_mut())
+        else {
+            return;
+        };
+        match self.baseline.revert(document, path).and_then(|()| document.serialize()) {
+            Ok(updated) => {
+                *content = updated;
+                self.save(content);
+            }
+            Err(err) => diagnostics::error(format!("cannot revert {}: {err}", path.join("."))),
+        }
+        // Parse what was written, or drop a half-done revert from the tree.
+        self.parsed = Some(ParseSnapshot::parse(self.text_version, content, self.file_type));
+        self.parser.mark_parsed(self.text_version, self.file_type);
+    }
+
+    /// Writes the staged edits to the file in one go.
+    fn apply_staged(&mut self, content: &mut String) {
+        let Some(mut staged) = self.staged.take() else {
+            return;
+        };
+        match staged.document.serialize() {
+            Ok(updated) => {
+                *content = updated;
+                self.save(content);
+                self.parsed = Some(ParseSnapshot::parse(self.text_version, content, self.file_type));
+                self.parser.mark_parsed(self.text_version, self.file_type);
+            }
+            Err(err) => {
+                diagnostics::error(format!("the staged edits cannot be written as {}: {err}", staged.file_type.label()));
+                self.staged = Some(staged);
+            }
+        }
+    }
+
+    fn render_collapsible_view(&mut self, ui: &mut egui::Ui, content: &mut String, size: egui::Vec2) {
         ui.allocate_ui_with_layout(
-            egui::Vec2::new(width * if self.show_raw_editor { 1. - RAW_EDITOR_WIDTH_FRACTION } else { 1.0 }, height),
+            size,
             egui::Layout::top_down(egui::Align::Min),
             |ui| {
                 let label = match self.file_type {
-                    FileType::Yaml => "📂 Collapsible YAML View:",
-                    FileType::Json => "📂 Collapsible JSON View:",
-                    FileType::Toml => "📂 Collapsible TOML View:",
-                    FileType::Unknown => "📂 Collapsible View:",
+                    FileType::Unknown => "📂 Collapsible View:".to_string(),
+                    file_type => format!("📂 Collapsible {} View:", file_type.label()),
                 };
-                ui.label(label);
+                self.render_pane_header(ui, label, Pane::Tree);
+                self.render_tree_commands(ui);
+                self.render_staged_edits(ui, content);
                 egui::ScrollArea::vertical()
-                    .id_salt("collapsible_yaml_scroll")
+                    .id_salt(("collapsible_yaml_scroll", self.tree_generation))
                     .auto_shrink([false; 2])
                     .show(ui, |ui| {
                         ui.vertical(|ui| {
-                            match self.file_type {
-                                FileType::Yaml => {
-                                    match YamlLoader::load_from_str(&*content) {
-                                        Ok(docs) => {
-                                            if let Some(mut parsed) = docs.into_iter().next() {
-                                                let mut modified = false;
-                                                render_yaml::render_yaml_value_with_tracking(
-                                                    ui,
-                                                    &mut parsed,
-                                                    &mut modified,
-                                                    &mut self.scroll_marker_key,
-                                                    &content,
-                                                    vec![]
-                                                );
-                                                ui.add_space(20.0);
-
-                                                if modified {
-                                                    let mut out_str = String::new();
-                                                    {
-                                                        let mut emitter = YamlEmitter::new(&mut out_str);
-                                                        let _ = emitter.dump(&parsed);
-                                                    }
-                                                    // Remove the '---\n' prefix if present
-                                                    if out_str.starts_with("---\n") {
-                                                        out_str = out_str.replacen("---\n", "", 1);
-                                                    }
-                                                    *content = out_str;
-                                                    let _ = fs::write(&*self.file_path.lock().unwrap(), &*content);
-                                                }
+                            if self.file_type == FileType::Unknown {
+                                ui.colored_label(egui::Color32::RED, "⚠️ Unknown file type. Pick a format from the toolbar.");
+                                return;
+                            }
+                            let Some(mut snapshot) = self.parsed.take() else {
+                                ui.horizontal(|ui| {
+                                    ui.spinner();
+                                    ui.label("Parsing…");
+                                });
+                                return;
+                            };
+                            let mut staged = self.staged.take();
+                            if staged.as_ref().is_some_and(|staged| staged.file_type != self.file_type) {
+                                diagnostics::warning("the staged edits were dropped because the format was changed");
+                                staged = None;
+                            }
+                            // Until the latest text is parsed, the previous tree is shown but cannot be edited.
+                            // Staged edits have a tree of their own, which stays editable.
+                            let stale = staged.is_none() && !snapshot.is_current(self.text_version, self.file_type);
+                            if stale {
+                                ui.horizontal(|ui| {
+                                    ui.spinner();
+                                    ui.label("Parsing…");
+                                });
+                            }
+                            let staging = staged.is_some();
+                            let shown = match &mut staged {
+                                Some(staged) => Ok(&mut staged.document),
+                                None => snapshot.result.as_mut(),
+                            };
+                            match shown {
+                                Ok(parsed) => {
+                                    let mut modified = false;
+                                    ui.add_enabled_ui(!stale, |ui| match parsed {
+                                        ParsedDocument::Yaml(doc) => render_yaml::render_yaml_value_with_tracking(
+                                            ui,
+                                            &mut doc.root,
+                                            &mut doc.meta,
+                                            &mut modified,
+                                            &mut self.scroll_marker_key,
+                                            content,
+                                            vec![]
+                                        ),
+                                        ParsedDocument::Json(value) => render_json::render_json_value_with_tracking(
+                                            ui,
+                                            value,
+                                            &mut modified,
+                                            &mut self.scroll_marker_key,
+                                            content,
+                                            vec![]
+                                        ),
+                                        ParsedDocument::Toml(value) => render_toml::render_toml_value_with_tracking(
+                                            ui,
+                                            value,
+                                            &mut modified,
+                                            &mut self.scroll_marker_key,
+                                            content,
+                                            vec![]
+                                        ),
+                                        ParsedDocument::Json5(doc) => render_json::render_json_value_with_tracking(
+                                            ui,
+                                            &mut doc.root,
+                                            &mut modified,
+                                            &mut self.scroll_marker_key,
+                                            content,
+                                            vec![]
+                                        ),
+                                        ParsedDocument::Ini(doc) => render_ini::render_ini_document(
+                                            ui,
+                                            doc,
+                                            &mut modified,
+                                            &mut self.scroll_marker_key,
+                                        ),
+                                    });
+                                    ui.add_space(20.0);
+
+                                    if modified && !stale && !self.is_read_only() {
+                                        if staging {
+                                            if let Some(staged) = &mut staged {
+                                                staged.edited();
                                             }
-                                        }
-                                        Err(err) => {
-                                            ui.colored_label(
-                                                egui::Color32::RED,
-                                                format!("⚠️ Invalid YAML: {err}"),
-                                            );
-                                        }
-                                    }
-                                }
-                                FileType::Json => {
-                                    match serde_json::from_str::<serde_json::Value>(&*content) {
-                                        Ok(mut parsed) => {
-                                            let mut modified = false;
-                                            render_json::render_json_value_with_tracking(
-                                                ui,
-                                                &mut parsed,
-                                                &mut modified,
-                                                &mut self.scroll_marker_key,
-                                                &content,
-                                                vec![]
-                                            );
-                                            ui.add_space(20.0);
-
-                                            if modified {
-                                                if let Ok(updated) = serde_json::to_string_pretty(&parsed) {
-                                                    *content = updated;
-                                                    let _ = fs::write(&*self.file_path.lock().unwrap(), &*content);
-                                                }
+                                        } else if self.stage_edits {
+                                            // The edit was made to the file's own tree: keep that as the staged
+                                            // copy and read the file's tree again.
+                                            let fresh = ParseSnapshot::parse(self.text_version, content, self.file_type);
+                                            if let Ok(document) = std::mem::replace(&mut snapshot, fresh).result {
+                                                staged = Some(StagedEdits::new(document, self.file_type, self.text_version));
                                             }
-                                        }
-                                        Err(err) => {
-                                            ui.colored_label(
-                                                egui::Color32::RED,
-                                                format!("⚠️ Invalid JSON: {err}"),
-                                            );
-                                        }
-                                    }
-                                }
-                                FileType::Toml => {
-                                    match content.parse::<toml::Value>() {
-                                        Ok(mut parsed) => {
-                                            let mut modified = false;
-                                            render_toml::render_toml_value_with_tracking(
-                                                ui,
-                                                &mut parsed,
-                                                &mut modified,
-                                                &mut self.scroll_marker_key,
-                                                &content,
-                                                vec![]
-                                            );
-                                            ui.add_space(20.0);
-
-                                            if modified {
-                                                if let Ok(updated) = toml::to_string_pretty(&parsed) {
+                                        } else {
+                                            match parsed.serialize() {
+                                                Ok(updated) => {
                                                     *content = updated;
-                                                    let _ = fs::write(&*self.file_path.lock().unwrap(), &*content);
+                                                    self.save_soon(ui.ctx(), content);
+                                                    // Parsing after every step of a drag would stall the UI; the
+                                                    // edited tree is shown until the worker has read the new text.
+                                                    snapshot.follow_edit(self.text_version);
+                                                    self.parser.request(self.text_version, content, self.file_type);
+                                                }
+                                                Err(err) => {
+                                                    diagnostics::error(format!("the edit cannot be written as {}: {err}", self.file_type.label()));
+                                                    // Drop the edit from the tree too, so it does not look applied.
+                                                    snapshot = ParseSnapshot::parse(self.text_version, content, self.file_type);
                                                 }
                                             }
                                         }
-                                        Err(err) => {
-                                            ui.colored_label(
-                                                egui::Color32::RED,
-                                                format!("⚠️ Invalid TOML: {err}"),
-                                            );
-                                        }
                                     }
                                 }
-                                FileType::Unknown => {
+                                Err(err) => {
                                     ui.colored_label(
                                         egui::Color32::RED,
-                                        "⚠️ Unknown file type.",
+                                        format!("⚠️ Invalid {}: {err}", self.file_type.label()),
                                     );
                                 }
                             }
+                            self.parsed = Some(snapshot);
+                            self.staged = staged;
                         });
                     });
             },
@@ -380,24 +1510,117 @@ impl YamlEditorApp {
 
 impl App for YamlEditorApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
-        ctx.set_visuals(if self.dark_mode {
-            egui::Visuals::dark()
-        } else {
-            egui::Visuals::light()
-        });
+        self.preferences.apply(ctx);
 
-        let mut content_owned = self.content.lock().unwrap().clone();
+        // Pick up changes made by the file watcher; the text is only copied when it changed.
+        {
+            let shared = self.content.lock().unwrap();
+            if shared.version != self.text_version && shared.text == self.text {
+                // E.g. the watcher reading back what this window wrote.
+                self.text_version = shared.version;
+            } else if shared.version != self.text_version && (self.unsaved || self.save_error.is_some() || self.save_when_unlocked) {
+                // Never drop edits that are not on disk, including ones whose save failed;
+                // keep them and ask what to do.
+                self.disk_conflict = true;
+            } else if shared.version != self.text_version {
+                // Only the watcher publishes versions this window did not make; remember what
+                // the document was so the reload can be diffed against it.
+                let previous = self
+                    .parsed
+                    .as_ref()
+                    .filter(|parsed| parsed.is_current(self.text_version, self.file_type))
+                    .and_then(|parsed| parsed.result.as_ref().ok())
+                    .map(convert::to_json);
+                match (&mut self.external_base, previous) {
+                    (Some((version, _)), _) => *version = shared.version,
+                    (base, Some(previous)) => *base = Some((shared.version, previous)),
+                    (_, None) => {}
+                }
+                self.text = shared.text.clone();
+                self.text_version = shared.version;
+                if self.preferences.save_mode == SaveMode::Manual {
+                    self.baseline = Baseline::new(shared.version, shared.text.clone());
+                }
+                // Reopen what was open, even where the reload shifted the tree's rows around.
+                render_base_types::restore_open_nodes(ctx, render_base_types::open_nodes(ctx));
+                drop(shared);
+                // Its permissions may have changed along with the content.
+                self.check_write_protection();
+            }
+        }
+        render_base_types::set_read_only(ctx, self.is_read_only());
+        let mut content_owned = std::mem::take(&mut self.text);
+
+        if ctx.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::S)) {
+            self.save_now(&content_owned);
+        }
+
+        // Keep sniffing an unrecognised file until its content gives the format away.
+        if self.file_type == FileType::Unknown && !self.file_type_forced && self.sniffed_version != self.text_version {
+            self.file_type = file_type_for(&self.file_path.lock().unwrap(), &content_owned);
+            self.sniffed_version = self.text_version;
+        }
+
+        if let Some(finished) = self.parser.take_finished() {
+            // A parse done on the UI thread may be newer than the worker's, but a tree edited in
+            // place gives way once its own text is parsed.
+            let newer = finished.is_current(self.text_version, self.file_type);
+            if !self.parsed.as_ref().is_some_and(|parsed| {
+                parsed.is_current(self.text_version, self.file_type) && !(parsed.provisional && newer)
+            }) {
+                self.parsed = Some(finished);
+            }
+        }
+        if self.file_type != FileType::Unknown && !self.parsed.as_ref().is_some_and(|parsed| parsed.is_current(self.text_version, self.file_type)) {
+            self.parser.request(self.text_version, &content_owned, self.file_type);
+        }
+        self.record_external_changes(ctx);
+        if let Some(parsed) = self.parsed.as_ref().filter(|parsed| parsed.is_current(self.text_version, self.file_type)) {
+            if self.baseline.compare(parsed) {
+                render_base_types::mark_modified(ctx, self.baseline.changes().iter().map(|change| &change.path));
+            }
+        }
+        if let Some(path) = render_base_types::take_revert_request(ctx) {
+            self.revert(&path, &mut content_owned);
+        }
+        if self.save_due.is_some_and(|due| Instant::now() >= due) {
+            self.flush_save(&content_owned);
+        }
+        self.check_file_lock(ctx, &content_owned);
 
+        self.render_status_bar(ctx, &content_owned);
+        self.render_folder_browser(ctx, &mut content_owned);
         egui::CentralPanel::default().show(ctx, |ui| {
-            self.render_toolbar(ui);
+            self.render_toolbar(ui, &content_owned);
             ui.separator();
-            self.render_editors(ui, ctx, &mut content_owned);
+            self.render_missing_file_banner(ui, ctx, &mut content_owned);
+            self.render_disk_conflict_banner(ui, ctx, &mut content_owned);
+            if self.load_error.is_some() {
+                self.render_load_error(ui, &mut content_owned);
+            } else {
+                self.render_editors(ui, ctx, &mut content_owned);
+            }
         });
+        if self.load_error.is_none() {
+            self.render_popped_out_panes(ctx, &mut content_owned);
+        }
+        self.render_export_report(ctx);
+        self.render_change_log_window(ctx);
+        self.render_diagnostics_window(ctx);
+        self.render_preferences_window(ctx, &content_owned);
+        self.render_unsaved_confirmation(ctx, &mut content_owned);
 
-        let mut content_guard = self.content.lock().unwrap();
-        if *content_guard != content_owned {
-            *content_guard = content_owned;
+        if ctx.input(|i| i.viewport().close_requested()) {
+            self.flush_save(&content_owned);
+            if self.has_pending_edits() && !self.close_confirmed {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                self.pending_action = Some(PendingAction::Close);
+            } else {
+                self.on_close(ctx);
+            }
         }
+
+        self.text = content_owned;
     }
 }
 
@@ -405,80 +1628,131 @@ impl App for YamlEditorApp {
 #[tokio::main]
 async fn main() -> Result<(), eframe::Error> {
     let args: Vec<String> = std::env::args().collect();
-    let file_path = if args.len() > 1 {
-        args[1].clone()
+    if let Some(code) = cli::run(&args[1..]) {
+        std::process::exit(code);
+    }
+    let untitled = args.get(1).is_some_and(|arg| arg == STDIN_ARG || arg == NEW_ARG);
+    let previous_session = session::load_session();
+    let preferences = Preferences::load();
+    file_lock::set_mode(preferences.file_locking);
+    let file_path = if untitled {
+        String::new()
+    } else if args.len() > 1 {
+        session::absolute_path(&args[1])
     } else {
-        load_saved_file_path().or_else(|| {
+        // Reopen every document of the last session: the first here, the others in their own windows.
+        let mut restored = session::documents_to_restore(&previous_session).into_iter().map(|document| document.path);
+        let first = restored.next();
+        for path in restored {
+            spawn_instance(&path);
+        }
+        first.or_else(|| session::recent_files().into_iter().next()).or_else(|| {
             FileDialog::new()
-                .add_filter("YAML", &["yaml", "yml"])
+                .add_filter("Settings files", &supported_extensions())
                 .pick_file()
                 .and_then(|p| p.into_os_string().into_string().ok())
         }).unwrap_or_else(|| std::process::exit(0))
     };
 
-    save_file_path(&file_path);
+    let loaded = match args.get(1).map(String::as_str) {
+        Some(STDIN_ARG) => load_stdin(),
+        Some(NEW_ARG) => Ok(String::new()),
+        _ => load_file(&file_path),
+    };
+    if !untitled {
+        session::add_recent_file(&file_path);
+    }
 
-    let (file_path, content) = init_file_state(&file_path);
+    let (file_path, content) = init_file_state(&file_path, loaded.clone().unwrap_or_default());
     let (tx, rx) = mpsc::channel(100);
 
-    init_file_watcher(tx.clone(), &file_path);
-    spawn_file_watcher(rx, file_path.clone(), content.clone());
+    let file_watcher = init_file_watcher(tx.clone(), &file_path.lock().unwrap());
 
     eframe::run_native(
         "Barnaby's YAML Editor",
         eframe::NativeOptions {
-            viewport: egui::ViewportBuilder::default().with_inner_size([WINDOW_WIDTH, WINDOW_HEIGHT]),
+            viewport: egui::ViewportBuilder::default().with_inner_size(preferences.window_size),
             ..Default::default()
         },
-        Box::new(|_cc| {
-            Ok(Box::new(YamlEditorApp::new(file_path, content)) as Box<dyn App>)
+        Box::new(move |cc| {
+            let mut app = YamlEditorApp::new(&cc.egui_ctx, file_path.clone(), content.clone(), preferences, tx);
+            spawn_file_watcher(rx, file_path, content, app.file_missing.clone(), cc.egui_ctx.clone());
+            app.load_error = loaded.err();
+            if let Some(err) = &app.load_error {
+                diagnostics::error(err.clone());
+            }
+            app.file_watcher = file_watcher;
+            app.check_write_protection();
+            if args.get(1).is_some_and(|arg| arg == STDIN_ARG) {
+                app.buffer_name = "stdin".to_string();
+            }
+            let path = app.file_path.lock().unwrap().clone();
+            render_base_types::restore_open_nodes(&cc.egui_ctx, session::load_open_nodes(&path));
+            if let Some(state) = previous_session.documents.into_iter().find(|d| !path.is_empty() && d.path == path) {
+                app.restore_document_state(&cc.egui_ctx, state);
+            }
+            session::register_document(app.document_state());
+            Ok(Box::new(app) as Box<dyn App>)
         })
     )
 }
 
 
-fn init_file_state(path: &str) -> (Arc<Mutex<String>>, Arc<Mutex<String>>) {
+/// Starts another editor window with `arg` as its command line.
+fn spawn_instance(arg: &str) {
+    let mut cmd = std::process::Command::new(std::env::current_exe().unwrap());
+    cmd.arg(arg);
+
+    #[cfg(unix)] {
+        use std::os::unix::process::CommandExt;
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid(); // new session
+            Ok(())
+        });
+    }}
+
+    #[cfg(windows)] {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_CONSOLE: u32 = 0x00000010;
+        cmd.creation_flags(CREATE_NEW_CONSOLE);
+    }
+
+    // The new instance runs in its own session and outlives this window
+    #[allow(clippy::zombie_processes)]
+    cmd.spawn().expect("Failed to launch new instance");
+}
+
+fn init_file_state(path: &str, content: String) -> (Arc<Mutex<String>>, Arc<Mutex<VersionedText>>) {
     let file_path = Arc::new(Mutex::new(path.to_string()));
-    let content = Arc::new(Mutex::new(load_file(path)));
+    let content = Arc::new(Mutex::new(VersionedText::new(content)));
     (file_path, content)
 }
 
-fn load_file(path: &str) -> String {
-    fs::read_to_string(path).unwrap_or_else(|_| String::new())
+/// Whether this process may write to `path`. On Unix this also catches read-only mounts,
+/// which the permission bits do not show.
+fn is_writable(path: &str) -> bool {
+    #[cfg(unix)]
+    {
+        let Ok(c_path) = std::ffi::CString::new(path) else {
+            return false;
+        };
+        unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        fs::metadata(path).is_ok_and(|metadata| !metadata.permissions().readonly())
+    }
 }
 
-fn init_file_watcher(tx: mpsc::Sender<Event>, file_path: &Arc<Mutex<String>>) {
-    let path = file_path.lock().unwrap().clone();
-    std::thread::spawn(move || {
-        let (notify_tx, notify_rx) = std::sync::mpsc::channel();
-        let mut watcher = RecommendedWatcher::new(
-            move |res: Result<Event, _>| {
-                let _ = notify_tx.send(res);
-            },
-            Config::default(),
-        ).unwrap();
-        watcher.watch(path.as_ref(), RecursiveMode::NonRecursive).unwrap();
-        for res in notify_rx {
-            if let Ok(event) = res {
-                let _ = tx.blocking_send(event);
-            }
-        }
-    });
+fn load_file(path: &str) -> Result<String, String> {
+    let bytes = file_lock::read(path, file_lock::UI_WAIT).map_err(|err| format!("cannot read {path}: {err}"))?;
+    String::from_utf8(bytes).map_err(|_| format!("{path} is not UTF-8 text"))
 }
 
-fn spawn_file_watcher(mut rx: mpsc::Receiver<Event>, file_path: Arc<Mutex<String>>, content: Arc<Mutex<String>>) {
-    tokio::spawn(async move {
-        while let Some(_) = rx.recv().await {
-            if let Ok(new_content) = fs::read_to_string(&*file_path.lock().unwrap()) {
-                let mut lock = content.lock().unwrap();
-                if *lock != new_content {
-                    *lock = new_content;
-                }
-            }
-        }
-    });
+fn load_stdin() -> Result<String, String> {
+    let mut bytes = vec![];
+    std::io::stdin().read_to_end(&mut bytes).map_err(|err| format!("cannot read stdin: {err}"))?;
+    String::from_utf8(bytes).map_err(|_| "stdin is not UTF-8 text".to_string())
 }
 
-
-
-
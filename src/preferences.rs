@@ -0,0 +1,260 @@
+// User preferences, stored as TOML in the config directory and edited from the settings
+// dialog. Every window reads them at launch; changes apply to the window they are made in.
+
+use std::fs;
+
+use eframe::egui;
+use toml::Value;
+
+use crate::diagnostics;
+use crate::file_lock;
+use crate::render_base_types;
+use crate::session;
+use crate::{INDENT_SPACES, RAW_EDITOR_WIDTH_FRACTION, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+const PREFERENCES_FILE_NAME: &str = "preferences.toml";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum SaveMode {
+    /// Every edit is written to disk straight away.
+    Auto,
+    /// Edits stay in memory until "Save" or Ctrl+S.
+    Manual,
+}
+
+impl SaveMode {
+    fn name(self) -> &'static str {
+        match self {
+            SaveMode::Auto => "auto",
+            SaveMode::Manual => "manual",
+        }
+    }
+}
+
+/// How reads and writes of the open file are coordinated with other programs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum LockMode {
+    Off,
+    /// `flock` on the file itself.
+    Flock,
+    /// A `<file>.lock` file next to it.
+    LockFile,
+}
+
+impl LockMode {
+    fn name(self) -> &'static str {
+        match self {
+            LockMode::Off => "off",
+            LockMode::Flock => "flock",
+            LockMode::LockFile => "lock_file",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Preferences {
+    pub(crate) dark_mode: bool,
+    pub(crate) font_size: f32,
+    pub(crate) monospace_size: f32,
+    /// Space in front of each tree row.
+    pub(crate) indent: f32,
+    /// Tree levels expanded when a document is opened; 0 keeps everything collapsed.
+    pub(crate) expand_depth: usize,
+    pub(crate) save_mode: SaveMode,
+    pub(crate) file_locking: LockMode,
+    /// Whether new windows show the raw editor.
+    pub(crate) show_raw_editor: bool,
+    /// Share of the window given to the raw editor in new windows and after a reset.
+    pub(crate) raw_editor_fraction: f32,
+    /// Size of the last window that was closed, used for the next one.
+    pub(crate) window_size: [f32; 2],
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            dark_mode: true,
+            font_size: 14.0,
+            monospace_size: 14.0,
+            indent: INDENT_SPACES,
+            expand_depth: 0,
+            save_mode: SaveMode::Auto,
+            file_locking: LockMode::Off,
+            show_raw_editor: false,
+            raw_editor_fraction: RAW_EDITOR_WIDTH_FRACTION,
+            window_size: [WINDOW_WIDTH, WINDOW_HEIGHT],
+        }
+    }
+}
+
+impl Preferences {
+    /// Reads the preferences file; missing or malformed settings keep their defaults.
+    pub(crate) fn load() -> Self {
+        let mut preferences = Self::default();
+        let Some(table) = session::config_path(PREFERENCES_FILE_NAME)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| {
+                text.parse::<toml::Table>()
+                    .map_err(|err| diagnostics::warning(format!("ignoring malformed {PREFERENCES_FILE_NAME}: {err}")))
+                    .ok()
+            })
+        else {
+            return preferences;
+        };
+        let float = |key: &str| table.get(key).and_then(|v| v.as_float().or_else(|| v.as_integer().map(|i| i as f64))).map(|f| f as f32);
+        if let Some(dark_mode) = table.get("dark_mode").and_then(Value::as_bool) {
+            preferences.dark_mode = dark_mode;
+        }
+        if let Some(size) = float("font_size") {
+            preferences.font_size = size.clamp(8.0, 40.0);
+        }
+        if let Some(size) = float("monospace_size") {
+            preferences.monospace_size = size.clamp(8.0, 40.0);
+        }
+        if let Some(indent) = float("indent") {
+            preferences.indent = indent.clamp(0.0, 100.0);
+        }
+        if let Some(depth) = table.get("expand_depth").and_then(Value::as_integer) {
+            preferences.expand_depth = depth.max(0) as usize;
+        }
+        match table.get("save_mode").and_then(Value::as_str) {
+            Some("auto") => preferences.save_mode = SaveMode::Auto,
+            Some("manual") => preferences.save_mode = SaveMode::Manual,
+            _ => {}
+        }
+        match table.get("file_locking").and_then(Value::as_str) {
+            Some("off") => preferences.file_locking = LockMode::Off,
+            Some("flock") => preferences.file_locking = LockMode::Flock,
+            Some("lock_file") => preferences.file_locking = LockMode::LockFile,
+            _ => {}
+        }
+        if let Some(show) = table.get("show_raw_editor").and_then(Value::as_bool) {
+            preferences.show_raw_editor = show;
+        }
+        if let Some(fraction) = float("raw_editor_fraction") {
+            preferences.raw_editor_fraction = fraction.clamp(0.1, 0.9);
+        }
+        if let (Some(width), Some(height)) = (float("window_width"), float("window_height")) {
+            preferences.window_size = [width.max(200.0), height.max(200.0)];
+        }
+        preferences
+    }
+
+    pub(crate) fn save(&self) {
+        let mut table = toml::Table::new();
+        table.insert("dark_mode".into(), Value::Boolean(self.dark_mode));
+        table.insert("font_size".into(), Value::Float(self.font_size.into()));
+        table.insert("monospace_size".into(), Value::Float(self.monospace_size.into()));
+        table.insert("indent".into(), Value::Float(self.indent.into()));
+        table.insert("expand_depth".into(), Value::Integer(self.expand_depth as i64));
+        table.insert("save_mode".into(), Value::String(self.save_mode.name().into()));
+        table.insert("file_locking".into(), Value::String(self.file_locking.name().into()));
+        table.insert("show_raw_editor".into(), Value::Boolean(self.show_raw_editor));
+        table.insert("raw_editor_fraction".into(), Value::Float(self.raw_editor_fraction.into()));
+        table.insert("window_width".into(), Value::Float(self.window_size[0].round().into()));
+        table.insert("window_height".into(), Value::Float(self.window_size[1].round().into()));
+        match toml::to_string(&table) {
+            Ok(text) => session::write_config(PREFERENCES_FILE_NAME, &text),
+            Err(err) => diagnostics::error(format!("cannot save the preferences: {err}")),
+        }
+    }
+
+    /// Applies the theme, font sizes and tree layout to `ctx`.
+    pub(crate) fn apply(&self, ctx: &egui::Context) {
+        ctx.set_visuals(if self.dark_mode {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        });
+        ctx.style_mut(|style| {
+            for (text_style, font) in style.text_styles.iter_mut() {
+                font.size = match text_style {
+                    egui::TextStyle::Heading => self.font_size * 1.4,
+                    egui::TextStyle::Small => self.font_size * 0.7,
+                    egui::TextStyle::Monospace => self.monospace_size,
+                    _ => self.font_size,
+                };
+            }
+        });
+        render_base_types::set_tree_layout(ctx, self.indent, self.expand_depth);
+        file_lock::set_mode(self.file_locking);
+    }
+}
+
+/// What the settings dialog did this frame.
+pub(crate) struct PreferencesEdit {
+    /// A setting changed; it applies right away.
+    pub(crate) changed: bool,
+    /// The edit is complete, e.g. a slider was let go, so the settings can be written.
+    pub(crate) finished: bool,
+}
+
+/// Whether an edit through `response` is complete: clicks are, drags once let go and typed
+/// numbers once the field is left.
+fn settled(response: &egui::Response) -> bool {
+    response.drag_stopped() || response.lost_focus() || (response.changed() && !response.dragged() && !response.has_focus())
+}
+
+/// The controls of the settings dialog.
+pub(crate) fn render_preferences(ui: &mut egui::Ui, preferences: &mut Preferences) -> PreferencesEdit {
+    let before = preferences.clone();
+    let mut finished = false;
+    egui::Grid::new("preferences_grid").num_columns(2).spacing([12.0, 6.0]).show(ui, |ui| {
+        ui.label("Theme:");
+        ui.horizontal(|ui| {
+            finished |= settled(&ui.selectable_value(&mut preferences.dark_mode, true, "🌙 Dark"));
+            finished |= settled(&ui.selectable_value(&mut preferences.dark_mode, false, "☀ Light"));
+        });
+        ui.end_row();
+
+        ui.label("Font size:");
+        finished |= settled(&ui.add(egui::Slider::new(&mut preferences.font_size, 8.0..=40.0).step_by(1.0)));
+        ui.end_row();
+
+        ui.label("Editor font size:");
+        finished |= settled(&ui.add(egui::Slider::new(&mut preferences.monospace_size, 8.0..=40.0).step_by(1.0)));
+        ui.end_row();
+
+        ui.label("Tree indentation:");
+        finished |= settled(&ui.add(egui::Slider::new(&mut preferences.indent, 0.0..=100.0).step_by(1.0).suffix(" px")));
+        ui.end_row();
+
+        ui.label("Expand tree to depth:");
+        let response = ui.add(egui::DragValue::new(&mut preferences.expand_depth).range(0..=32))
+            .on_hover_text("Levels opened when a document is loaded; 0 keeps everything collapsed");
+        finished |= settled(&response);
+        ui.end_row();
+
+        ui.label("Save mode:");
+        ui.horizontal(|ui| {
+            finished |= settled(&ui.selectable_value(&mut preferences.save_mode, SaveMode::Auto, "Save on every edit"));
+            finished |= settled(&ui.selectable_value(&mut preferences.save_mode, SaveMode::Manual, "Save with Ctrl+S"));
+        });
+        ui.end_row();
+
+        ui.label("File locking:");
+        ui.horizontal(|ui| {
+            finished |= settled(&ui.selectable_value(&mut preferences.file_locking, LockMode::Off, "Off"));
+            let flock = ui.selectable_value(&mut preferences.file_locking, LockMode::Flock, "flock")
+                .on_hover_text("Lock the file itself while reading and writing it");
+            let lock_file = ui.selectable_value(&mut preferences.file_locking, LockMode::LockFile, "Lock file")
+                .on_hover_text("Create <file>.lock next to the file while reading and writing it");
+            finished |= settled(&flock) || settled(&lock_file);
+        });
+        ui.end_row();
+
+        ui.label("Raw editor:");
+        finished |= settled(&ui.checkbox(&mut preferences.show_raw_editor, "Show in new windows"));
+        ui.end_row();
+
+        ui.label("Default split:");
+        finished |= settled(&ui.add(egui::Slider::new(&mut preferences.raw_editor_fraction, 0.1..=0.9).custom_formatter(|f, _| format!("{:.0} %", f * 100.0))));
+        ui.end_row();
+    });
+    ui.separator();
+    if ui.button("Restore defaults").clicked() {
+        *preferences = Preferences { window_size: preferences.window_size, ..Preferences::default() };
+        finished = true;
+    }
+    PreferencesEdit { changed: *preferences != before, finished }
+}
@@ -0,0 +1,337 @@
+// Line index of the raw text and the line-by-line view used for large files. A multiline
+// `TextEdit` lays out the whole text every frame, which stops being usable somewhere around
+// tens of thousands of lines; past that the raw editor only lays out the lines in view and
+// edits one line at a time.
+
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+
+/// Files with more lines or bytes than this open in the line-by-line view.
+const LARGE_FILE_LINES: usize = 5_000;
+const LARGE_FILE_BYTES: usize = 1 << 20;
+/// Longer lines (e.g. minified JSON) are cut short in the view; editing shows them in full.
+const MAX_SHOWN_LINE_CHARS: usize = 1_000;
+pub(crate) const GUTTER_WIDTH: f32 = 52.0;
+/// Changed stretches with more old × new lines than this are marked modified as a whole
+/// instead of being matched up line by line.
+const MAX_DIFF_CELLS: usize = 1_000_000;
+/// While typing, the change marks are worked out again at most this often.
+const MARK_INTERVAL: Duration = Duration::from_millis(300);
+
+/// How a line differs from the same place in the baseline text.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum LineMark {
+    Unchanged,
+    Added,
+    Modified,
+    /// Baseline lines were removed just above this line, or below it for the last line.
+    Deleted,
+}
+
+impl LineMark {
+    fn glyph(self) -> char {
+        match self {
+            LineMark::Unchanged => ' ',
+            LineMark::Added => '+',
+            LineMark::Modified => '~',
+            LineMark::Deleted => '-',
+        }
+    }
+
+    fn color(self, ui: &egui::Ui) -> egui::Color32 {
+        match self {
+            LineMark::Unchanged => ui.visuals().weak_text_color(),
+            LineMark::Added => egui::Color32::from_rgb(80, 200, 120),
+            LineMark::Modified => crate::MODIFIED_COLOR,
+            LineMark::Deleted => egui::Color32::RED,
+        }
+    }
+}
+
+/// Byte offsets of the line starts of one version of the text, and how each line differs
+/// from the baseline text.
+#[derive(Default)]
+pub(crate) struct LineIndex {
+    version: u64,
+    baseline_version: u64,
+    starts: Vec<usize>,
+    marks: Vec<LineMark>,
+    /// The text version `marks` were worked out for, and when.
+    marked: Option<(u64, Instant)>,
+    /// Line numbers for the gutter of the full editor; empty for large files.
+    gutter: String,
+}
+
+impl LineIndex {
+    /// Re-indexes `text` and compares it with `baseline` unless both are the versions indexed last.
+    /// Comparing a large file on every keystroke would slow typing down, so while the text keeps
+    /// changing the marks of the last comparison are kept and `ctx` repainted to catch up.
+    pub(crate) fn update(&mut self, ctx: &egui::Context, version: u64, text: &str, baseline_version: u64, baseline: &str) {
+        let marks_current = self.marked.is_some_and(|(marked, _)| marked == version);
+        if self.version == version && self.baseline_version == baseline_version && marks_current && !self.starts.is_empty() {
+            return;
+        }
+        let text_changed = self.version != version || self.starts.is_empty();
+        if text_changed {
+            self.starts = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        }
+        let throttled = self.baseline_version == baseline_version
+            && self.marked.is_some_and(|(_, at)| at.elapsed() < MARK_INTERVAL);
+        self.version = version;
+        self.baseline_version = baseline_version;
+        if throttled {
+            if !text_changed {
+                return;
+            }
+            self.marks.resize(self.starts.len(), LineMark::Unchanged);
+            ctx.request_repaint_after(MARK_INTERVAL);
+        } else {
+            self.marks = line_marks(baseline, text);
+            self.marked = Some((version, Instant::now()));
+        }
+        self.gutter = if self.is_large(text) {
+            String::new()
+        } else {
+            (0..self.starts.len()).map(|line| self.gutter_label(line)).collect::<Vec<_>>().join("\n")
+        };
+    }
+
+    /// The change mark and number of zero-based line `line`.
+    fn gutter_label(&self, line: usize) -> String {
+        format!("{} {:>4}", self.marks[line].glyph(), line + 1)
+    }
+
+    /// The gutter text, coloured by each line's change mark.
+    pub(crate) fn gutter_job(&self, ui: &egui::Ui) -> egui::text::LayoutJob {
+        let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+        let mut job = egui::text::LayoutJob::default();
+        for (line, (label, mark)) in self.gutter.split('\n').zip(&self.marks).enumerate() {
+            let separator = if line == 0 { "" } else { "\n" };
+            job.append(&format!("{separator}{label}"), 0.0, egui::TextFormat::simple(font_id.clone(), mark.color(ui)));
+        }
+        job
+    }
+
+    pub(crate) fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Whether `text` is too large for the full editor.
+    pub(crate) fn is_large(&self, text: &str) -> bool {
+        self.starts.len() > LARGE_FILE_LINES || text.len() > LARGE_FILE_BYTES
+    }
+
+    pub(crate) fn gutter(&self) -> &str {
+        &self.gutter
+    }
+
+    /// Zero-based line containing the byte `offset`.
+    pub(crate) fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset).saturating_sub(1)
+    }
+
+    /// Byte range of line `line`, without its line break.
+    fn line_range(&self, text: &str, line: usize) -> Range<usize> {
+        let start = self.starts[line];
+        let end = self.starts.get(line + 1).map_or(text.len(), |&next| next - 1);
+        let end = if text[start..end].ends_with('\r') { end - 1 } else { end };
+        start..end
+    }
+}
+
+fn edited_line_id() -> egui::Id {
+    egui::Id::new("raw_view_edited_line")
+}
+
+/// Draws the lines of `text` that are in view, scrolled so that `scroll_to_line` is at the top
+/// when given. Clicking a line edits it unless `read_only`; returns true when `text` was changed.
+pub(crate) fn render_lines(
+    ui: &mut egui::Ui,
+    text: &mut String,
+    index: &LineIndex,
+    scroll_to_line: Option<usize>,
+    read_only: bool,
+) -> bool {
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let row_height = ui.fonts(|f| f.row_height(&font_id)).max(ui.spacing().interact_size.y);
+    let mut scroll_area = egui::ScrollArea::both().id_salt("raw_view_scroll").auto_shrink([false; 2]);
+    if let Some(line) = scroll_to_line {
+        scroll_area = scroll_area.vertical_scroll_offset(line as f32 * (row_height + ui.spacing().item_spacing.y));
+    }
+
+    let mut edited: Option<(usize, String)> = if read_only { None } else { ui.data(|data| data.get_temp(edited_line_id())) };
+    let mut commit = None;
+    scroll_area.show_rows(ui, row_height, index.line_count(), |ui, rows| {
+        for line in rows {
+            ui.horizontal(|ui| {
+                ui.add_sized(
+                    [GUTTER_WIDTH, row_height],
+                    egui::Label::new(egui::RichText::new(index.gutter_label(line)).monospace().color(index.marks[line].color(ui))),
+                );
+                match &mut edited {
+                    Some((edited_line, buffer)) if *edited_line == line => {
+                        let response = ui.add(
+                            egui::TextEdit::singleline(buffer)
+                                .font(egui::TextStyle::Monospace)
+                                .desired_width(f32::INFINITY),
+                        );
+                        if !response.has_focus() && !response.lost_focus() {
+                            response.request_focus();
+                        }
+                        if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                            commit = Some(None);
+                        } else if response.lost_focus() {
+                            commit = Some(Some((line, buffer.clone())));
+                        }
+                    }
+                    _ => {
+                        let range = index.line_range(text, line);
+                        let full = &text[range];
+                        let shown = match full.char_indices().nth(MAX_SHOWN_LINE_CHARS) {
+                            Some((cut, _)) => format!("{}…", &full[..cut]),
+                            None => full.to_string(),
+                        };
+                        let response = ui.add(egui::Label::new(egui::RichText::new(shown).monospace()).sense(egui::Sense::click()));
+                        if response.clicked() && !read_only {
+                            edited = Some((line, full.to_string()));
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let changed = match commit {
+        Some(Some((line, buffer))) if line < index.line_count() => {
+            let range = index.line_range(text, line);
+            let changed = text[range.clone()] != buffer;
+            if changed {
+                text.replace_range(range, &buffer);
+            }
+            edited = None;
+            changed
+        }
+        Some(_) => {
+            edited = None;
+            false
+        }
+        None => false,
+    };
+    ui.data_mut(|data| match edited {
+        Some(edited) => data.insert_temp(edited_line_id(), edited),
+        None => data.remove::<(usize, String)>(edited_line_id()),
+    });
+    changed
+}
+
+/// Marks each line of `new` by how it differs from `old`, matching lines up the way a diff does.
+fn line_marks(old: &str, new: &str) -> Vec<LineMark> {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+    let mut marks = vec![LineMark::Unchanged; new.len()];
+    // Edits usually touch a few lines in one place; only the stretch between the common start
+    // and end needs matching up.
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_mid, new_mid) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+    let (n, m) = (old_mid.len(), new_mid.len());
+    if n * m > MAX_DIFF_CELLS {
+        marks[prefix..prefix + m].fill(LineMark::Modified);
+        return marks;
+    }
+
+    // common[i * (m + 1) + j]: length of the longest common subsequence of old_mid[i..] and new_mid[j..].
+    let mut common = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i * (m + 1) + j] = if old_mid[i] == new_mid[j] {
+                common[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                common[(i + 1) * (m + 1) + j].max(common[i * (m + 1) + j + 1])
+            };
+        }
+    }
+    // Lines removed and added between the same two matches pair up as modified lines.
+    let (mut i, mut j, mut removed) = (0, 0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_mid[i] == new_mid[j] {
+            if removed > 0 {
+                mark_deleted(&mut marks, prefix + j);
+                removed = 0;
+            }
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || common[(i + 1) * (m + 1) + j] >= common[i * (m + 1) + j + 1]) {
+            removed += 1;
+            i += 1;
+        } else {
+            marks[prefix + j] = if removed > 0 {
+                removed -= 1;
+                LineMark::Modified
+            } else {
+                LineMark::Added
+            };
+            j += 1;
+        }
+    }
+    if removed > 0 {
+        mark_deleted(&mut marks, prefix + m);
+    }
+    marks
+}
+
+/// Marks removed lines at `line`, or at the last line for lines removed at the end.
+fn mark_deleted(marks: &mut [LineMark], line: usize) {
+    let line = line.min(marks.len() - 1);
+    if marks[line] == LineMark::Unchanged {
+        marks[line] = LineMark::Deleted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use LineMark::{Added as A, Deleted as D, Modified as M, Unchanged as U};
+
+    #[test]
+    fn unchanged_text_has_no_marks() {
+        assert_eq!(line_marks("a\nb\n", "a\nb\n"), [U, U, U]);
+    }
+
+    #[test]
+    fn inserted_lines_are_added() {
+        assert_eq!(line_marks("a\nb\nc", "a\nx\ny\nb\nc"), [U, A, A, U, U]);
+        assert_eq!(line_marks("a\nb", "x\na\nb"), [A, U, U]);
+        assert_eq!(line_marks("a\nb", "a\nb\nx"), [U, U, A]);
+    }
+
+    #[test]
+    fn deleted_lines_mark_the_line_after_them() {
+        assert_eq!(line_marks("a\nb\nc", "a\nc"), [U, D]);
+        assert_eq!(line_marks("a\nb\nc", "b\nc"), [D, U]);
+    }
+
+    #[test]
+    fn deletion_at_the_end_marks_the_last_line() {
+        assert_eq!(line_marks("a\nb\nc", "a\nb"), [U, D]);
+        assert_eq!(line_marks("a\nb\nc\n", "a\n"), [U, D]);
+    }
+
+    #[test]
+    fn replaced_lines_are_modified() {
+        assert_eq!(line_marks("a\nb\nc", "a\nB\nc"), [U, M, U]);
+        // One line replaced by two: the first pairs up with the old line, the second is new.
+        assert_eq!(line_marks("a\nb\nc", "a\nB1\nB2\nc"), [U, M, A, U]);
+        assert_eq!(line_marks("a\nb1\nb2\nc", "a\nB\nc"), [U, M, D]);
+    }
+
+    #[test]
+    fn repeated_lines_match_the_right_copy() {
+        // Only one copy of `- x` is new, and only one line is marked for it.
+        assert_eq!(line_marks("- x\n- y\n- x", "- x\n- y\n- x\n- x"), [U, U, U, A]);
+        assert_eq!(line_marks("}\n}\n}", "}\n}"), [U, D]);
+        assert_eq!(line_marks("a: 1\nb: 1\na: 1", "a: 1\na: 1"), [U, D]);
+    }
+}
@@ -0,0 +1,866 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use eframe::egui;
+
+use crate::{INDENT_SPACES, MODIFIED_COLOR};
+
+/// The value types a tree node can be converted between from the type selector.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum NodeType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Null,
+    Map,
+    List,
+    Datetime,
+}
+
+impl NodeType {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            NodeType::String => "string",
+            NodeType::Integer => "int",
+            NodeType::Float => "float",
+            NodeType::Boolean => "bool",
+            NodeType::Null => "null",
+            NodeType::Map => "map",
+            NodeType::List => "list",
+            NodeType::Datetime => "datetime",
+        }
+    }
+}
+
+/// Format-neutral scalar used to share coercion rules between the YAML, JSON and TOML trees.
+/// Floats are kept as text so that a conversion never rewrites the original representation.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Scalar {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(String),
+    String(String),
+    Datetime(String),
+}
+
+impl Scalar {
+    pub(crate) fn node_type(&self) -> NodeType {
+        match self {
+            Scalar::Null => NodeType::Null,
+            Scalar::Boolean(_) => NodeType::Boolean,
+            Scalar::Integer(_) => NodeType::Integer,
+            Scalar::Float(_) => NodeType::Float,
+            Scalar::String(_) => NodeType::String,
+            Scalar::Datetime(_) => NodeType::Datetime,
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Scalar::Null => String::new(),
+            Scalar::Boolean(b) => b.to_string(),
+            Scalar::Integer(i) => i.to_string(),
+            Scalar::Float(s) | Scalar::String(s) | Scalar::Datetime(s) => s.clone(),
+        }
+    }
+
+    /// The value a freshly converted, empty node of `node_type` takes.
+    fn default_for(node_type: NodeType) -> Scalar {
+        match node_type {
+            NodeType::Boolean => Scalar::Boolean(false),
+            NodeType::Integer => Scalar::Integer(0),
+            NodeType::Float => Scalar::Float("0.0".to_string()),
+            NodeType::String => Scalar::String(String::new()),
+            _ => Scalar::Null,
+        }
+    }
+}
+
+/// Result of a successful conversion; `warning` is set when information was lost on the way.
+pub(crate) struct Converted<T> {
+    pub(crate) value: T,
+    pub(crate) warning: Option<String>,
+}
+
+impl<T> Converted<T> {
+    pub(crate) fn exact(value: T) -> Self {
+        Self { value, warning: None }
+    }
+
+    pub(crate) fn lossy(value: T, warning: impl Into<String>) -> Self {
+        Self { value, warning: Some(warning.into()) }
+    }
+
+    pub(crate) fn map<U>(self, f: impl FnOnce(T) -> U) -> Converted<U> {
+        Converted { value: f(self.value), warning: self.warning }
+    }
+}
+
+/// Parses a float the way YAML writes them, including `.inf`, `-.inf` and `.nan`.
+pub(crate) fn parse_float(text: &str) -> Option<f64> {
+    match text.trim() {
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => Some(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => Some(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => Some(f64::NAN),
+        other => other.parse::<f64>().ok(),
+    }
+}
+
+/// Formats a float so that it reads back as a float (`3` becomes `3.0`).
+pub(crate) fn format_float(f: f64) -> String {
+    if f.is_finite() && f.fract() == 0.0 && f.abs() < 1e16 {
+        format!("{f:.1}")
+    } else if f.is_finite() && f.fract() == 0.0 {
+        format!("{f:e}")
+    } else {
+        f.to_string()
+    }
+}
+
+const SI_PREFIXES: &[(i32, &str)] = &[
+    (-24, "y"), (-21, "z"), (-18, "a"), (-15, "f"), (-12, "p"), (-9, "n"), (-6, "µ"), (-3, "m"),
+    (3, "k"), (6, "M"), (9, "G"), (12, "T"), (15, "P"), (18, "E"), (21, "Z"), (24, "Y"),
+];
+
+/// Parses a float typed into the float editor: YAML specials, `inf`/`nan`,
+/// scientific notation and an optional SI prefix such as `10n` or `2.5 k`.
+pub(crate) fn parse_float_input(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if let Some(f) = parse_float(text) {
+        return Some(f);
+    }
+    match text.to_lowercase().as_str() {
+        "inf" | "+inf" | "infinity" => return Some(f64::INFINITY),
+        "-inf" | "-infinity" => return Some(f64::NEG_INFINITY),
+        "nan" => return Some(f64::NAN),
+        _ => {}
+    }
+    let (exponent, number) = SI_PREFIXES
+        .iter()
+        .chain(&[(-6, "u")])
+        .find_map(|(exponent, prefix)| text.strip_suffix(prefix).map(|number| (*exponent, number)))?;
+    parse_float(number.trim()).map(|f| f * 10f64.powi(exponent))
+}
+
+/// Formats `value` in the notation of `original`: `.inf`/`.nan` for special values,
+/// scientific when the original was written with an exponent, decimal otherwise.
+pub(crate) fn format_float_like(original: &str, value: f64) -> String {
+    if value.is_nan() {
+        ".nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { ".inf" } else { "-.inf" }.to_string()
+    } else if original.contains(['e', 'E']) && !original.to_lowercase().contains("inf") {
+        format!("{value:e}")
+    } else {
+        format_float(value)
+    }
+}
+
+/// `value` with an SI prefix, e.g. `1.5 n` for 1.5e-9, or `None` when no prefix applies.
+pub(crate) fn si_text(value: f64) -> Option<String> {
+    if value == 0.0 || !value.is_finite() {
+        return None;
+    }
+    let exponent = ((value.abs().log10() / 3.0).floor() as i32 * 3).clamp(-24, 24);
+    let prefix = SI_PREFIXES.iter().find(|(e, _)| *e == exponent)?.1;
+    let mantissa = value / 10f64.powi(exponent);
+    let mantissa = format!("{mantissa:.4}");
+    Some(format!("{} {prefix}", mantissa.trim_end_matches('0').trim_end_matches('.')))
+}
+
+fn same_float(a: f64, b: f64) -> bool {
+    a == b || (a.is_nan() && b.is_nan())
+}
+
+/// Edits a float stored as text. The text is only rewritten when the value actually changes,
+/// so `1e-9` or `.inf` keep their spelling. Returns whether `text` changed.
+pub(crate) fn render_float_editor(ui: &mut egui::Ui, text: &mut String) -> bool {
+    let Some(original) = parse_float(text) else {
+        // Not something we can read as a number; leave it editable as plain text
+        return ui.add(egui::TextEdit::singleline(text).desired_width(80.0)).changed();
+    };
+    let mut value = original;
+    let changed = if original.is_finite() {
+        let original_text = text.clone();
+        let speed = if original == 0.0 { 0.01 } else { original.abs() * 0.01 };
+        ui.add(
+            egui::DragValue::new(&mut value)
+                .speed(speed)
+                .custom_formatter(move |n, _| {
+                    if same_float(n, original) {
+                        original_text.clone()
+                    } else {
+                        format_float_like(&original_text, n)
+                    }
+                })
+                .custom_parser(parse_float_input),
+        )
+        .changed()
+    } else {
+        // Dragging makes no sense for infinities or NaN, so they are typed instead
+        let id = ui.id().with(("float_text", text.as_str()));
+        let mut input = ui.data(|data| data.get_temp::<String>(id)).unwrap_or_else(|| text.clone());
+        let response = ui.add(egui::TextEdit::singleline(&mut input).desired_width(80.0));
+        let parsed = parse_float_input(&input);
+        if response.lost_focus() {
+            ui.data_mut(|data| data.remove::<String>(id));
+        } else if response.has_focus() {
+            ui.data_mut(|data| data.insert_temp(id, input));
+        }
+        match parsed {
+            Some(f) if response.lost_focus() => {
+                value = f;
+                true
+            }
+            _ => false,
+        }
+    };
+    if let Some(si) = si_text(value) {
+        ui.label(egui::RichText::new(format!("= {si}")).weak()).on_hover_text(format_float_like("e", value));
+    }
+    if changed && !same_float(value, original) {
+        *text = format_float_like(text, value);
+        true
+    } else {
+        false
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn float_to_integer(f: f64, text: &str) -> Result<Converted<Scalar>, String> {
+    if !f.is_finite() {
+        return Err(format!("{text} cannot be represented as an integer"));
+    }
+    let rounded = f.round();
+    // `i64::MAX as f64` rounds up to 2^63, which is already out of range.
+    if rounded < i64::MIN as f64 || rounded >= i64::MAX as f64 {
+        return Err(format!("{text} is out of range for an integer"));
+    }
+    if rounded == f {
+        Ok(Converted::exact(Scalar::Integer(rounded as i64)))
+    } else {
+        Ok(Converted::lossy(Scalar::Integer(rounded as i64), format!("{text} rounded to {rounded}")))
+    }
+}
+
+/// Converts a scalar to another scalar type. Unparseable input is an error, lossy coercions
+/// (rounding, truncated precision, discarded values) succeed with a warning.
+pub(crate) fn coerce_scalar(value: Scalar, target: NodeType) -> Result<Converted<Scalar>, String> {
+    if value.node_type() == target {
+        return Ok(Converted::exact(value));
+    }
+    let text = value.text();
+    match (value, target) {
+        (_, NodeType::String) => Ok(Converted::exact(Scalar::String(text))),
+
+        (Scalar::Null, NodeType::Datetime) => Err("null cannot be converted to a datetime".to_string()),
+        (Scalar::Null, _) => Ok(Converted::exact(Scalar::default_for(target))),
+
+        (Scalar::String(s), NodeType::Null) if s.is_empty() || s == "~" || s.eq_ignore_ascii_case("null") => {
+            Ok(Converted::exact(Scalar::Null))
+        }
+        (_, NodeType::Null) => Ok(Converted::lossy(Scalar::Null, format!("value \"{text}\" discarded"))),
+
+        (Scalar::Boolean(b), NodeType::Integer) => Ok(Converted::exact(Scalar::Integer(b as i64))),
+        (Scalar::Boolean(b), NodeType::Float) => Ok(Converted::exact(Scalar::Float(format_float(b as i64 as f64)))),
+
+        (Scalar::Integer(i), NodeType::Float) => {
+            let f = i as f64;
+            // Through i128, as `f as i64` saturates and would call i64::MAX exact.
+            if f as i128 == i as i128 {
+                Ok(Converted::exact(Scalar::Float(format_float(f))))
+            } else {
+                Ok(Converted::lossy(Scalar::Float(format_float(f)), format!("{i} loses precision as a float")))
+            }
+        }
+        (Scalar::Integer(i), NodeType::Boolean) => match i {
+            0 | 1 => Ok(Converted::exact(Scalar::Boolean(i == 1))),
+            _ => Ok(Converted::lossy(Scalar::Boolean(true), format!("{i} treated as true"))),
+        },
+
+        (Scalar::Float(s), NodeType::Integer) => match parse_float(&s) {
+            Some(f) => float_to_integer(f, &s),
+            None => Err(format!("\"{s}\" is not a number")),
+        },
+        (Scalar::Float(s), NodeType::Boolean) => match parse_float(&s) {
+            Some(f) if f == 0.0 || f == 1.0 => Ok(Converted::exact(Scalar::Boolean(f == 1.0))),
+            Some(f) => Ok(Converted::lossy(Scalar::Boolean(!f.is_nan()), format!("{s} treated as {}", !f.is_nan()))),
+            None => Err(format!("\"{s}\" is not a number")),
+        },
+
+        (Scalar::String(s), NodeType::Integer) => {
+            if let Ok(i) = s.trim().parse::<i64>() {
+                Ok(Converted::exact(Scalar::Integer(i)))
+            } else if let Some(f) = parse_float(&s) {
+                float_to_integer(f, s.trim())
+            } else {
+                Err(format!("\"{s}\" is not an integer"))
+            }
+        }
+        (Scalar::String(s), NodeType::Float) => match parse_float(&s) {
+            // `inf` or `NaN` parse in Rust but are strings in YAML; write them the YAML way.
+            Some(f) if !f.is_finite() => Ok(Converted::exact(Scalar::Float(format_float_like(&s, f)))),
+            Some(_) => Ok(Converted::exact(Scalar::Float(s.trim().to_string()))),
+            None => Err(format!("\"{s}\" is not a float")),
+        },
+        (Scalar::String(s), NodeType::Boolean) => match parse_bool(&s) {
+            Some(b) if s.trim() == b.to_string() => Ok(Converted::exact(Scalar::Boolean(b))),
+            Some(b) => Ok(Converted::lossy(Scalar::Boolean(b), format!("\"{s}\" read as {b}"))),
+            None => Err(format!("\"{s}\" is not a boolean")),
+        },
+        (Scalar::String(s), NodeType::Datetime) => Ok(Converted::exact(Scalar::Datetime(s.trim().to_string()))),
+
+        (Scalar::Datetime(s), _) => Err(format!("datetime {s} cannot be converted to {}", target.label())),
+        (value, _) => Err(format!("{} cannot be converted to {}", value.node_type().label(), target.label())),
+    }
+}
+
+/// Converts a collection with `len` entries, printed as `text`, to a scalar type.
+/// Becoming a string keeps the text; any other scalar type drops the entries.
+pub(crate) fn collapse_collection(from: NodeType, len: usize, text: String, target: NodeType) -> Result<Converted<Scalar>, String> {
+    match target {
+        NodeType::String => Ok(Converted::lossy(
+            Scalar::String(text),
+            format!("{} flattened into text", from.label()),
+        )),
+        NodeType::Datetime => Err(format!("{} cannot be converted to a datetime", from.label())),
+        _ if len == 0 => Ok(Converted::exact(Scalar::default_for(target))),
+        _ => Ok(Converted::lossy(
+            Scalar::default_for(target),
+            format!("{len} {} entries discarded", from.label()),
+        )),
+    }
+}
+
+/// A small framed label, e.g. for YAML tags and anchors next to a key.
+pub(crate) fn render_badge(ui: &mut egui::Ui, text: &str, color: egui::Color32) -> egui::Response {
+    egui::Frame::new()
+        .stroke(egui::Stroke::new(1.0, color))
+        .corner_radius(3.0)
+        .inner_margin(egui::Margin::symmetric(3, 0))
+        .show(ui, |ui| ui.label(egui::RichText::new(text).small().color(color)))
+        .inner
+}
+
+/// Header text for a collapsible node: the key followed by its badges in their colors.
+pub(crate) fn header_text(ui: &egui::Ui, label: &str, badges: &[(String, egui::Color32)]) -> egui::text::LayoutJob {
+    let mut job = egui::text::LayoutJob::default();
+    let style = ui.style();
+    egui::RichText::new(label).append_to(&mut job, style, egui::FontSelection::Default, egui::Align::Center);
+    for (badge, color) in badges {
+        egui::RichText::new(format!("  {badge}"))
+            .small()
+            .color(*color)
+            .append_to(&mut job, style, egui::FontSelection::Default, egui::Align::Center);
+    }
+    job
+}
+
+/// Tree layout from the preferences, shared with the renderers through egui memory.
+#[derive(Clone, Copy)]
+struct TreeLayout {
+    indent: f32,
+    expand_depth: usize,
+}
+
+fn tree_layout_id() -> egui::Id {
+    egui::Id::new("tree_layout")
+}
+
+pub(crate) fn set_tree_layout(ctx: &egui::Context, indent: f32, expand_depth: usize) {
+    ctx.data_mut(|data| data.insert_temp(tree_layout_id(), TreeLayout { indent, expand_depth }));
+}
+
+/// Space in front of each tree row.
+pub(crate) fn indent(ui: &egui::Ui) -> f32 {
+    ui.data(|data| data.get_temp::<TreeLayout>(tree_layout_id())).map_or(INDENT_SPACES, |layout| layout.indent)
+}
+
+/// Whether the node at `path` starts expanded, given the preferred expansion depth.
+pub(crate) fn starts_open(ui: &egui::Ui, path: &[String]) -> bool {
+    ui.data(|data| data.get_temp::<TreeLayout>(tree_layout_id())).is_some_and(|layout| path.len() <= layout.expand_depth)
+}
+
+fn read_only_id() -> egui::Id {
+    egui::Id::new("tree_read_only")
+}
+
+/// Turns the tree's editing widgets off or on; the tree can still be browsed while read-only.
+pub(crate) fn set_read_only(ctx: &egui::Context, read_only: bool) {
+    ctx.data_mut(|data| data.insert_temp(read_only_id(), read_only));
+}
+
+pub(crate) fn is_read_only(ui: &egui::Ui) -> bool {
+    ui.data(|data| data.get_temp(read_only_id())).unwrap_or(false)
+}
+
+/// Draws editing widgets, disabled while the tree is read-only. Widgets that only add or
+/// remove nodes are better skipped altogether with `is_read_only`.
+pub(crate) fn editable<R>(ui: &mut egui::Ui, add_contents: impl FnOnce(&mut egui::Ui) -> R) -> R {
+    let enabled = !is_read_only(ui);
+    ui.add_enabled_ui(enabled, add_contents).inner
+}
+
+fn open_nodes_id() -> egui::Id {
+    egui::Id::new("open_tree_nodes")
+}
+
+fn restored_nodes_id() -> egui::Id {
+    egui::Id::new("restored_tree_nodes")
+}
+
+/// Key paths of the collapsible nodes that are currently expanded.
+pub(crate) fn open_nodes(ctx: &egui::Context) -> HashSet<Vec<String>> {
+    ctx.data(|data| data.get_temp(open_nodes_id())).unwrap_or_default()
+}
+
+/// Expands `nodes` as they are first drawn, e.g. when a session is restored.
+pub(crate) fn restore_open_nodes(ctx: &egui::Context, nodes: HashSet<Vec<String>>) {
+    ctx.data_mut(|data| {
+        data.insert_temp(open_nodes_id(), nodes.clone());
+        data.insert_temp(restored_nodes_id(), nodes);
+    });
+}
+
+/// `Some(true)` the first time a node that should be restored open is drawn; pass to
+/// `CollapsingHeader::open`.
+pub(crate) fn take_restored_open(ui: &egui::Ui, path: &[String]) -> Option<bool> {
+    ui.data_mut(|data| {
+        data.get_temp_mut_or_default::<HashSet<Vec<String>>>(restored_nodes_id())
+            .remove(path)
+            .then_some(true)
+    })
+}
+
+/// Remembers whether the collapsible node at `path` is expanded.
+pub(crate) fn record_open<R>(ui: &egui::Ui, path: &[String], response: &egui::CollapsingResponse<R>) {
+    let open = response.openness > 0.0;
+    ui.data_mut(|data| {
+        let nodes = data.get_temp_mut_or_default::<HashSet<Vec<String>>>(open_nodes_id());
+        if !open {
+            nodes.remove(path);
+        } else if !nodes.contains(path) {
+            nodes.insert(path.to_vec());
+        }
+    });
+}
+
+fn jump_target_id() -> egui::Id {
+    egui::Id::new("jump_target")
+}
+
+/// Scrolls the tree to the node at `path` and flashes it, opening its ancestors on the way.
+pub(crate) fn set_jump_target(ctx: &egui::Context, path: Vec<String>) {
+    ctx.data_mut(|data| data.insert_temp(jump_target_id(), path));
+}
+
+/// Whether a pending jump points somewhere below `path`, so its header has to open.
+fn is_on_jump_path(ui: &egui::Ui, path: &[String]) -> bool {
+    ui.data(|data| data.get_temp::<Vec<String>>(jump_target_id()))
+        .is_some_and(|target| target.len() > path.len() && target.starts_with(path))
+}
+
+/// Scrolls to and flashes the row at `path` when it is the jump target; call with the row's rect.
+pub(crate) fn highlight_jump_target(ui: &mut egui::Ui, path: &[String], rect: egui::Rect) {
+    let highlight_id = egui::Id::new("jump_highlight");
+    let now = ui.input(|i| i.time);
+    if ui.data(|data| data.get_temp::<Vec<String>>(jump_target_id())).as_deref() == Some(path) {
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        let target_rect = egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), row_height));
+        ui.scroll_to_rect(target_rect, Some(egui::Align::Center));
+        ui.data_mut(|data| {
+            data.remove::<Vec<String>>(jump_target_id());
+            data.insert_temp(highlight_id, (path.to_vec(), now));
+        });
+    }
+    if let Some((highlighted, since)) = ui.data(|data| data.get_temp::<(Vec<String>, f64)>(highlight_id)) {
+        if highlighted == path && now - since < 1.5 {
+            ui.painter().rect_stroke(
+                rect.expand(2.0),
+                4.0,
+                egui::Stroke::new(1.5, ui.visuals().selection.stroke.color),
+                egui::StrokeKind::Outside,
+            );
+            ui.ctx().request_repaint();
+        }
+    }
+}
+
+/// A tree-wide expand or collapse command from the tree pane's toolbar.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Expansion {
+    All,
+    None,
+    /// Opens the nodes at most this many levels deep and closes the rest.
+    Depth(usize),
+}
+
+impl Expansion {
+    fn opens(self, path: &[String]) -> bool {
+        match self {
+            Expansion::All => true,
+            Expansion::None => false,
+            Expansion::Depth(depth) => path.len() <= depth,
+        }
+    }
+}
+
+/// The last expansion command and the nodes it was applied to so far. Nodes are only drawn
+/// once their parent is open, so the command is applied to each node as it first appears.
+#[derive(Clone)]
+struct PendingExpansion {
+    expansion: Expansion,
+    applied: HashSet<Vec<String>>,
+}
+
+fn expansion_id() -> egui::Id {
+    egui::Id::new("tree_expansion")
+}
+
+/// Expands or collapses the whole tree.
+pub(crate) fn set_expansion(ctx: &egui::Context, expansion: Expansion) {
+    ctx.data_mut(|data| {
+        data.remove::<HashSet<Vec<String>>>(restored_nodes_id());
+        // Nodes below a collapsed parent are not drawn, so they would not be recorded closed.
+        data.get_temp_mut_or_default::<HashSet<Vec<String>>>(open_nodes_id()).retain(|path| expansion.opens(path));
+        data.insert_temp(expansion_id(), Some(PendingExpansion { expansion, applied: HashSet::new() }));
+    });
+}
+
+/// Forgets the last expansion command, e.g. when another file is opened.
+pub(crate) fn clear_expansion(ctx: &egui::Context) {
+    ctx.data_mut(|data| data.remove::<Option<PendingExpansion>>(expansion_id()));
+}
+
+/// `Some(open)` the first time the node at `path` is drawn after an expansion command.
+fn take_expansion(ui: &egui::Ui, path: &[String]) -> Option<bool> {
+    ui.data_mut(|data| {
+        let pending = data.get_temp_mut_or_default::<Option<PendingExpansion>>(expansion_id()).as_mut()?;
+        pending.applied.insert(path.to_vec()).then(|| pending.expansion.opens(path))
+    })
+}
+
+/// The `CollapsingHeader::open` override for the node at `path`: open when a jump passes
+/// through it, as set by the last expansion command, or the first time it is drawn after its
+/// open state was restored.
+pub(crate) fn forced_open(ui: &egui::Ui, path: &[String]) -> Option<bool> {
+    is_on_jump_path(ui, path)
+        .then_some(true)
+        .or_else(|| take_expansion(ui, path))
+        .or_else(|| take_restored_open(ui, path))
+}
+
+/// The collapsible header of the tree node at `path`. Its id comes from the key path rather
+/// than the label, so that equal keys at one level never share state and edits keep it.
+pub(crate) fn tree_header(ui: &egui::Ui, text: impl Into<egui::WidgetText>, path: &[String]) -> egui::CollapsingHeader {
+    egui::CollapsingHeader::new(text)
+        .id_salt(("tree_node", path))
+        .default_open(starts_open(ui, path))
+        .open(forced_open(ui, path))
+}
+
+/// How long rows changed by an external reload keep flashing; the mark stays until the next one.
+const CHANGE_FLASH_SECONDS: f64 = 3.0;
+
+#[derive(Clone)]
+struct ChangeMarks {
+    /// Changed key paths and all their ancestors, so that collapsed parents are marked too.
+    paths: HashSet<Vec<String>>,
+    since: f64,
+}
+
+fn change_marks_id() -> egui::Id {
+    egui::Id::new("external_change_marks")
+}
+
+/// Marks the rows at `paths` as changed outside the editor, replacing earlier marks.
+pub(crate) fn mark_external_changes<'a>(ctx: &egui::Context, paths: impl IntoIterator<Item = &'a Vec<String>>) {
+    let paths = paths
+        .into_iter()
+        .flat_map(|path| (1..=path.len()).map(|len| path[..len].to_vec()))
+        .collect();
+    let since = ctx.input(|i| i.time);
+    ctx.data_mut(|data| data.insert_temp(change_marks_id(), ChangeMarks { paths, since }));
+}
+
+/// Draws the change mark next to the row at `path`, flashing it for a while after the reload.
+fn highlight_external_change(ui: &mut egui::Ui, path: &[String], rect: egui::Rect) {
+    let Some(marks) = ui.data(|data| data.get_temp::<ChangeMarks>(change_marks_id())) else {
+        return;
+    };
+    if !marks.paths.contains(path) {
+        return;
+    }
+    let color = egui::Color32::from_rgb(255, 165, 0);
+    let row_height = ui.text_style_height(&egui::TextStyle::Body);
+    let bar = egui::Rect::from_min_size(rect.min, egui::vec2(3.0, row_height));
+    ui.painter().rect_filled(bar, 1.0, color);
+    let elapsed = ui.input(|i| i.time) - marks.since;
+    if elapsed < CHANGE_FLASH_SECONDS {
+        let fade = 1.0 - (elapsed / CHANGE_FLASH_SECONDS) as f32;
+        let target_rect = egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), row_height));
+        ui.painter().rect_filled(target_rect, 2.0, color.gamma_multiply(0.35 * fade));
+        ui.ctx().request_repaint();
+    }
+}
+
+/// Key paths whose values differ from the baseline, and all their ancestors. Shared, so that
+/// looking them up for every row does not copy them.
+type ModifiedMarks = Arc<HashSet<Vec<String>>>;
+
+fn modified_marks_id() -> egui::Id {
+    egui::Id::new("modified_marks")
+}
+
+fn revert_request_id() -> egui::Id {
+    egui::Id::new("revert_request")
+}
+
+/// Marks the rows at `paths` as differing from the baseline, replacing earlier marks.
+pub(crate) fn mark_modified<'a>(ctx: &egui::Context, paths: impl IntoIterator<Item = &'a Vec<String>>) {
+    let paths: HashSet<Vec<String>> = paths
+        .into_iter()
+        .flat_map(|path| (1..=path.len()).map(|len| path[..len].to_vec()))
+        .collect();
+    ctx.data_mut(|data| data.insert_temp::<ModifiedMarks>(modified_marks_id(), Arc::new(paths)));
+}
+
+/// The row whose revert button was clicked since the last call.
+pub(crate) fn take_revert_request(ctx: &egui::Context) -> Option<Vec<String>> {
+    ctx.data_mut(|data| data.remove_temp(revert_request_id()))
+}
+
+/// Tints the row at `path` when it differs from the baseline, with a button after its first
+/// line that reverts it.
+fn highlight_modified(ui: &mut egui::Ui, path: &[String], rect: egui::Rect) {
+    let Some(marks) = ui.data(|data| data.get_temp::<ModifiedMarks>(modified_marks_id())) else {
+        return;
+    };
+    if !marks.contains(path) {
+        return;
+    }
+    let row_height = ui.text_style_height(&egui::TextStyle::Body);
+    let first_line = egui::Rect::from_min_size(rect.min, egui::vec2(rect.width(), row_height));
+    ui.painter().rect_filled(first_line, 2.0, MODIFIED_COLOR.gamma_multiply(0.15));
+    let button = egui::Rect::from_min_size(egui::pos2(rect.max.x + 4.0, rect.min.y), egui::vec2(row_height, row_height));
+    if is_read_only(ui) {
+        ui.painter().circle_filled(button.center(), 3.0, MODIFIED_COLOR);
+        return;
+    }
+    let response = ui
+        .interact(button, egui::Id::new("revert_node").with(path), egui::Sense::click())
+        .on_hover_text("Revert to the saved value");
+    let color = if response.hovered() { ui.visuals().strong_text_color() } else { MODIFIED_COLOR };
+    ui.painter().text(button.center(), egui::Align2::CENTER_CENTER, "⟲", egui::FontId::proportional(row_height), color);
+    if response.clicked() {
+        ui.data_mut(|data| data.insert_temp(revert_request_id(), path.to_vec()));
+    }
+}
+
+/// Lists longer than this are drawn a page at a time.
+const LIST_PAGE_SIZE: usize = 100;
+
+fn shown_items_id(path: &[String]) -> egui::Id {
+    egui::Id::new("shown_list_items").with(path)
+}
+
+/// How many of the `len` items of the list at `path` are drawn: one page at first, more on
+/// request, and always enough to reach a pending jump target.
+pub(crate) fn shown_items(ui: &egui::Ui, path: &[String], len: usize) -> usize {
+    let id = shown_items_id(path);
+    let mut shown = ui.data(|data| data.get_temp(id)).unwrap_or(LIST_PAGE_SIZE);
+    let jump_index = ui
+        .data(|data| data.get_temp::<Vec<String>>(jump_target_id()))
+        .filter(|target| target.len() > path.len() && target.starts_with(path))
+        .and_then(|target| target[path.len()].parse::<usize>().ok());
+    if let Some(index) = jump_index.filter(|&index| index >= shown) {
+        shown = (index / LIST_PAGE_SIZE + 1) * LIST_PAGE_SIZE;
+        ui.data_mut(|data| data.insert_temp(id, shown));
+    }
+    shown.min(len)
+}
+
+/// Offers to draw more of a list that is only partly drawn.
+pub(crate) fn render_more_items(ui: &mut egui::Ui, path: &[String], shown: usize, len: usize) {
+    if shown >= len {
+        return;
+    }
+    ui.horizontal(|ui| {
+        ui.add_space(indent(ui));
+        ui.weak(format!("{shown} of {len} items shown"));
+        let id = shown_items_id(path);
+        if ui.button(format!("Show {} more", LIST_PAGE_SIZE.min(len - shown))).clicked() {
+            ui.data_mut(|data| data.insert_temp(id, shown + LIST_PAGE_SIZE));
+        }
+        if ui.button("Show all").clicked() {
+            ui.data_mut(|data| data.insert_temp(id, len));
+        }
+    });
+}
+
+/// Draws the tree row at `path`, or only reserves its last measured height while it is
+/// scrolled out of view, so that large trees cost little more than the rows on screen.
+pub(crate) fn lazy_row(ui: &mut egui::Ui, path: &[String], add_row: impl FnOnce(&mut egui::Ui)) {
+    let height_id = egui::Id::new("tree_row_height").with(path);
+    let row_id = ("tree_row", path);
+    if let Some(height) = ui.data(|data| data.get_temp::<f32>(height_id)) {
+        let rect = egui::Rect::from_min_size(ui.cursor().min, egui::vec2(ui.available_width(), height));
+        let jumping_here = ui
+            .data(|data| data.get_temp::<Vec<String>>(jump_target_id()))
+            .is_some_and(|target| target.starts_with(path));
+        if !ui.is_rect_visible(rect) && !jumping_here {
+            ui.push_id(row_id, |ui| ui.allocate_space(egui::vec2(0.0, height)));
+            return;
+        }
+    }
+    let rect = ui.push_id(row_id, add_row).response.rect;
+    highlight_external_change(ui, path, rect);
+    highlight_modified(ui, path, rect);
+    ui.data_mut(|data| data.insert_temp(height_id, rect.height()));
+}
+
+#[derive(Clone)]
+struct ConversionNotice {
+    message: String,
+    is_error: bool,
+}
+
+fn notice_id(path: &[String]) -> egui::Id {
+    egui::Id::new("type_conversion_notice").with(path)
+}
+
+/// Draws the type dropdown for a node and returns the newly picked type, if any.
+pub(crate) fn render_type_selector(
+    ui: &mut egui::Ui,
+    path: &[String],
+    current: NodeType,
+    options: &[NodeType],
+) -> Option<NodeType> {
+    let mut selected = current;
+    editable(ui, |ui| {
+        egui::ComboBox::from_id_salt(("node_type", path))
+            .width(70.0)
+            .selected_text(egui::RichText::new(current.label()).small())
+            .show_ui(ui, |ui| {
+                for &option in options {
+                    ui.selectable_value(&mut selected, option, option.label());
+                }
+            });
+    });
+    (selected != current).then_some(selected)
+}
+
+/// Applies a conversion outcome to `target`, remembering any warning or error for the node
+/// so `render_conversion_notice` can show it. Returns whether `target` changed.
+pub(crate) fn apply_conversion<T>(ui: &mut egui::Ui, path: &[String], outcome: Result<Converted<T>, String>, target: &mut T) -> bool {
+    let (changed, notice) = match outcome {
+        Ok(converted) => {
+            *target = converted.value;
+            (true, converted.warning.map(|message| ConversionNotice { message, is_error: false }))
+        }
+        Err(message) => (false, Some(ConversionNotice { message, is_error: true })),
+    };
+    let id = notice_id(path);
+    ui.data_mut(|data| match notice {
+        Some(notice) => data.insert_temp(id, notice),
+        None => data.remove::<ConversionNotice>(id),
+    });
+    changed
+}
+
+/// Attaches a message to a node, shown by `render_conversion_notice` until dismissed.
+pub(crate) fn show_notice(ui: &mut egui::Ui, path: &[String], message: String, is_error: bool) {
+    ui.data_mut(|data| data.insert_temp(notice_id(path), ConversionNotice { message, is_error }));
+}
+
+/// Shows the last conversion warning for a node; clicking it dismisses it.
+pub(crate) fn render_conversion_notice(ui: &mut egui::Ui, path: &[String]) {
+    let id = notice_id(path);
+    let Some(notice) = ui.data(|data| data.get_temp::<ConversionNotice>(id)) else {
+        return;
+    };
+    let color = if notice.is_error { egui::Color32::RED } else { egui::Color32::YELLOW };
+    let text = egui::RichText::new(format!("⚠ {}", notice.message)).small().color(color);
+    if ui
+        .add(egui::Label::new(text).sense(egui::Sense::click()))
+        .on_hover_text("Click to dismiss")
+        .clicked()
+    {
+        ui.data_mut(|data| data.remove::<ConversionNotice>(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float(text: &str) -> Scalar {
+        Scalar::Float(text.to_string())
+    }
+
+    fn string(text: &str) -> Scalar {
+        Scalar::String(text.to_string())
+    }
+
+    #[test]
+    fn coerce_scalar_table() {
+        // (value, target, result, whether a warning is expected)
+        let cases = [
+            (string("42"), NodeType::Integer, Scalar::Integer(42), false),
+            (string("4.6"), NodeType::Integer, Scalar::Integer(5), true),
+            (string("1.5"), NodeType::Float, float("1.5"), false),
+            (string("inf"), NodeType::Float, float(".inf"), false),
+            (string("-Infinity"), NodeType::Float, float("-.inf"), false),
+            (string("NaN"), NodeType::Float, float(".nan"), false),
+            (string(".inf"), NodeType::Float, float(".inf"), false),
+            (string("true"), NodeType::Boolean, Scalar::Boolean(true), false),
+            (string("yes"), NodeType::Boolean, Scalar::Boolean(true), true),
+            (string("~"), NodeType::Null, Scalar::Null, false),
+            (string("x"), NodeType::Null, Scalar::Null, true),
+            (Scalar::Integer(3), NodeType::Float, float("3.0"), false),
+            (Scalar::Integer(1 << 53), NodeType::Float, float("9007199254740992.0"), false),
+            (Scalar::Integer((1 << 53) + 1), NodeType::Float, float("9007199254740992.0"), true),
+            (Scalar::Integer(i64::MAX), NodeType::Float, float("9.223372036854776e18"), true),
+            (Scalar::Integer(2), NodeType::Boolean, Scalar::Boolean(true), true),
+            (Scalar::Integer(0), NodeType::Boolean, Scalar::Boolean(false), false),
+            (float("2.0"), NodeType::Integer, Scalar::Integer(2), false),
+            (float("0.5"), NodeType::Boolean, Scalar::Boolean(true), true),
+            (Scalar::Boolean(true), NodeType::Integer, Scalar::Integer(1), false),
+            (Scalar::Null, NodeType::Integer, Scalar::Integer(0), false),
+            (Scalar::Integer(7), NodeType::String, string("7"), false),
+        ];
+        for (value, target, expected, lossy) in cases {
+            let label = format!("{value:?} -> {}", target.label());
+            let converted = coerce_scalar(value, target).unwrap_or_else(|err| panic!("{label}: {err}"));
+            assert_eq!(converted.value, expected, "{label}");
+            assert_eq!(converted.warning.is_some(), lossy, "{label}: {:?}", converted.warning);
+        }
+    }
+
+    #[test]
+    fn coerce_scalar_rejects() {
+        let cases = [
+            (string("abc"), NodeType::Float),
+            (string("abc"), NodeType::Integer),
+            (string("maybe"), NodeType::Boolean),
+            (float(".inf"), NodeType::Integer),
+            (float("9.3e18"), NodeType::Integer),
+            (float("9223372036854775807.0"), NodeType::Integer),
+            (Scalar::Null, NodeType::Datetime),
+            (Scalar::Datetime("1979-05-27".to_string()), NodeType::Integer),
+        ];
+        for (value, target) in cases {
+            let label = format!("{value:?} -> {}", target.label());
+            assert!(coerce_scalar(value, target).is_err(), "{label}");
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use eframe::egui;
+use crate::ini_document::{IniDialect, IniDocument};
+use crate::render_base_types;
+
+/// Renders an INI or .env file: top-level entries first, then one collapsible header per section.
+pub(crate) fn render_ini_document(
+    ui: &mut egui::Ui,
+    doc: &mut IniDocument,
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+) {
+    let mut to_remove = None;
+    for section in doc.sections() {
+        match &section.name {
+            None => {
+                render_entries(ui, doc, &section.entries, None, &mut to_remove, modified, scroll_marker_key);
+                render_new_entry(ui, doc, None, modified, scroll_marker_key);
+            }
+            Some(name) => {
+                ui.horizontal(|ui| {
+                    ui.add_space(render_base_types::indent(ui));
+                    let path = vec![name.clone()];
+                    let response = render_base_types::tree_header(ui, format!("[{name}]"), &path)
+                        .show(ui, |ui| {
+                            render_entries(ui, doc, &section.entries, Some(name), &mut to_remove, modified, scroll_marker_key);
+                            render_new_entry(ui, doc, Some(name), modified, scroll_marker_key);
+                        });
+                    render_base_types::record_open(ui, &path, &response);
+                });
+            }
+        }
+    }
+
+    if let Some(index) = to_remove {
+        doc.remove_entry(index);
+        *modified = true;
+    }
+
+    if doc.dialect == IniDialect::Ini {
+        render_new_section(ui, doc, modified, scroll_marker_key);
+    }
+}
+
+fn render_entries(
+    ui: &mut egui::Ui,
+    doc: &mut IniDocument,
+    entries: &[usize],
+    section: Option<&str>,
+    to_remove: &mut Option<usize>,
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+) {
+    for &index in entries {
+        let Some(entry) = doc.entry_mut(index) else {
+            continue;
+        };
+        let full_key = match section {
+            Some(name) => format!("{name}.{}", entry.key),
+            None => entry.key.clone(),
+        };
+        let path: Vec<String> = section.into_iter().map(str::to_string).chain([entry.key.clone()]).collect();
+        render_base_types::lazy_row(ui, &path, |ui| {
+            let row = ui.horizontal(|ui| {
+                ui.add_space(render_base_types::indent(ui));
+                ui.label(format!("{}:", entry.key));
+                let mut value = entry.value.clone();
+                let editor = if value.contains('\n') {
+                    egui::TextEdit::multiline(&mut value)
+                } else {
+                    egui::TextEdit::singleline(&mut value)
+                };
+                if render_base_types::editable(ui, |ui| ui.add(editor)).changed() {
+                    entry.value = value;
+                    *modified = true;
+                    *scroll_marker_key = Some(full_key);
+                }
+                if !render_base_types::is_read_only(ui) && ui.button("\u{274C}").on_hover_text("Remove").clicked() {
+                    *to_remove = Some(index);
+                }
+            });
+            render_base_types::highlight_jump_target(ui, &path, row.response.rect);
+        });
+    }
+}
+
+fn render_new_entry(
+    ui: &mut egui::Ui,
+    doc: &mut IniDocument,
+    section: Option<&str>,
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+) {
+    if render_base_types::is_read_only(ui) {
+        return;
+    }
+    use egui::TextEdit;
+
+    let key_id = egui::Id::new("new_ini_key_input").with(ui.id()).with(section);
+    let value_id = egui::Id::new("new_ini_value_input").with(ui.id()).with(section);
+    let notice_path = vec![section.unwrap_or_default().to_string(), "+".to_string()];
+
+    egui::CollapsingHeader::new("Add new field")
+        .id_salt(("ini_new_field", section))
+        .default_open(false)
+        .show(ui, |ui| {
+            let mut key_input = ui
+                .memory_mut(|mem| mem.data.get_temp::<String>(key_id))
+                .unwrap_or_default();
+            let mut value_input = ui
+                .memory_mut(|mem| mem.data.get_temp::<String>(value_id))
+                .unwrap_or_default();
+
+            ui.horizontal(|ui| {
+                ui.label("Key:");
+                let key_response = ui.add(TextEdit::singleline(&mut key_input).hint_text("key").desired_width(100.0));
+                ui.label("Value:");
+                let value_response = ui.add(TextEdit::singleline(&mut value_input).hint_text("value").desired_width(100.0));
+
+                let key = key_input.trim().to_string();
+                if (key_response.lost_focus() || value_response.lost_focus())
+                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                    && !key.is_empty()
+                {
+                    if doc.find(section, &key).is_some() {
+                        render_base_types::show_notice(ui, &notice_path, format!("key {key} already exists"), true);
+                    } else {
+                        doc.add_entry(section, &key, &value_input);
+                        *modified = true;
+                        *scroll_marker_key = Some(key);
+                        key_input.clear();
+                        value_input.clear();
+                    }
+                }
+            });
+
+            render_base_types::render_conversion_notice(ui, &notice_path);
+
+            ui.memory_mut(|mem| {
+                mem.data.insert_temp(key_id, key_input);
+                mem.data.insert_temp(value_id, value_input);
+            });
+        });
+}
+
+fn render_new_section(
+    ui: &mut egui::Ui,
+    doc: &mut IniDocument,
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+) {
+    if render_base_types::is_read_only(ui) {
+        return;
+    }
+    let name_id = egui::Id::new("new_ini_section_input").with(ui.id());
+    let mut name_input = ui
+        .memory_mut(|mem| mem.data.get_temp::<String>(name_id))
+        .unwrap_or_default();
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label("New section:");
+        let response = ui.add(egui::TextEdit::singleline(&mut name_input).hint_text("name").desired_width(100.0));
+        let name = name_input.trim().to_string();
+        let exists = doc.sections().iter().any(|s| s.name.as_deref() == Some(name.as_str()));
+        if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) && !name.is_empty() && !exists {
+            doc.add_section(&name);
+            *modified = true;
+            *scroll_marker_key = Some(name);
+            name_input.clear();
+        }
+    });
+
+    ui.memory_mut(|mem| mem.data.insert_temp(name_id, name_input));
+}
@@ -1,7 +1,17 @@
 use eframe::egui;
-use serde_json;
-use serde_json::Value;
-use crate::{INDENT_SPACES, UI_SPACE};
+use serde_json::{Map, Value};
+use crate::UI_SPACE;
+use crate::render_base_types::{self, Converted, NodeType, Scalar};
+
+const JSON_TYPES: &[NodeType] = &[
+    NodeType::String,
+    NodeType::Integer,
+    NodeType::Float,
+    NodeType::Boolean,
+    NodeType::Null,
+    NodeType::Map,
+    NodeType::List,
+];
 
 pub enum EditableValueRef<'a> {
     Json(&'a mut Value),
@@ -23,29 +33,37 @@ pub(crate) fn render_json_value_with_tracking(
                 new_path.push(key.clone());
                 let full_key = new_path.join(".");
 
-                ui.horizontal(|ui| {
-                    ui.add_space(INDENT_SPACES);
-                    match v {
-                        Value::Object(_) | Value::Array(_) => {
-                            egui::CollapsingHeader::new(key)
-                                .default_open(false)
-                                .show(ui, |ui| {
-                                    render_json_value_with_tracking(
-                                        ui, v, modified, scroll_marker_key, _content, new_path,
-                                    );
-                                });
-                        }
-                        _ => {
-                            ui.label(format!("{}:", key));
-                            render_editable_value(
-                                ui,
-                                EditableValueRef::Json(v),
-                                &full_key,
-                                modified,
-                                scroll_marker_key,
-                            );
+                render_base_types::lazy_row(ui, &new_path, |ui| {
+                    let row = ui.horizontal(|ui| {
+                        ui.add_space(render_base_types::indent(ui));
+                        match v {
+                            Value::Object(_) | Value::Array(_) => {
+                                let response = render_base_types::tree_header(ui, key, &new_path)
+                                    .show(ui, |ui| {
+                                        ui.horizontal(|ui| {
+                                            ui.label("type:");
+                                            render_json_type_selector(ui, v, &full_key, &new_path, modified, scroll_marker_key);
+                                        });
+                                        render_json_value_with_tracking(
+                                            ui, v, modified, scroll_marker_key, _content, new_path.clone(),
+                                        );
+                                    });
+                                render_base_types::record_open(ui, &new_path, &response);
+                            }
+                            _ => {
+                                ui.label(format!("{}:", key));
+                                render_base_types::editable(ui, |ui| render_editable_value(
+                                    ui,
+                                    EditableValueRef::Json(v),
+                                    &full_key,
+                                    modified,
+                                    scroll_marker_key,
+                                ));
+                                render_json_type_selector(ui, v, &full_key, &new_path, modified, scroll_marker_key);
+                            }
                         }
-                    }
+                    });
+                    render_base_types::highlight_jump_target(ui, &new_path, row.response.rect);
                 });
             }
         }
@@ -55,15 +73,120 @@ pub(crate) fn render_json_value_with_tracking(
         }
 
         _ => {
-            render_editable_value(
+            let full_key = key_path.join(".");
+            render_base_types::editable(ui, |ui| render_editable_value(
                 ui,
                 EditableValueRef::Json(value),
-                &key_path.join("."),
+                &full_key,
                 modified,
                 scroll_marker_key,
-            );
+            ));
+            render_json_type_selector(ui, value, &full_key, &key_path, modified, scroll_marker_key);
+        }
+    }
+}
+
+fn render_json_type_selector(
+    ui: &mut egui::Ui,
+    value: &mut Value,
+    key: &str,
+    path: &[String],
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+) {
+    if let Some(target) = render_base_types::render_type_selector(ui, path, json_node_type(value), JSON_TYPES) {
+        if render_base_types::apply_conversion(ui, path, convert_json_value(value, target), value) {
+            *modified = true;
+            *scroll_marker_key = Some(key.to_string());
         }
     }
+    render_base_types::render_conversion_notice(ui, path);
+}
+
+fn json_node_type(value: &Value) -> NodeType {
+    match value {
+        Value::Object(_) => NodeType::Map,
+        Value::Array(_) => NodeType::List,
+        // Integers past i64::MAX only fit a float, as in `convert.rs`.
+        Value::Number(n) if n.as_i64().is_some() => NodeType::Integer,
+        Value::Number(_) => NodeType::Float,
+        Value::String(_) => NodeType::String,
+        Value::Bool(_) => NodeType::Boolean,
+        Value::Null => NodeType::Null,
+    }
+}
+
+pub(crate) fn json_to_scalar(value: &Value) -> Option<Scalar> {
+    match value {
+        Value::Null => Some(Scalar::Null),
+        Value::Bool(b) => Some(Scalar::Boolean(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Some(Scalar::Integer(i)),
+            None => Some(Scalar::Float(n.to_string())),
+        },
+        Value::String(s) => Some(Scalar::String(s.clone())),
+        Value::Object(_) | Value::Array(_) => None,
+    }
+}
+
+pub(crate) fn json_from_scalar(scalar: Scalar) -> Result<Value, String> {
+    match scalar {
+        Scalar::Null => Ok(Value::Null),
+        Scalar::Boolean(b) => Ok(Value::Bool(b)),
+        Scalar::Integer(i) => Ok(Value::from(i)),
+        Scalar::Float(s) => render_base_types::parse_float(&s)
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| format!("{s} cannot be stored in JSON")),
+        Scalar::String(s) | Scalar::Datetime(s) => Ok(Value::String(s)),
+    }
+}
+
+fn json_from_converted(converted: Converted<Scalar>) -> Result<Converted<Value>, String> {
+    Ok(Converted { value: json_from_scalar(converted.value)?, warning: converted.warning })
+}
+
+/// Converts a JSON node to `target`, following the same coercion rules as the YAML tree.
+pub(crate) fn convert_json_value(value: &Value, target: NodeType) -> Result<Converted<Value>, String> {
+    match (value, target) {
+        (Value::Object(_), NodeType::Map) | (Value::Array(_), NodeType::List) => Ok(Converted::exact(value.clone())),
+        (Value::Object(map), NodeType::List) => {
+            let items: Vec<Value> = map.values().cloned().collect();
+            if map.is_empty() {
+                Ok(Converted::exact(Value::Array(items)))
+            } else {
+                Ok(Converted::lossy(Value::Array(items), format!("{} keys discarded", map.len())))
+            }
+        }
+        (Value::Array(arr), NodeType::Map) => {
+            let map: Map<String, Value> = arr
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (i.to_string(), v.clone()))
+                .collect();
+            Ok(Converted::exact(Value::Object(map)))
+        }
+        (Value::Object(map), _) => {
+            let text = serde_json::to_string(value).unwrap_or_default();
+            json_from_converted(render_base_types::collapse_collection(NodeType::Map, map.len(), text, target)?)
+        }
+        (Value::Array(arr), _) => {
+            let text = serde_json::to_string(value).unwrap_or_default();
+            json_from_converted(render_base_types::collapse_collection(NodeType::List, arr.len(), text, target)?)
+        }
+        (Value::Null, NodeType::Map) => Ok(Converted::exact(Value::Object(Map::new()))),
+        (Value::Null, NodeType::List) => Ok(Converted::exact(Value::Array(vec![]))),
+        (_, NodeType::Map) => {
+            let mut map = Map::new();
+            map.insert("value".to_string(), value.clone());
+            Ok(Converted::exact(Value::Object(map)))
+        }
+        (_, NodeType::List) => Ok(Converted::exact(Value::Array(vec![value.clone()]))),
+        _ => match json_to_scalar(value) {
+            Some(scalar) => json_from_converted(render_base_types::coerce_scalar(scalar, target)?),
+            None => Err(format!("{value} cannot be converted")),
+        },
+    }
 }
 
 fn render_editable_value(
@@ -83,6 +206,16 @@ fn render_editable_value(
                     *scroll_marker_key = Some(key.to_string());
                 }
             }
+            Value::Number(n) if n.as_i64().is_some() => {
+                if let Some(i) = n.as_i64() {
+                    let mut temp = i;
+                    if ui.add(egui::DragValue::new(&mut temp)).changed() {
+                        *val = Value::from(temp);
+                        *modified = true;
+                        *scroll_marker_key = Some(key.to_string());
+                    }
+                }
+            }
             Value::Number(n) => {
                 if let Some(f) = n.as_f64() {
                     let mut temp = f;
@@ -120,24 +253,32 @@ fn render_array(
     content: &str,
 ) {
     let mut to_remove = None;
-    for (i, elem) in arr.iter_mut().enumerate() {
+    let shown = render_base_types::shown_items(ui, &key_path, arr.len());
+    for (i, elem) in arr.iter_mut().enumerate().take(shown) {
         let mut path = key_path.clone();
         path.push(i.to_string());
 
-        ui.horizontal(|ui| {
-            ui.add_space(INDENT_SPACES);
-            render_json_value_with_tracking(ui, elem, modified, scroll_marker_key, content, path);
-            if ui.button("\u{274C}").on_hover_text("Remove").clicked() {
-                to_remove = Some(i);
-            }
+        render_base_types::lazy_row(ui, &path, |ui| {
+            let row = ui.horizontal(|ui| {
+                ui.add_space(render_base_types::indent(ui));
+                render_json_value_with_tracking(ui, elem, modified, scroll_marker_key, content, path.clone());
+                if !render_base_types::is_read_only(ui) && ui.button("\u{274C}").on_hover_text("Remove").clicked() {
+                    to_remove = Some(i);
+                }
+            });
+            render_base_types::highlight_jump_target(ui, &path, row.response.rect);
         });
     }
+    render_base_types::render_more_items(ui, &key_path, shown, arr.len());
 
     if let Some(i) = to_remove {
         arr.remove(i);
         *modified = true;
     }
 
+    if render_base_types::is_read_only(ui) {
+        return;
+    }
     ui.add_space(UI_SPACE);
     if ui.button("+ Add element").clicked() {
         arr.push(Value::Null);
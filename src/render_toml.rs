@@ -1,6 +1,17 @@
-use crate::toml::Value;
+use toml::Value;
 use eframe::egui;
-use crate::{INDENT_SPACES};
+use crate::render_base_types::{self, Converted, NodeType, Scalar};
+use toml::value::{Array, Datetime, Table};
+
+const TOML_TYPES: &[NodeType] = &[
+    NodeType::String,
+    NodeType::Integer,
+    NodeType::Float,
+    NodeType::Boolean,
+    NodeType::Datetime,
+    NodeType::Map,
+    NodeType::List,
+];
 
 pub(crate) fn render_toml_value_with_tracking(
     ui: &mut egui::Ui,
@@ -12,89 +23,198 @@ pub(crate) fn render_toml_value_with_tracking(
 ) {
     match value {
         Value::Table(table) => {
-            // Mutable statics for new key/val input fields
-            static mut NEW_KEY: String = String::new();
-            static mut NEW_VAL: String = String::new();
             for (key, v) in table.iter_mut() {
                 let mut new_path = key_path.clone();
                 new_path.push(key.to_string());
-                let full_key = key.to_string();
-
-                ui.horizontal(|ui| {
-                    ui.add_space(INDENT_SPACES);
-                    match v {
-                        Value::Table(_) | Value::Array(_) => {
-                            egui::CollapsingHeader::new(&full_key)
-                                .default_open(false)
-                                .show(ui, |ui| {
-                                    render_toml_value_with_tracking(
-                                        ui, v, modified, scroll_marker_key, content, new_path,
-                                    );
-                                });
-                        }
-                        _ => {
-                            ui.label(format!("{}:", full_key));
-                            render_editable_toml_value(ui, v, &full_key, modified, scroll_marker_key);
-                        }
-                    }
-                });
+                render_toml_entry(ui, key, v, modified, scroll_marker_key, content, new_path);
+            }
+            if render_base_types::is_read_only(ui) {
+                return;
             }
             // Section for adding a new key-value pair
+            let key_id = egui::Id::new("new_toml_key_input").with(ui.id());
+            let value_id = egui::Id::new("new_toml_value_input").with(ui.id());
+            let mut new_key = ui
+                .memory_mut(|mem| mem.data.get_temp::<String>(key_id))
+                .unwrap_or_default();
+            let mut new_val = ui
+                .memory_mut(|mem| mem.data.get_temp::<String>(value_id))
+                .unwrap_or_default();
             ui.separator();
             ui.horizontal(|ui| {
-                unsafe {
-                    ui.colored_label(egui::Color32::GRAY, "New Key:");
-                    let key_changed = ui.text_edit_singleline(&mut NEW_KEY).changed();
-                    ui.colored_label(egui::Color32::GRAY, "Value:");
-                    let val_changed = ui.text_edit_singleline(&mut NEW_VAL).changed();
-                    if key_changed || val_changed {
-                        if !NEW_KEY.trim().is_empty() && !table.contains_key(&NEW_KEY) {
-                            if let Some(parsed_val) = value_from_string(&NEW_VAL) {
-                                table.insert(NEW_KEY.clone(), parsed_val);
-                                *modified = true;
-                                *scroll_marker_key = Some(NEW_KEY.clone());
-                                NEW_KEY.clear();
-                                NEW_VAL.clear();
-                            }
-                        }
+                ui.colored_label(egui::Color32::GRAY, "New Key:");
+                let key_changed = ui.text_edit_singleline(&mut new_key).changed();
+                ui.colored_label(egui::Color32::GRAY, "Value:");
+                let val_changed = ui.text_edit_singleline(&mut new_val).changed();
+                if (key_changed || val_changed) && !new_key.trim().is_empty() && !table.contains_key(&new_key) {
+                    if let Some(parsed_val) = value_from_string(&new_val) {
+                        table.insert(new_key.clone(), parsed_val);
+                        *modified = true;
+                        *scroll_marker_key = Some(new_key.clone());
+                        new_key.clear();
+                        new_val.clear();
                     }
                 }
             });
+            ui.memory_mut(|mem| {
+                mem.data.insert_temp(key_id, new_key);
+                mem.data.insert_temp(value_id, new_val);
+            });
         }
         Value::Array(arr) => {
-            for (idx, v) in arr.iter_mut().enumerate() {
+            let shown = render_base_types::shown_items(ui, &key_path, arr.len());
+            for (idx, v) in arr.iter_mut().enumerate().take(shown) {
                 let mut new_path = key_path.clone();
                 new_path.push(idx.to_string());
-                let full_key = idx.to_string();
-
-                ui.horizontal(|ui| {
-                    ui.add_space(INDENT_SPACES);
-                    match v {
-                        Value::Table(_) | Value::Array(_) => {
-                            egui::CollapsingHeader::new(&full_key)
-                                .default_open(false)
-                                .show(ui, |ui| {
-                                    render_toml_value_with_tracking(
-                                        ui, v, modified, scroll_marker_key, content, new_path,
-                                    );
-                                });
-                        }
-                        _ => {
-                            ui.label(format!("{}:", full_key));
-                            render_editable_toml_value(ui, v, &full_key, modified, scroll_marker_key);
-                        }
-                    }
-                });
+                render_toml_entry(ui, &idx.to_string(), v, modified, scroll_marker_key, content, new_path);
             }
+            render_base_types::render_more_items(ui, &key_path, shown, arr.len());
         }
         _ => {}
     }
 }
 
+fn render_toml_entry(
+    ui: &mut egui::Ui,
+    full_key: &str,
+    v: &mut Value,
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+    content: &str,
+    path: Vec<String>,
+) {
+    render_base_types::lazy_row(ui, &path, |ui| {
+        let row = ui.horizontal(|ui| {
+            ui.add_space(render_base_types::indent(ui));
+            match v {
+                Value::Table(_) | Value::Array(_) => {
+                    let response = render_base_types::tree_header(ui, full_key, &path)
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.label("type:");
+                                render_toml_type_selector(ui, v, full_key, &path, modified, scroll_marker_key);
+                            });
+                            render_toml_value_with_tracking(
+                                ui, v, modified, scroll_marker_key, content, path.clone(),
+                            );
+                        });
+                    render_base_types::record_open(ui, &path, &response);
+                }
+                _ => {
+                    ui.label(format!("{}:", full_key));
+                    render_base_types::editable(ui, |ui| render_editable_toml_value(ui, v, full_key, &path, modified, scroll_marker_key));
+                    render_toml_type_selector(ui, v, full_key, &path, modified, scroll_marker_key);
+                }
+            }
+        });
+        render_base_types::highlight_jump_target(ui, &path, row.response.rect);
+    });
+}
+
+fn render_toml_type_selector(
+    ui: &mut egui::Ui,
+    value: &mut Value,
+    key: &str,
+    path: &[String],
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+) {
+    if let Some(target) = render_base_types::render_type_selector(ui, path, toml_node_type(value), TOML_TYPES) {
+        if render_base_types::apply_conversion(ui, path, convert_toml_value(value, target), value) {
+            *modified = true;
+            *scroll_marker_key = Some(key.to_string());
+        }
+    }
+    render_base_types::render_conversion_notice(ui, path);
+}
+
+fn toml_node_type(value: &Value) -> NodeType {
+    match value {
+        Value::Table(_) => NodeType::Map,
+        Value::Array(_) => NodeType::List,
+        Value::String(_) => NodeType::String,
+        Value::Integer(_) => NodeType::Integer,
+        Value::Float(_) => NodeType::Float,
+        Value::Boolean(_) => NodeType::Boolean,
+        Value::Datetime(_) => NodeType::Datetime,
+    }
+}
+
+pub(crate) fn toml_to_scalar(value: &Value) -> Option<Scalar> {
+    match value {
+        Value::String(s) => Some(Scalar::String(s.clone())),
+        Value::Integer(i) => Some(Scalar::Integer(*i)),
+        Value::Float(f) => Some(Scalar::Float(render_base_types::format_float(*f))),
+        Value::Boolean(b) => Some(Scalar::Boolean(*b)),
+        Value::Datetime(d) => Some(Scalar::Datetime(d.to_string())),
+        Value::Table(_) | Value::Array(_) => None,
+    }
+}
+
+pub(crate) fn toml_from_scalar(scalar: Scalar) -> Result<Value, String> {
+    match scalar {
+        Scalar::Null => Err("TOML has no null value".to_string()),
+        Scalar::Boolean(b) => Ok(Value::Boolean(b)),
+        Scalar::Integer(i) => Ok(Value::Integer(i)),
+        Scalar::Float(s) => render_base_types::parse_float(&s)
+            .map(Value::Float)
+            .ok_or_else(|| format!("\"{s}\" is not a float")),
+        Scalar::String(s) => Ok(Value::String(s)),
+        Scalar::Datetime(s) => s
+            .parse::<Datetime>()
+            .map(Value::Datetime)
+            .map_err(|_| format!("\"{s}\" is not a TOML datetime")),
+    }
+}
+
+fn toml_from_converted(converted: Converted<Scalar>) -> Result<Converted<Value>, String> {
+    Ok(Converted { value: toml_from_scalar(converted.value)?, warning: converted.warning })
+}
+
+/// Converts a TOML node to `target`, following the same coercion rules as the YAML tree.
+pub(crate) fn convert_toml_value(value: &Value, target: NodeType) -> Result<Converted<Value>, String> {
+    match (value, target) {
+        (Value::Table(_), NodeType::Map) | (Value::Array(_), NodeType::List) => Ok(Converted::exact(value.clone())),
+        (Value::Table(table), NodeType::List) => {
+            let items: Array = table.values().cloned().collect();
+            if table.is_empty() {
+                Ok(Converted::exact(Value::Array(items)))
+            } else {
+                Ok(Converted::lossy(Value::Array(items), format!("{} keys discarded", table.len())))
+            }
+        }
+        (Value::Array(arr), NodeType::Map) => {
+            let table: Table = arr
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (i.to_string(), v.clone()))
+                .collect();
+            Ok(Converted::exact(Value::Table(table)))
+        }
+        (Value::Table(table), _) => {
+            toml_from_converted(render_base_types::collapse_collection(NodeType::Map, table.len(), value.to_string(), target)?)
+        }
+        (Value::Array(arr), _) => {
+            toml_from_converted(render_base_types::collapse_collection(NodeType::List, arr.len(), value.to_string(), target)?)
+        }
+        (_, NodeType::Map) => {
+            let mut table = Table::new();
+            table.insert("value".to_string(), value.clone());
+            Ok(Converted::exact(Value::Table(table)))
+        }
+        (_, NodeType::List) => Ok(Converted::exact(Value::Array(vec![value.clone()]))),
+        _ => match toml_to_scalar(value) {
+            Some(scalar) => toml_from_converted(render_base_types::coerce_scalar(scalar, target)?),
+            None => Err(format!("{value} cannot be converted")),
+        },
+    }
+}
+
 fn render_editable_toml_value(
     ui: &mut egui::Ui,
     value: &mut Value,
     key: &str,
+    path: &[String],
     modified: &mut bool,
     scroll_marker_key: &mut Option<String>,
 ) {
@@ -131,9 +251,45 @@ fn render_editable_toml_value(
                 *scroll_marker_key = Some(key.to_string());
             }
         }
+        Value::Datetime(d) => {
+            if let Some(parsed) = render_datetime_editor(ui, d, path) {
+                *value = Value::Datetime(parsed);
+                *modified = true;
+                *scroll_marker_key = Some(key.to_string());
+            }
+        }
         _ => {}
     }
 }
+fn datetime_edit_id() -> egui::Id {
+    egui::Id::new("toml_datetime_edit")
+}
+
+/// Text field for a datetime. Half-typed text stays in the field until Enter is pressed or the
+/// field loses focus; the new datetime is returned then, or the edit dropped if it does not parse.
+fn render_datetime_editor(ui: &mut egui::Ui, datetime: &Datetime, path: &[String]) -> Option<Datetime> {
+    let buffer = ui
+        .data(|data| data.get_temp::<(Vec<String>, String)>(datetime_edit_id()))
+        .filter(|(editing, _)| editing == path)
+        .map(|(_, text)| text);
+    let mut text = buffer.clone().unwrap_or_else(|| datetime.to_string());
+    let response = ui.add(egui::TextEdit::singleline(&mut text));
+    let parsed = text.trim().parse::<Datetime>();
+    if let Err(err) = &parsed {
+        ui.colored_label(egui::Color32::RED, "⚠").on_hover_text(err.to_string());
+    }
+    if response.lost_focus() {
+        if buffer.is_some() {
+            ui.data_mut(|data| data.remove::<(Vec<String>, String)>(datetime_edit_id()));
+        }
+        return parsed.ok().filter(|parsed| parsed != datetime);
+    }
+    if response.changed() {
+        ui.data_mut(|data| data.insert_temp(datetime_edit_id(), (path.to_vec(), text)));
+    }
+    None
+}
+
 /// Unescapes TOML-style escape sequences in a string, such as `\n`, `\t`, `\\`, `\"`, and unicode escapes like `\uXXXX`.
 fn unescape_string(s: &str) -> String {
     let mut result = String::new();
@@ -180,6 +336,8 @@ fn value_from_string(input: &str) -> Option<Value> {
     if let Ok(float_val) = input.parse::<f64>() {
         return Some(Value::Float(float_val));
     }
-    let stripped = input.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(input);
-    Some(Value::String(stripped.to_string()))
+    match input.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(quoted) => Some(Value::String(unescape_string(quoted))),
+        None => Some(Value::String(input.to_string())),
+    }
 }
\ No newline at end of file
@@ -1,10 +1,23 @@
 use eframe::egui;
-use crate::{INDENT_SPACES};
+use crate::render_base_types::{self, Converted, NodeType, Scalar};
+use crate::yaml_document::{self, YamlMeta};
 use yaml_rust::Yaml;
+use yaml_rust::yaml::Hash;
+
+const YAML_TYPES: &[NodeType] = &[
+    NodeType::String,
+    NodeType::Integer,
+    NodeType::Float,
+    NodeType::Boolean,
+    NodeType::Null,
+    NodeType::Map,
+    NodeType::List,
+];
 
 pub(crate) fn render_yaml_value_with_tracking(
     ui: &mut egui::Ui,
     value: &mut Yaml,
+    meta: &mut YamlMeta,
     modified: &mut bool,
     scroll_marker_key: &mut Option<String>,
     content: &str,
@@ -12,35 +25,58 @@ pub(crate) fn render_yaml_value_with_tracking(
 ) {
     match value {
         Yaml::Hash(map) => {
+            let merge_sources = map
+                .get(&Yaml::String(yaml_document::MERGE_KEY.to_string()))
+                .map(|merge| yaml_document::merge_sources(merge, meta))
+                .unwrap_or_default();
+
+            let mut rename = None;
             for (k, v) in &mut *map {
-                if let Yaml::String(key_str) = k {
-                    let mut new_path = key_path.clone();
-                    new_path.push(key_str.clone());
-                    let full_key = key_str;
+                let mut new_path = key_path.clone();
+                new_path.push(yaml_document::key_segment(k));
+                if yaml_document::is_merge_key(k) {
+                    yaml_row(ui, &new_path, |ui| render_merge_key(ui, v, meta));
+                    continue;
+                }
+                let overrides = merge_sources.iter().find(|(_, source)| source.contains_key(k));
+                yaml_row(ui, &new_path.clone(), |ui| {
+                    if let Some((source, _)) = overrides {
+                        ui.label(egui::RichText::new("⤺").color(egui::Color32::LIGHT_BLUE))
+                            .on_hover_text(format!("Overrides the value merged from {}", merge_source_name(*source, meta)));
+                    }
+                    if let Some(new_key) = render_yaml_node(ui, Some(k), v, meta, modified, scroll_marker_key, content, new_path) {
+                        rename = Some((k.clone(), new_key));
+                    }
+                });
+            }
 
-                    ui.horizontal(|ui| {
-                        ui.add_space(INDENT_SPACES);
-                        match v {
-                            Yaml::Hash(_) | Yaml::Array(_) => {
-                                egui::CollapsingHeader::new(full_key)
-                                    .default_open(false)
-                                    .show(ui, |ui| {
-                                        render_yaml_value_with_tracking(
-                                            ui, v, modified, scroll_marker_key, content, new_path,
-                                        );
-                                    });
-                            }
-                            _ => {
-                                ui.label(format!("{}:", full_key));
-                                render_editable_yaml_value(
-                                    ui, v, full_key, modified, scroll_marker_key,
-                                );
-                            }
-                        }
-                    });
+            // Committing a key without changing it is not a rename.
+            if let Some((old_key, new_key)) = rename.filter(|(old_key, new_key)| old_key != new_key) {
+                let mut old_path = key_path.clone();
+                old_path.push(yaml_document::key_segment(&old_key));
+                let mut new_path = key_path.clone();
+                new_path.push(yaml_document::key_segment(&new_key));
+                if map.contains_key(&new_key) {
+                    render_base_types::show_notice(ui, &old_path, format!("key {} already exists", new_path.last().unwrap()), true);
+                } else if let Some(other) = yaml_document::clashing_key(map, &new_key, Some(&old_key)) {
+                    let message = format!("key {} cannot be told apart from {}", yaml_document::flow_text(&new_key), yaml_document::flow_text(other));
+                    render_base_types::show_notice(ui, &old_path, message, true);
+                } else {
+                    *map = std::mem::take(map)
+                        .into_iter()
+                        .map(|(k, v)| if k == old_key { (new_key.clone(), v) } else { (k, v) })
+                        .collect();
+                    meta.rename_path(&old_path, &new_path);
+                    *modified = true;
+                    *scroll_marker_key = new_path.last().cloned();
                 }
             }
 
+            render_inherited_fields(ui, map, &merge_sources, meta, &key_path);
+            if render_base_types::is_read_only(ui) {
+                return;
+            }
+
             use egui::TextEdit;
 
             let key_id = egui::Id::new("new_key_input").with(ui.id());
@@ -63,30 +99,22 @@ pub(crate) fn render_yaml_value_with_tracking(
                         ui.label("Value:");
                         let value_response = ui.add(TextEdit::singleline(&mut value_input).hint_text("value").desired_width(100.0));
 
+                        let new_key = Yaml::String(key_input.clone());
+                        let clash = yaml_document::clashing_key(map, &new_key, None);
+                        if let Some(other) = clash {
+                            ui.colored_label(egui::Color32::RED, "⚠")
+                                .on_hover_text(format!("cannot be told apart from the key {}", yaml_document::flow_text(other)));
+                        }
                         if (key_response.lost_focus() || value_response.lost_focus())
                             && ui.input(|i| i.key_pressed(egui::Key::Enter))
+                            && !key_input.trim().is_empty()
+                            && clash.is_none()
                         {
-                            if !key_input.trim().is_empty() {
-                                let inferred_value = if value_input.eq_ignore_ascii_case("true") {
-                                    Yaml::Boolean(true)
-                                } else if value_input.eq_ignore_ascii_case("false") {
-                                    Yaml::Boolean(false)
-                                } else if value_input.eq_ignore_ascii_case("null") {
-                                    Yaml::Null
-                                } else if let Ok(i) = value_input.parse::<i64>() {
-                                    Yaml::Integer(i)
-                                } else if let Ok(f) = value_input.parse::<f64>() {
-                                    Yaml::Real(f.to_string())
-                                } else {
-                                    Yaml::String(value_input.clone())
-                                };
-
-                                map.insert(Yaml::String(key_input.clone()), inferred_value);
-                                *modified = true;
-                                *scroll_marker_key = Some(key_input.clone());
-                                key_input.clear();
-                                value_input.clear();
-                            }
+                            map.insert(Yaml::String(key_input.clone()), infer_yaml_value(&value_input));
+                            *modified = true;
+                            *scroll_marker_key = Some(key_input.clone());
+                            key_input.clear();
+                            value_input.clear();
                         }
                     });
 
@@ -96,34 +124,402 @@ pub(crate) fn render_yaml_value_with_tracking(
                     });
                 });
         }
+        Yaml::Array(arr) => {
+            let shown = render_base_types::shown_items(ui, &key_path, arr.len());
+            for (idx, v) in arr.iter_mut().enumerate().take(shown) {
+                let mut new_path = key_path.clone();
+                new_path.push(idx.to_string());
+                yaml_row(ui, &new_path.clone(), |ui| {
+                    render_yaml_node(ui, None, v, meta, modified, scroll_marker_key, content, new_path);
+                });
+            }
+            render_base_types::render_more_items(ui, &key_path, shown, arr.len());
+        }
         _ => {}
     }
 }
 
+/// Lays out one indented tree row and scrolls to it when it is the target of an alias link.
+fn yaml_row(ui: &mut egui::Ui, path: &[String], add_contents: impl FnOnce(&mut egui::Ui)) {
+    render_base_types::lazy_row(ui, path, |ui| {
+        let row = ui.horizontal(|ui| {
+            ui.add_space(render_base_types::indent(ui));
+            add_contents(ui);
+        });
+        render_base_types::highlight_jump_target(ui, path, row.response.rect);
+    });
+}
+
+/// Renders one mapping entry or list item. `key` is the mapping key, if any, which can be
+/// renamed from the row; the new key is returned when the user commits a rename.
+#[allow(clippy::too_many_arguments)]
+fn render_yaml_node(
+    ui: &mut egui::Ui,
+    key: Option<&Yaml>,
+    v: &mut Yaml,
+    meta: &mut YamlMeta,
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+    content: &str,
+    path: Vec<String>,
+) -> Option<Yaml> {
+    let label = path.last().cloned().unwrap_or_default();
+    let badges = node_badges(meta, &path);
+    let mut renamed = None;
+    let editing_key = key.is_some() && key_edit_buffer(ui, &path).is_some();
+    if let Some(key) = key {
+        if editing_key {
+            renamed = render_key_editor(ui, &path);
+        } else if let Some(marker) = key_type_marker(key) {
+            render_base_types::render_badge(ui, marker, egui::Color32::GRAY)
+                .on_hover_text(format!("{marker} key"));
+        }
+    }
+    match v {
+        Yaml::Hash(_) | Yaml::Array(_) => {
+            let response = render_base_types::tree_header(ui, render_base_types::header_text(ui, &label, &badges), &path)
+                .show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("type:");
+                        render_yaml_type_selector(ui, v, &label, &path, modified, scroll_marker_key);
+                    });
+                    render_yaml_value_with_tracking(
+                        ui, v, meta, modified, scroll_marker_key, content, path.clone(),
+                    );
+                });
+            render_base_types::record_open(ui, &path, &response);
+            if let Some(key) = key {
+                key_context_menu(ui, &response.header_response, key, &path);
+            }
+        }
+        Yaml::Alias(id) => {
+            let id = *id;
+            render_key_label(ui, key, editing_key, &label, &path);
+            render_alias_link(ui, id, meta);
+            if let Some(target) = meta.anchored_values.get(&id).filter(|_| !render_base_types::is_read_only(ui)) {
+                if ui.small_button("⛓").on_hover_text("Replace the alias with an editable copy of its value").clicked() {
+                    *v = target.clone();
+                    *modified = true;
+                    *scroll_marker_key = Some(label.clone());
+                }
+            }
+        }
+        _ => {
+            render_key_label(ui, key, editing_key, &label, &path);
+            for (badge, color) in &badges {
+                render_base_types::render_badge(ui, badge, *color);
+            }
+            let multiline = meta.is_block_scalar(&path) || matches!(v, Yaml::String(s) if s.contains('\n'));
+            render_base_types::editable(ui, |ui| render_editable_yaml_value(
+                ui, v, &label, multiline, modified, scroll_marker_key,
+            ));
+            render_yaml_type_selector(ui, v, &label, &path, modified, scroll_marker_key);
+        }
+    }
+    renamed
+}
+
+fn key_edit_id() -> egui::Id {
+    egui::Id::new("yaml_key_edit")
+}
+
+/// The text of the key being renamed, if the key at `path` is in edit mode.
+fn key_edit_buffer(ui: &egui::Ui, path: &[String]) -> Option<String> {
+    ui.data(|data| data.get_temp::<(Vec<String>, String)>(key_edit_id()))
+        .filter(|(editing, _)| editing == path)
+        .map(|(_, text)| text)
+}
+
+fn start_key_edit(ui: &egui::Ui, key: &Yaml, path: &[String]) {
+    if render_base_types::is_read_only(ui) {
+        return;
+    }
+    let text = yaml_document::flow_text(key);
+    ui.data_mut(|data| data.insert_temp(key_edit_id(), (path.to_vec(), text)));
+}
+
+/// `key:` label of a scalar row; double-click or the context menu starts a rename.
+fn render_key_label(ui: &mut egui::Ui, key: Option<&Yaml>, editing_key: bool, label: &str, path: &[String]) {
+    if editing_key {
+        return;
+    }
+    let response = ui.add(egui::Label::new(format!("{}:", label)).sense(egui::Sense::click()));
+    if let Some(key) = key {
+        if response.double_clicked() {
+            start_key_edit(ui, key, path);
+        }
+        key_context_menu(ui, &response, key, path);
+    }
+}
+
+fn key_context_menu(ui: &egui::Ui, response: &egui::Response, key: &Yaml, path: &[String]) {
+    response.context_menu(|menu| {
+        if menu.button("Rename key").clicked() {
+            start_key_edit(ui, key, path);
+            menu.close_menu();
+        }
+    });
+}
+
+/// Inline editor for a key being renamed. The text is read as YAML, so `1` or `[a, b]` give
+/// integer or sequence keys and `"1"` a string. Returns the new key once Enter is pressed;
+/// leaving the field any other way cancels the rename.
+fn render_key_editor(ui: &mut egui::Ui, path: &[String]) -> Option<Yaml> {
+    let mut text = key_edit_buffer(ui, path)?;
+    let response = ui.add(egui::TextEdit::singleline(&mut text).desired_width(100.0));
+    let parsed = yaml_document::parse_key(&text);
+    if let Err(err) = &parsed {
+        ui.colored_label(egui::Color32::RED, "⚠").on_hover_text(err);
+    }
+    if response.lost_focus() {
+        let committed = ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if !committed || parsed.is_ok() {
+            ui.data_mut(|data| data.remove::<(Vec<String>, String)>(key_edit_id()));
+            return parsed.ok().filter(|_| committed);
+        }
+    } else if !response.has_focus() {
+        response.request_focus();
+    }
+    ui.data_mut(|data| data.insert_temp(key_edit_id(), (path.to_vec(), text)));
+    None
+}
+
+/// Type marker shown before keys that are not plain strings.
+fn key_type_marker(key: &Yaml) -> Option<&'static str> {
+    match key {
+        Yaml::String(_) => None,
+        Yaml::Integer(_) => Some("int"),
+        Yaml::Real(_) => Some("float"),
+        Yaml::Boolean(_) => Some("bool"),
+        Yaml::Null => Some("null"),
+        Yaml::Array(_) => Some("list"),
+        Yaml::Hash(_) => Some("map"),
+        Yaml::Alias(_) => Some("alias"),
+        Yaml::BadValue => Some("invalid"),
+    }
+}
+
+/// The `&anchor` and `!tag` badges shown next to a node's key.
+fn node_badges(meta: &YamlMeta, path: &[String]) -> Vec<(String, egui::Color32)> {
+    let mut badges = vec![];
+    if let Some(&id) = meta.anchors.get(path) {
+        badges.push((format!("&{}", meta.anchor_name(id)), egui::Color32::LIGHT_BLUE));
+    }
+    if let Some(tag) = meta.tags.get(path) {
+        badges.push((tag.clone(), egui::Color32::from_rgb(214, 160, 80)));
+    }
+    badges
+}
+
+/// Draws `*name` as a link that jumps to the anchored node, with its value on hover.
+fn render_alias_link(ui: &mut egui::Ui, id: usize, meta: &YamlMeta) {
+    let preview = meta
+        .anchored_values
+        .get(&id)
+        .map(yaml_document::emit_value)
+        .unwrap_or_else(|| "(unresolved alias)".to_string());
+    if ui.link(format!("*{}", meta.anchor_name(id))).on_hover_text(preview).clicked() {
+        if let Some(path) = meta.anchor_path(id) {
+            render_base_types::set_jump_target(ui.ctx(), path.clone());
+        }
+    }
+}
+
+fn merge_source_name(source: Option<usize>, meta: &YamlMeta) -> String {
+    match source {
+        Some(id) => format!("*{}", meta.anchor_name(id)),
+        None => "an inline mapping".to_string(),
+    }
+}
+
+fn render_merge_key(ui: &mut egui::Ui, value: &Yaml, meta: &YamlMeta) {
+    ui.label(format!("{}:", yaml_document::MERGE_KEY)).on_hover_text("Merge key: inherits the fields of the listed mappings");
+    ui.label(egui::RichText::new("merges").italics().weak());
+    let aliases: Vec<usize> = match value {
+        Yaml::Alias(id) => vec![*id],
+        Yaml::Array(items) => items.iter().filter_map(|item| match item {
+            Yaml::Alias(id) => Some(*id),
+            _ => None,
+        }).collect(),
+        _ => vec![],
+    };
+    if aliases.is_empty() {
+        ui.label(yaml_document::emit_value(value));
+    }
+    for id in aliases {
+        render_alias_link(ui, id, meta);
+    }
+}
+
+/// Shows the fields a mapping inherits through `<<` without overriding them, read-only and dimmed.
+fn render_inherited_fields(
+    ui: &mut egui::Ui,
+    map: &Hash,
+    merge_sources: &[(Option<usize>, Hash)],
+    meta: &YamlMeta,
+    key_path: &[String],
+) {
+    let mut shown: Vec<&Yaml> = vec![];
+    for (source, entries) in merge_sources {
+        for (k, v) in entries {
+            if map.contains_key(k) || shown.contains(&k) {
+                continue;
+            }
+            shown.push(k);
+            let mut path = key_path.to_vec();
+            path.push(yaml_document::key_segment(k));
+            yaml_row(ui, &path, |ui| {
+                ui.label(egui::RichText::new(format!("{}:", yaml_document::key_segment(k))).italics().weak());
+                ui.label(egui::RichText::new(yaml_document::emit_value(v)).italics().weak());
+                ui.label(egui::RichText::new("inherited from").small().weak());
+                match source {
+                    Some(id) => render_alias_link(ui, *id, meta),
+                    None => {
+                        ui.label(egui::RichText::new(merge_source_name(None, meta)).small().weak());
+                    }
+                }
+            });
+        }
+    }
+}
+
+fn render_yaml_type_selector(
+    ui: &mut egui::Ui,
+    value: &mut Yaml,
+    key: &str,
+    path: &[String],
+    modified: &mut bool,
+    scroll_marker_key: &mut Option<String>,
+) {
+    let Some(current) = yaml_node_type(value) else {
+        return;
+    };
+    if let Some(target) = render_base_types::render_type_selector(ui, path, current, YAML_TYPES) {
+        if render_base_types::apply_conversion(ui, path, convert_yaml_value(value, target), value) {
+            *modified = true;
+            *scroll_marker_key = Some(key.to_string());
+        }
+    }
+    render_base_types::render_conversion_notice(ui, path);
+}
+
+pub(crate) fn yaml_node_type(value: &Yaml) -> Option<NodeType> {
+    match value {
+        Yaml::Hash(_) => Some(NodeType::Map),
+        Yaml::Array(_) => Some(NodeType::List),
+        _ => yaml_to_scalar(value).map(|s| s.node_type()),
+    }
+}
+
+pub(crate) fn yaml_to_scalar(value: &Yaml) -> Option<Scalar> {
+    match value {
+        Yaml::Null => Some(Scalar::Null),
+        Yaml::Boolean(b) => Some(Scalar::Boolean(*b)),
+        Yaml::Integer(i) => Some(Scalar::Integer(*i)),
+        Yaml::Real(s) => Some(Scalar::Float(s.clone())),
+        Yaml::String(s) => Some(Scalar::String(s.clone())),
+        _ => None,
+    }
+}
+
+pub(crate) fn yaml_from_scalar(scalar: Scalar) -> Yaml {
+    match scalar {
+        Scalar::Null => Yaml::Null,
+        Scalar::Boolean(b) => Yaml::Boolean(b),
+        Scalar::Integer(i) => Yaml::Integer(i),
+        Scalar::Float(s) => Yaml::Real(s),
+        Scalar::String(s) | Scalar::Datetime(s) => Yaml::String(s),
+    }
+}
+
+/// Converts a YAML node to `target`. Scalars become single-entry lists or `value:` maps,
+/// lists become maps keyed by index, and maps lose their keys when turned into lists.
+pub(crate) fn convert_yaml_value(value: &Yaml, target: NodeType) -> Result<Converted<Yaml>, String> {
+    match (value, target) {
+        (Yaml::Hash(_), NodeType::Map) | (Yaml::Array(_), NodeType::List) => Ok(Converted::exact(value.clone())),
+        (Yaml::Hash(map), NodeType::List) => {
+            let items: Vec<Yaml> = map.values().cloned().collect();
+            if map.is_empty() {
+                Ok(Converted::exact(Yaml::Array(items)))
+            } else {
+                Ok(Converted::lossy(Yaml::Array(items), format!("{} keys discarded", map.len())))
+            }
+        }
+        (Yaml::Array(arr), NodeType::Map) => {
+            let map: Hash = arr
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (Yaml::String(i.to_string()), v.clone()))
+                .collect();
+            Ok(Converted::exact(Yaml::Hash(map)))
+        }
+        (Yaml::Hash(map), _) => {
+            render_base_types::collapse_collection(NodeType::Map, map.len(), yaml_document::emit_value(value), target)
+                .map(|c| c.map(yaml_from_scalar))
+        }
+        (Yaml::Array(arr), _) => {
+            render_base_types::collapse_collection(NodeType::List, arr.len(), yaml_document::emit_value(value), target)
+                .map(|c| c.map(yaml_from_scalar))
+        }
+        (Yaml::Null, NodeType::Map) => Ok(Converted::exact(Yaml::Hash(Hash::new()))),
+        (Yaml::Null, NodeType::List) => Ok(Converted::exact(Yaml::Array(vec![]))),
+        (_, NodeType::Map) => {
+            let mut map = Hash::new();
+            map.insert(Yaml::String("value".to_string()), value.clone());
+            Ok(Converted::exact(Yaml::Hash(map)))
+        }
+        (_, NodeType::List) => Ok(Converted::exact(Yaml::Array(vec![value.clone()]))),
+        _ => match yaml_to_scalar(value) {
+            Some(scalar) => render_base_types::coerce_scalar(scalar, target).map(|c| c.map(yaml_from_scalar)),
+            None => Err(format!("{:?} cannot be converted", value)),
+        },
+    }
+}
+
+fn infer_yaml_value(input: &str) -> Yaml {
+    if input.eq_ignore_ascii_case("true") {
+        Yaml::Boolean(true)
+    } else if input.eq_ignore_ascii_case("false") {
+        Yaml::Boolean(false)
+    } else if input.eq_ignore_ascii_case("null") {
+        Yaml::Null
+    } else if let Ok(i) = input.parse::<i64>() {
+        Yaml::Integer(i)
+    } else if render_base_types::parse_float(input).is_some() {
+        Yaml::Real(input.trim().to_string())
+    } else {
+        Yaml::String(input.to_string())
+    }
+}
+
 fn render_editable_yaml_value(
     ui: &mut egui::Ui,
     value: &mut Yaml,
     key: &str,
+    multiline: bool,
     modified: &mut bool,
     scroll_marker_key: &mut Option<String>,
 ) {
     match value {
         Yaml::String(s) => {
             let mut val = s.clone();
-            if ui.add(egui::TextEdit::singleline(&mut val)).changed() {
+            let editor = if multiline {
+                egui::TextEdit::multiline(&mut val)
+                    .desired_rows(s.lines().count().max(2))
+                    .font(egui::TextStyle::Monospace)
+            } else {
+                egui::TextEdit::singleline(&mut val)
+            };
+            if ui.add(editor).changed() {
                 *value = Yaml::String(val);
                 *modified = true;
                 *scroll_marker_key = Some(key.to_string());
             }
         }
         Yaml::Real(s) => {
-            if let Ok(f) = s.parse::<f64>() {
-                let mut val = f;
-                if ui.add(egui::DragValue::new(&mut val)).changed() {
-                    *value = Yaml::Real(val.to_string());
-                    *modified = true;
-                    *scroll_marker_key = Some(key.to_string());
-                }
+            if render_base_types::render_float_editor(ui, s) {
+                *modified = true;
+                *scroll_marker_key = Some(key.to_string());
             }
         }
         Yaml::Integer(i) => {
@@ -146,15 +542,8 @@ fn render_editable_yaml_value(
             let mut input = String::new();
             if ui.add(egui::TextEdit::singleline(&mut input).hint_text("null")).changed() {
                 // Try to infer type
-                if input.eq_ignore_ascii_case("true") {
-                    *value = Yaml::Boolean(true);
-                } else if input.eq_ignore_ascii_case("false") {
-                    *value = Yaml::Boolean(false);
-                } else if let Ok(i) = input.parse::<i64>() {
-                    *value = Yaml::Integer(i);
-                } else if let Ok(f) = input.parse::<f64>() {
-                    *value = Yaml::Real(f.to_string());
-                } else {
+                *value = infer_yaml_value(&input);
+                if let Yaml::Null = value {
                     *value = Yaml::String(input.clone());
                 }
                 *modified = true;
@@ -165,4 +554,4 @@ fn render_editable_yaml_value(
             ui.label(format!("{:?}", value)); // fallback for other types
         }
     }
-}
\ No newline at end of file
+}
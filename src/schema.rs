@@ -0,0 +1,245 @@
+// A small JSON Schema validator covering the keywords settings schemas use in practice.
+//
+// Supported: type, enum, const, properties, required, additionalProperties, minProperties,
+// maxProperties, items (single schema or tuple), additionalItems, minItems, maxItems,
+// uniqueItems, minimum, maximum, exclusiveMinimum, exclusiveMaximum, multipleOf, minLength,
+// maxLength, allOf, anyOf, oneOf, not and local `$ref`s ("#/definitions/...", "#/$defs/...").
+// `pattern` and `format` are not checked.
+
+use serde_json::{Map, Value};
+
+/// Every place `instance` breaks `schema`, as "path: message" lines (`$` is the document root).
+pub(crate) fn validate(instance: &Value, schema: &Value) -> Vec<String> {
+    let mut errors = vec![];
+    check(instance, schema, schema, "$", &mut errors);
+    errors
+}
+
+fn check(instance: &Value, schema: &Value, root: &Value, path: &str, errors: &mut Vec<String>) {
+    let schema = match schema {
+        Value::Bool(true) => return,
+        Value::Bool(false) => {
+            errors.push(format!("{path}: no value is allowed here"));
+            return;
+        }
+        Value::Object(schema) => schema,
+        _ => return,
+    };
+
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve_ref(root, reference) {
+            Some(target) => check(instance, target, root, path, errors),
+            None => errors.push(format!("{path}: cannot resolve $ref {reference}")),
+        }
+    }
+
+    if let Some(expected) = schema.get("type") {
+        let names: Vec<&str> = match expected {
+            Value::String(name) => vec![name],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => vec![],
+        };
+        if !names.is_empty() && !names.iter().any(|name| has_type(instance, name)) {
+            errors.push(format!("{path}: expected {}, found {}", names.join(" or "), type_name(instance)));
+        }
+    }
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(instance) {
+            let options: Vec<String> = options.iter().map(Value::to_string).collect();
+            errors.push(format!("{path}: {instance} is not one of {}", options.join(", ")));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != instance {
+            errors.push(format!("{path}: expected {expected}, found {instance}"));
+        }
+    }
+
+    match instance {
+        Value::Object(map) => check_object(map, schema, root, path, errors),
+        Value::Array(items) => check_array(items, schema, root, path, errors),
+        Value::Number(number) => {
+            if let Some(n) = number.as_f64() {
+                check_number(n, schema, path, errors);
+            }
+        }
+        Value::String(text) => {
+            let len = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    errors.push(format!("{path}: shorter than {min} characters"));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    errors.push(format!("{path}: longer than {max} characters"));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    if let Some(Value::Array(all)) = schema.get("allOf") {
+        for sub in all {
+            check(instance, sub, root, path, errors);
+        }
+    }
+    if let Some(Value::Array(any)) = schema.get("anyOf") {
+        if !any.iter().any(|sub| matches(instance, sub, root)) {
+            errors.push(format!("{path}: does not match any of the allowed schemas"));
+        }
+    }
+    if let Some(Value::Array(one)) = schema.get("oneOf") {
+        let count = one.iter().filter(|sub| matches(instance, sub, root)).count();
+        if count != 1 {
+            errors.push(format!("{path}: matches {count} schemas in oneOf, expected exactly one"));
+        }
+    }
+    if let Some(not) = schema.get("not") {
+        if matches(instance, not, root) {
+            errors.push(format!("{path}: matches a schema it must not match"));
+        }
+    }
+}
+
+fn check_object(map: &Map<String, Value>, schema: &Map<String, Value>, root: &Value, path: &str, errors: &mut Vec<String>) {
+    let properties = schema.get("properties").and_then(Value::as_object);
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !map.contains_key(name) {
+                errors.push(format!("{path}: missing required field {name}"));
+            }
+        }
+    }
+    for (key, value) in map {
+        let child_path = format!("{path}.{key}");
+        match properties.and_then(|p| p.get(key)) {
+            Some(sub) => check(value, sub, root, &child_path, errors),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => errors.push(format!("{path}: unexpected field {key}")),
+                Some(sub) => check(value, sub, root, &child_path, errors),
+                None => {}
+            },
+        }
+    }
+    if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+        if (map.len() as u64) < min {
+            errors.push(format!("{path}: fewer than {min} fields"));
+        }
+    }
+    if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+        if map.len() as u64 > max {
+            errors.push(format!("{path}: more than {max} fields"));
+        }
+    }
+}
+
+fn check_array(items: &[Value], schema: &Map<String, Value>, root: &Value, path: &str, errors: &mut Vec<String>) {
+    match schema.get("items") {
+        Some(Value::Array(tuple)) => {
+            for (i, item) in items.iter().enumerate() {
+                let child_path = format!("{path}.{i}");
+                match tuple.get(i) {
+                    Some(sub) => check(item, sub, root, &child_path, errors),
+                    None => match schema.get("additionalItems") {
+                        Some(Value::Bool(false)) => {
+                            errors.push(format!("{path}: more than {} items", tuple.len()));
+                            break;
+                        }
+                        Some(sub) => check(item, sub, root, &child_path, errors),
+                        None => {}
+                    },
+                }
+            }
+        }
+        Some(sub) => {
+            for (i, item) in items.iter().enumerate() {
+                check(item, sub, root, &format!("{path}.{i}"), errors);
+            }
+        }
+        None => {}
+    }
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if (items.len() as u64) < min {
+            errors.push(format!("{path}: fewer than {min} items"));
+        }
+    }
+    if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+        if items.len() as u64 > max {
+            errors.push(format!("{path}: more than {max} items"));
+        }
+    }
+    if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+        for (i, item) in items.iter().enumerate() {
+            if items[..i].contains(item) {
+                errors.push(format!("{path}.{i}: duplicate item {item}"));
+            }
+        }
+    }
+}
+
+fn check_number(n: f64, schema: &Map<String, Value>, path: &str, errors: &mut Vec<String>) {
+    let bound = |name: &str| schema.get(name).and_then(Value::as_f64);
+    if let Some(min) = bound("minimum") {
+        if n < min {
+            errors.push(format!("{path}: {n} is less than the minimum {min}"));
+        }
+    }
+    if let Some(max) = bound("maximum") {
+        if n > max {
+            errors.push(format!("{path}: {n} is greater than the maximum {max}"));
+        }
+    }
+    if let Some(min) = bound("exclusiveMinimum") {
+        if n <= min {
+            errors.push(format!("{path}: {n} must be greater than {min}"));
+        }
+    }
+    if let Some(max) = bound("exclusiveMaximum") {
+        if n >= max {
+            errors.push(format!("{path}: {n} must be less than {max}"));
+        }
+    }
+    if let Some(step) = bound("multipleOf").filter(|step| *step > 0.0) {
+        let ratio = n / step;
+        if (ratio - ratio.round()).abs() > 1e-9 {
+            errors.push(format!("{path}: {n} is not a multiple of {step}"));
+        }
+    }
+}
+
+fn matches(instance: &Value, schema: &Value, root: &Value) -> bool {
+    let mut errors = vec![];
+    check(instance, schema, root, "$", &mut errors);
+    errors.is_empty()
+}
+
+fn has_type(instance: &Value, name: &str) -> bool {
+    match name {
+        "integer" => instance.as_i64().is_some()
+            || instance.as_u64().is_some()
+            || instance.as_f64().is_some_and(|f| f.fract() == 0.0),
+        "number" => instance.is_number(),
+        other => type_name(instance) == other,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Follows a JSON pointer inside the schema document itself.
+fn resolve_ref<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    let pointer = reference.strip_prefix('#')?;
+    if pointer.is_empty() {
+        return Some(root);
+    }
+    root.pointer(pointer)
+}
@@ -0,0 +1,262 @@
+// Recent files and the session restored on the next launch. Both live in the config
+// directory next to the preferences; the session is JSON so it can be read and fixed by hand.
+//
+// Every editor window is its own process. Each one registers its document in the session
+// when it starts and, when it closes, either drops its entry (other windows are still open)
+// or keeps it (it was the last window), so the next launch reopens what was open at exit.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use serde_json::{json, Value};
+
+use crate::diagnostics;
+use crate::{MIN_SPLIT_FRACTION, RAW_EDITOR_WIDTH_FRACTION};
+
+const RECENT_FILES_NAME: &str = "recent_files.txt";
+/// Held only the last opened file before the recent files list replaced it.
+const LEGACY_LAST_FILE_NAME: &str = "last_opened_file.txt";
+const SESSION_FILE_NAME: &str = "session.json";
+const TREE_STATE_FILE_NAME: &str = "open_nodes.json";
+const MAX_RECENT_FILES: usize = 10;
+/// Files whose expanded tree nodes are remembered; the least recently closed are forgotten.
+const MAX_TREE_STATES: usize = 100;
+
+/// What one editor window had open.
+#[derive(Clone, Default)]
+pub(crate) struct DocumentState {
+    /// Empty for untitled buffers, which are tracked but never restored.
+    pub(crate) path: String,
+    /// Process that owns the window.
+    pub(crate) pid: u32,
+    pub(crate) show_raw_editor: bool,
+    pub(crate) layout: PaneLayout,
+    /// Root of the folder browser; empty when it is closed.
+    pub(crate) folder: String,
+    pub(crate) search_query: String,
+}
+
+/// How the raw editor and the tree share the window.
+#[derive(Clone, Copy)]
+pub(crate) struct PaneLayout {
+    /// Share of the window taken by the raw editor.
+    pub(crate) split_fraction: f32,
+    /// Raw editor above the tree instead of beside it.
+    pub(crate) stacked: bool,
+    pub(crate) raw_popped_out: bool,
+    pub(crate) tree_popped_out: bool,
+}
+
+impl Default for PaneLayout {
+    fn default() -> Self {
+        Self { split_fraction: RAW_EDITOR_WIDTH_FRACTION, stacked: false, raw_popped_out: false, tree_popped_out: false }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Session {
+    pub(crate) documents: Vec<DocumentState>,
+}
+
+pub(crate) fn config_path(name: &str) -> Option<PathBuf> {
+    ProjectDirs::from("org", "QuantumTools", "YamlEditor").map(|proj_dirs| {
+        let dir = proj_dirs.config_dir();
+        let _ = fs::create_dir_all(dir);
+        dir.join(name)
+    })
+}
+
+/// Writes through a temporary file so that a window closing at the same time as another
+/// never leaves a half-written file behind.
+pub(crate) fn write_config(name: &str, text: &str) {
+    let Some(path) = config_path(name) else {
+        diagnostics::error(format!("cannot save {name}: no config directory"));
+        return;
+    };
+    let temp = path.with_extension(format!("{}.tmp", std::process::id()));
+    if let Err(err) = fs::write(&temp, text).and_then(|()| fs::rename(&temp, &path)) {
+        let _ = fs::remove_file(&temp);
+        diagnostics::error(format!("cannot write {}: {err}", path.display()));
+    }
+}
+
+/// Reads a JSON config file; a missing file is `None`, a malformed one is reported too.
+fn read_json_config(name: &str) -> Option<Value> {
+    let text = config_path(name).and_then(|path| fs::read_to_string(path).ok())?;
+    serde_json::from_str(&text)
+        .map_err(|err| diagnostics::warning(format!("ignoring malformed {name}: {err}")))
+        .ok()
+}
+
+/// The absolute form of `path`, so that the same file opened from different directories
+/// is listed once.
+pub(crate) fn absolute_path(path: &str) -> String {
+    std::path::absolute(path)
+        .ok()
+        .and_then(|p| p.into_os_string().into_string().ok())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Recently opened files that still exist, newest first.
+pub(crate) fn recent_files() -> Vec<String> {
+    let read = |name| config_path(name).and_then(|path| fs::read_to_string(path).ok());
+    read(RECENT_FILES_NAME)
+        .or_else(|| read(LEGACY_LAST_FILE_NAME))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && Path::new(line).exists())
+        .map(str::to_string)
+        .collect()
+}
+
+pub(crate) fn add_recent_file(path: &str) {
+    let mut files = recent_files();
+    files.retain(|file| file != path);
+    files.insert(0, path.to_string());
+    files.truncate(MAX_RECENT_FILES);
+    write_config(RECENT_FILES_NAME, &(files.join("\n") + "\n"));
+}
+
+pub(crate) fn clear_recent_files() {
+    write_config(RECENT_FILES_NAME, "");
+}
+
+/// Whether the window process `pid` is still running.
+pub(crate) fn is_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        // Signal 0 only checks that the process exists.
+        unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+pub(crate) fn load_session() -> Session {
+    let Some(json) = read_json_config(SESSION_FILE_NAME) else {
+        return Session::default();
+    };
+    let documents = json["documents"]
+        .as_array()
+        .map(|documents| documents.iter().map(document_from_json).collect())
+        .unwrap_or_default();
+    Session { documents }
+}
+
+fn document_from_json(json: &Value) -> DocumentState {
+    DocumentState {
+        path: json["path"].as_str().unwrap_or_default().to_string(),
+        pid: json["pid"].as_u64().unwrap_or_default() as u32,
+        show_raw_editor: json["show_raw_editor"].as_bool().unwrap_or_default(),
+        layout: PaneLayout {
+            split_fraction: json["split_fraction"]
+                .as_f64()
+                .map_or(RAW_EDITOR_WIDTH_FRACTION, |f| (f as f32).clamp(MIN_SPLIT_FRACTION, 1. - MIN_SPLIT_FRACTION)),
+            stacked: json["stacked"].as_bool().unwrap_or_default(),
+            raw_popped_out: json["raw_popped_out"].as_bool().unwrap_or_default(),
+            tree_popped_out: json["tree_popped_out"].as_bool().unwrap_or_default(),
+        },
+        folder: json["folder"].as_str().unwrap_or_default().to_string(),
+        search_query: json["search_query"].as_str().unwrap_or_default().to_string(),
+    }
+}
+
+fn document_to_json(document: &DocumentState) -> Value {
+    json!({
+        "path": document.path,
+        "pid": document.pid,
+        "show_raw_editor": document.show_raw_editor,
+        "split_fraction": document.layout.split_fraction,
+        "stacked": document.layout.stacked,
+        "raw_popped_out": document.layout.raw_popped_out,
+        "tree_popped_out": document.layout.tree_popped_out,
+        "folder": document.folder,
+        "search_query": document.search_query,
+    })
+}
+
+fn save_session(session: &Session) {
+    let json = json!({
+        "documents": session.documents.iter().map(document_to_json).collect::<Vec<_>>(),
+    });
+    if let Ok(text) = serde_json::to_string_pretty(&json) {
+        write_config(SESSION_FILE_NAME, &text);
+    }
+}
+
+/// Documents left open by windows that are no longer running, to reopen at launch.
+pub(crate) fn documents_to_restore(session: &Session) -> Vec<DocumentState> {
+    session
+        .documents
+        .iter()
+        .filter(|document| !document.path.is_empty() && !is_running(document.pid) && Path::new(&document.path).exists())
+        .cloned()
+        .collect()
+}
+
+/// Records the document of this window, replacing its previous entry and any older entry
+/// for the same file.
+pub(crate) fn register_document(document: DocumentState) {
+    let mut session = load_session();
+    session.documents.retain(|d| d.pid != document.pid && (document.path.is_empty() || d.path != document.path));
+    session.documents.push(document);
+    save_session(&session);
+}
+
+/// Called when a window closes: its document stays in the session only if it was the last
+/// window, so closing one of several windows does not bring it back next time.
+pub(crate) fn close_document(document: DocumentState) {
+    let mut session = load_session();
+    session.documents.retain(|d| d.pid != document.pid && (document.path.is_empty() || d.path != document.path));
+    let others_open = session.documents.iter().any(|d| is_running(d.pid));
+    if !others_open {
+        session.documents.clear();
+        if !document.path.is_empty() {
+            session.documents.push(document);
+        }
+    }
+    save_session(&session);
+}
+
+fn load_tree_states() -> Vec<Value> {
+    read_json_config(TREE_STATE_FILE_NAME)
+        .and_then(|json| json["files"].as_array().cloned())
+        .unwrap_or_default()
+}
+
+/// Key paths of the tree nodes that were expanded when `path` was last closed.
+pub(crate) fn load_open_nodes(path: &str) -> HashSet<Vec<String>> {
+    let strings = |value: &Value| -> Vec<String> {
+        value.as_array().into_iter().flatten().filter_map(|s| s.as_str().map(str::to_string)).collect()
+    };
+    load_tree_states()
+        .iter()
+        .find(|file| file["path"] == path)
+        .and_then(|file| file["open_nodes"].as_array())
+        .map(|nodes| nodes.iter().map(strings).collect())
+        .unwrap_or_default()
+}
+
+/// Remembers the expanded tree nodes of `path` for the next time it is opened.
+pub(crate) fn save_open_nodes(path: &str, open_nodes: &HashSet<Vec<String>>) {
+    if path.is_empty() {
+        return;
+    }
+    let mut nodes: Vec<&Vec<String>> = open_nodes.iter().collect();
+    nodes.sort();
+    let mut files = load_tree_states();
+    files.retain(|file| file["path"] != path);
+    files.insert(0, json!({ "path": path, "open_nodes": nodes }));
+    files.truncate(MAX_TREE_STATES);
+    if let Ok(text) = serde_json::to_string_pretty(&json!({ "files": files })) {
+        write_config(TREE_STATE_FILE_NAME, &text);
+    }
+}
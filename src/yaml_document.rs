@@ -0,0 +1,839 @@
+// YAML loading and emitting that keeps what `YamlLoader`/`YamlEmitter` throw away:
+// aliases stay `Yaml::Alias` nodes, while anchors, tags and scalar styles are remembered by key path.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use yaml_rust::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust::scanner::{Marker, ScanError, TScalarStyle, TokenType, Scanner};
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+/// The merge key (`<<: *base`) that pulls the entries of another mapping into this one.
+pub(crate) const MERGE_KEY: &str = "<<";
+
+/// Everything about a YAML document that does not fit in a `Yaml` value, keyed by key path.
+#[derive(Default, Clone)]
+pub(crate) struct YamlMeta {
+    /// Anchor names by the parser's anchor id; `Yaml::Alias(id)` refers into this table.
+    pub(crate) anchor_names: BTreeMap<usize, String>,
+    /// The anchor defined on the node at each key path.
+    pub(crate) anchors: HashMap<Vec<String>, usize>,
+    /// Current value of every anchored node, refreshed before each render so aliases can be resolved.
+    pub(crate) anchored_values: BTreeMap<usize, Yaml>,
+    /// Explicit tags such as `!!float` or `!include`, as written in the source.
+    pub(crate) tags: HashMap<Vec<String>, String>,
+    /// Quoting or block style of string scalars that were not written plain.
+    pub(crate) styles: HashMap<Vec<String>, TScalarStyle>,
+}
+
+impl YamlMeta {
+    pub(crate) fn anchor_name(&self, id: usize) -> String {
+        self.anchor_names.get(&id).cloned().unwrap_or_else(|| format!("anchor{id}"))
+    }
+
+    pub(crate) fn anchor_path(&self, id: usize) -> Option<&Vec<String>> {
+        self.anchors.iter().find(|(_, &a)| a == id).map(|(path, _)| path)
+    }
+
+    /// Moves everything recorded under `old` to `new`, after the key at `old` was renamed.
+    pub(crate) fn rename_path(&mut self, old: &[String], new: &[String]) {
+        self.move_paths(|path| {
+            let rest = path.strip_prefix(old)?;
+            Some(Some([new, rest].concat()))
+        });
+    }
+
+    /// Forgets everything recorded at and below `path`, after the node there was replaced or removed.
+    pub(crate) fn forget_path(&mut self, path: &[String]) {
+        self.move_paths(|recorded| recorded.starts_with(path).then_some(None));
+    }
+
+    /// After item `index` of the list at `list` was removed, forgets what was recorded for it
+    /// and moves what was recorded for the items after it up by one.
+    pub(crate) fn remove_list_item(&mut self, list: &[String], index: usize) {
+        self.move_paths(|path| {
+            let rest = path.strip_prefix(list)?;
+            let item = rest.first()?.parse::<usize>().ok()?;
+            match item.cmp(&index) {
+                std::cmp::Ordering::Less => None,
+                std::cmp::Ordering::Equal => Some(None),
+                std::cmp::Ordering::Greater => Some(Some([list, &[(item - 1).to_string()], &rest[1..]].concat())),
+            }
+        });
+    }
+
+    /// Copies what is recorded at and below `path` in `from`, another parse of the same file.
+    /// Anchor ids differ between parses, so anchors are matched up by name, and only taken
+    /// over while no other node has that anchor.
+    pub(crate) fn copy_path(&mut self, from: &YamlMeta, path: &[String]) {
+        let under = |recorded: &&Vec<String>| recorded.starts_with(path);
+        self.tags.extend(from.tags.iter().filter(|(p, _)| under(p)).map(|(p, tag)| (p.clone(), tag.clone())));
+        self.styles.extend(from.styles.iter().filter(|(p, _)| under(p)).map(|(p, style)| (p.clone(), *style)));
+        for (recorded, from_id) in from.anchors.iter().filter(|(p, _)| under(p)) {
+            let name = from.anchor_name(*from_id);
+            let id = self.anchor_names.iter().find(|(_, n)| **n == name).map(|(&id, _)| id);
+            if let Some(id) = id.filter(|&id| self.anchor_path(id).is_none()) {
+                self.anchors.insert(recorded.clone(), id);
+            }
+        }
+    }
+
+    /// Re-keys every recorded path: `moved` returns `None` to leave a path as it is,
+    /// `Some(None)` to forget it and `Some(Some(new))` to move it.
+    fn move_paths(&mut self, moved: impl Fn(&[String]) -> Option<Option<Vec<String>>>) {
+        fn rekey<V>(map: &mut HashMap<Vec<String>, V>, moved: &impl Fn(&[String]) -> Option<Option<Vec<String>>>) {
+            let changes: Vec<(Vec<String>, Option<Vec<String>>)> =
+                map.keys().filter_map(|path| moved(path).map(|new| (path.clone(), new))).collect();
+            let values: Vec<(Option<Vec<String>>, V)> =
+                changes.into_iter().filter_map(|(old, new)| map.remove(&old).map(|value| (new, value))).collect();
+            // Inserted only after all removals, so a moved entry never lands on one still to move.
+            for (new, value) in values {
+                if let Some(new) = new {
+                    map.insert(new, value);
+                }
+            }
+        }
+        rekey(&mut self.anchors, &moved);
+        rekey(&mut self.tags, &moved);
+        rekey(&mut self.styles, &moved);
+    }
+
+    /// Whether the string at `path` is a `|` or `>` block scalar.
+    pub(crate) fn is_block_scalar(&self, path: &[String]) -> bool {
+        matches!(self.styles.get(path), Some(TScalarStyle::Literal | TScalarStyle::Foled))
+    }
+}
+
+pub(crate) struct YamlDocument {
+    pub(crate) root: Yaml,
+    pub(crate) meta: YamlMeta,
+}
+
+impl YamlDocument {
+    /// Re-reads the value behind every anchor so aliases show the latest edits. Anchors that
+    /// were removed keep their last value, so aliases to them can still be written out.
+    pub(crate) fn refresh_anchored_values(&mut self) {
+        let values: Vec<(usize, Yaml)> = self
+            .meta
+            .anchors
+            .iter()
+            .filter_map(|(path, &id)| lookup(&self.root, path).map(|v| (id, v.clone())))
+            .collect();
+        self.meta.anchored_values.extend(values);
+    }
+}
+
+/// The key path segment for a mapping key; non-string keys use their one-line YAML text.
+pub(crate) fn key_segment(key: &Yaml) -> String {
+    match key {
+        Yaml::String(s) => s.clone(),
+        _ => flow_text(key),
+    }
+}
+
+/// The key of `map`, other than `except`, with the same path segment as `key`. Paths cannot
+/// tell such keys apart, e.g. the integer key `1` and the string key `"1"`.
+pub(crate) fn clashing_key<'a>(map: &'a Hash, key: &Yaml, except: Option<&Yaml>) -> Option<&'a Yaml> {
+    let segment = key_segment(key);
+    map.keys().find(|k| Some(*k) != except && *k != key && key_segment(k) == segment)
+}
+
+/// A value in YAML flow style on a single line, e.g. `[a, b]` or `{x: 1}`.
+pub(crate) fn flow_text(value: &Yaml) -> String {
+    match value {
+        Yaml::Array(items) => format!("[{}]", items.iter().map(flow_text).collect::<Vec<_>>().join(", ")),
+        Yaml::Hash(map) => format!(
+            "{{{}}}",
+            map.iter().map(|(k, v)| format!("{}: {}", flow_text(k), flow_text(v))).collect::<Vec<_>>().join(", ")
+        ),
+        _ => emit_value(value),
+    }
+}
+
+/// Parses text typed as a mapping key the way it would read in a YAML file,
+/// so `1`, `true`, `~` and `[a, b]` become integer, boolean, null and sequence keys.
+pub(crate) fn parse_key(text: &str) -> Result<Yaml, String> {
+    if text.trim().is_empty() {
+        return Err("key cannot be empty".to_string());
+    }
+    match load_yaml(text) {
+        Ok(Some(doc)) if !matches!(doc.root, Yaml::Alias(_) | Yaml::BadValue) => Ok(doc.root),
+        Ok(_) => Err(format!("\"{text}\" is not a valid key")),
+        Err(err) => Err(format!("\"{text}\" is not a valid key: {err}")),
+    }
+}
+
+/// Finds the node at `path`, without following aliases.
+pub(crate) fn lookup<'a>(root: &'a Yaml, path: &[String]) -> Option<&'a Yaml> {
+    path.iter().try_fold(root, |node, segment| match node {
+        Yaml::Hash(map) => map.iter().find(|(k, _)| key_segment(k) == *segment).map(|(_, v)| v),
+        Yaml::Array(arr) => segment.parse::<usize>().ok().and_then(|i| arr.get(i)),
+        _ => None,
+    })
+}
+
+/// The mappings a merge value (`*base`, `[*a, *b]` or an inline mapping) pulls in, in priority order.
+pub(crate) fn merge_sources(value: &Yaml, meta: &YamlMeta) -> Vec<(Option<usize>, Hash)> {
+    match value {
+        Yaml::Alias(id) => match meta.anchored_values.get(id) {
+            Some(Yaml::Hash(map)) => vec![(Some(*id), flatten_merges(map, meta))],
+            _ => vec![],
+        },
+        Yaml::Hash(map) => vec![(None, flatten_merges(map, meta))],
+        Yaml::Array(items) => items.iter().flat_map(|item| merge_sources(item, meta)).collect(),
+        _ => vec![],
+    }
+}
+
+/// `value` as a YAML reader sees it: aliases replaced by what they point to and merge keys applied.
+pub(crate) fn resolve(value: &Yaml, meta: &YamlMeta) -> Yaml {
+    resolve_within(value, meta, &mut vec![])
+}
+
+/// `following` holds the aliases being expanded, so an anchor that contains itself ends in `~`.
+fn resolve_within(value: &Yaml, meta: &YamlMeta, following: &mut Vec<usize>) -> Yaml {
+    match value {
+        Yaml::Alias(id) => match meta.anchored_values.get(id) {
+            Some(target) if !following.contains(id) => {
+                following.push(*id);
+                let resolved = resolve_within(target, meta, following);
+                following.pop();
+                resolved
+            }
+            _ => Yaml::Null,
+        },
+        Yaml::Array(items) => Yaml::Array(items.iter().map(|item| resolve_within(item, meta, following)).collect()),
+        Yaml::Hash(map) => Yaml::Hash(
+            flatten_merges(map, meta)
+                .iter()
+                .map(|(k, v)| (k.clone(), resolve_within(v, meta, following)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// A mapping with its own merge keys applied, as a YAML reader would see it.
+fn flatten_merges(map: &Hash, meta: &YamlMeta) -> Hash {
+    let mut flat = Hash::new();
+    for (k, v) in map {
+        if !is_merge_key(k) {
+            flat.insert(k.clone(), v.clone());
+        }
+    }
+    if let Some(merge) = map.get(&Yaml::String(MERGE_KEY.to_string())) {
+        for (_, source) in merge_sources(merge, meta) {
+            for (k, v) in source {
+                flat.entry(k).or_insert(v);
+            }
+        }
+    }
+    flat
+}
+
+pub(crate) fn is_merge_key(key: &Yaml) -> bool {
+    matches!(key, Yaml::String(s) if s == MERGE_KEY)
+}
+
+struct DocumentBuilder {
+    anchor_names: Vec<String>,
+    /// Every tag in the source with its position; collection tags are matched to nodes by position.
+    tags: Vec<(usize, String)>,
+    next_tag: usize,
+    doc_stack: Vec<(Yaml, usize, Option<Vec<String>>)>,
+    key_stack: Vec<Yaml>,
+    root: Option<Yaml>,
+    meta: YamlMeta,
+    /// Two keys of one mapping that would share a path, found while loading.
+    clash: Option<ScanError>,
+}
+
+impl DocumentBuilder {
+    /// Key path of the node about to be inserted, or `None` inside a mapping key.
+    fn next_path(&self) -> Option<Vec<String>> {
+        let Some((parent, _, parent_path)) = self.doc_stack.last() else {
+            return Some(vec![]);
+        };
+        let mut path = parent_path.clone()?;
+        match parent {
+            Yaml::Array(items) => path.push(items.len().to_string()),
+            Yaml::Hash(_) => {
+                let key = self.key_stack.last()?;
+                if key.is_badvalue() {
+                    return None;
+                }
+                path.push(key_segment(key));
+            }
+            _ => return None,
+        }
+        Some(path)
+    }
+
+    fn record_anchor(&mut self, anchor_id: usize, path: &Option<Vec<String>>) {
+        if anchor_id == 0 {
+            return;
+        }
+        // Anchor ids are handed out in the order the scanner meets `&name` tokens
+        if let Some(name) = self.anchor_names.get(anchor_id - 1) {
+            self.meta.anchor_names.insert(anchor_id, name.clone());
+        }
+        if let Some(path) = path {
+            self.meta.anchors.insert(path.clone(), anchor_id);
+        }
+    }
+
+    /// Consumes the tags written before `mark`, returning the last one: the tag of the node starting there.
+    fn take_tag_before(&mut self, mark: &Marker) -> Option<String> {
+        let mut tag = None;
+        while let Some((index, text)) = self.tags.get(self.next_tag) {
+            if *index >= mark.index() {
+                break;
+            }
+            tag = Some(text.clone());
+            self.next_tag += 1;
+        }
+        tag
+    }
+
+    fn record_tag(&mut self, tag: Option<String>, path: &Option<Vec<String>>) {
+        if let (Some(tag), Some(path)) = (tag, path) {
+            self.meta.tags.insert(path.clone(), tag);
+        }
+    }
+
+    fn insert_node(&mut self, node: Yaml, mark: Marker) {
+        match self.doc_stack.last_mut() {
+            None => {
+                if self.root.is_none() {
+                    self.root = Some(node);
+                }
+            }
+            Some((Yaml::Array(items), _, _)) => items.push(node),
+            Some((Yaml::Hash(map), _, _)) => {
+                let key = self.key_stack.last_mut().unwrap();
+                if key.is_badvalue() {
+                    if let Some(other) = clashing_key(map, &node, None).filter(|_| self.clash.is_none()) {
+                        let message = format!("keys {} and {} cannot be told apart; rename one", flow_text(other), flow_text(&node));
+                        self.clash = Some(ScanError::new(mark, &message));
+                    }
+                    *key = node;
+                } else {
+                    let key = std::mem::replace(key, Yaml::BadValue);
+                    map.insert(key, node);
+                }
+            }
+            Some(_) => unreachable!(),
+        }
+    }
+}
+
+impl MarkedEventReceiver for DocumentBuilder {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::SequenceStart(aid) => {
+                let path = self.next_path();
+                let tag = self.take_tag_before(&mark);
+                self.record_tag(tag, &path);
+                self.doc_stack.push((Yaml::Array(Vec::new()), aid, path));
+            }
+            Event::MappingStart(aid) => {
+                let path = self.next_path();
+                let tag = self.take_tag_before(&mark);
+                self.record_tag(tag, &path);
+                self.doc_stack.push((Yaml::Hash(Hash::new()), aid, path));
+                self.key_stack.push(Yaml::BadValue);
+            }
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Event::MappingEnd = ev {
+                    self.key_stack.pop();
+                }
+                let (node, aid, path) = self.doc_stack.pop().unwrap();
+                self.record_anchor(aid, &path);
+                self.insert_node(node, mark);
+            }
+            Event::Scalar(v, style, aid, tag) => {
+                let path = self.next_path();
+                self.record_anchor(aid, &path);
+                if tag.is_some() {
+                    self.take_tag_before(&mark);
+                    self.record_tag(tag.as_ref().and_then(tag_text), &path);
+                }
+                // A tagged plain scalar stays plain: `!ramp 0.5` must not become `!ramp "0.5"`.
+                let styled = style != TScalarStyle::Plain || tag.is_some();
+                if let (Some(path), true) = (&path, styled) {
+                    self.meta.styles.insert(path.clone(), style);
+                }
+                self.insert_node(scalar_to_yaml(v, style, tag), mark);
+            }
+            Event::Alias(id) => self.insert_node(Yaml::Alias(id), mark),
+            _ => {}
+        }
+    }
+}
+
+fn tag_text(token: &TokenType) -> Option<String> {
+    match token {
+        TokenType::Tag(handle, suffix) if handle.is_empty() && suffix == "!" => Some("!".to_string()),
+        TokenType::Tag(handle, suffix) if handle.is_empty() => Some(format!("!<{suffix}>")),
+        TokenType::Tag(handle, suffix) => Some(format!("{handle}{suffix}")),
+        _ => None,
+    }
+}
+
+/// Same scalar resolution as `YamlLoader`.
+fn scalar_to_yaml(v: String, style: TScalarStyle, tag: Option<TokenType>) -> Yaml {
+    if style != TScalarStyle::Plain {
+        return Yaml::String(v);
+    }
+    match tag {
+        Some(TokenType::Tag(handle, suffix)) if handle == "!!" => match suffix.as_str() {
+            "bool" => v.parse::<bool>().map(Yaml::Boolean).unwrap_or(Yaml::BadValue),
+            "int" => v.parse::<i64>().map(Yaml::Integer).unwrap_or(Yaml::BadValue),
+            "float" => match crate::render_base_types::parse_float(&v) {
+                Some(_) => Yaml::Real(v),
+                None => Yaml::BadValue,
+            },
+            "null" => match v.as_str() {
+                "~" | "null" => Yaml::Null,
+                _ => Yaml::BadValue,
+            },
+            _ => Yaml::String(v),
+        },
+        Some(_) => Yaml::String(v),
+        None => Yaml::from_str(&v),
+    }
+}
+
+/// Parses the first document of `source`. An empty stream gives `Ok(None)`.
+pub(crate) fn load_yaml(source: &str) -> Result<Option<YamlDocument>, ScanError> {
+    // The parser only hands out numeric anchor ids and drops collection tags,
+    // so both are recovered from the token stream
+    let mut anchor_names = vec![];
+    let mut tags = vec![];
+    for token in Scanner::new(source.chars()) {
+        match &token.1 {
+            TokenType::Anchor(name) => anchor_names.push(name.clone()),
+            TokenType::Tag(..) => tags.extend(tag_text(&token.1).map(|tag| (token.0.index(), tag))),
+            _ => {}
+        }
+    }
+    let mut builder = DocumentBuilder {
+        anchor_names,
+        tags,
+        next_tag: 0,
+        doc_stack: vec![],
+        key_stack: vec![],
+        root: None,
+        meta: YamlMeta::default(),
+        clash: None,
+    };
+    Parser::new(source.chars()).load(&mut builder, false)?;
+    if let Some(clash) = builder.clash {
+        return Err(clash);
+    }
+
+    Ok(builder.root.map(|root| {
+        let mut doc = YamlDocument { root, meta: builder.meta };
+        doc.refresh_anchored_values();
+        doc
+    }))
+}
+
+/// Writes the document back out in the same layout as `YamlEmitter`, with anchors and aliases intact.
+pub(crate) fn emit_yaml(doc: &YamlDocument) -> String {
+    let mut emitter = Emitter { out: String::new(), level: -1, meta: &doc.meta, emitted: vec![] };
+    emitter.emit_root(&doc.root);
+    emitter.out.push('\n');
+    emitter.out
+}
+
+/// Emits a standalone value, e.g. to show it as text. Aliases are written as `*name`.
+pub(crate) fn emit_value(value: &Yaml) -> String {
+    let meta = YamlMeta::default();
+    let mut emitter = Emitter { out: String::new(), level: -1, meta: &meta, emitted: vec![] };
+    emitter.emit_node(value, None);
+    emitter.out
+}
+
+/// Emits the value found at `path` on its own, with the tags and scalar styles recorded for it.
+/// Meant for resolved values: anchors are left out, as nothing in them refers back.
+pub(crate) fn emit_value_at(value: &Yaml, path: &[String], meta: &YamlMeta) -> String {
+    let meta = YamlMeta { anchors: HashMap::new(), ..meta.clone() };
+    let mut emitter = Emitter { out: String::new(), level: -1, meta: &meta, emitted: vec![] };
+    emitter.emit_node(value, Some(&path.to_vec()));
+    emitter.out
+}
+
+struct Emitter<'a> {
+    out: String,
+    level: isize,
+    meta: &'a YamlMeta,
+    /// Anchors already written, so later aliases may refer to them.
+    emitted: Vec<usize>,
+}
+
+impl Emitter<'_> {
+    fn write_indent(&mut self) {
+        for _ in 0..self.level.max(0) * 2 {
+            self.out.push(' ');
+        }
+    }
+
+    fn anchor_at(&self, path: Option<&Vec<String>>) -> Option<usize> {
+        path.and_then(|p| self.meta.anchors.get(p)).copied()
+    }
+
+    /// Writes ` &name !tag` for an anchored or tagged node; returns whether anything was written.
+    fn emit_properties(&mut self, node: &Yaml, path: Option<&Vec<String>>) -> bool {
+        let mut written = false;
+        if let Some(id) = self.anchor_at(path) {
+            let name = self.meta.anchor_name(id);
+            let _ = write!(self.out, " &{name}");
+            self.emitted.push(id);
+            written = true;
+        }
+        if let Some(tag) = path.and_then(|p| self.meta.tags.get(p)) {
+            if tag_fits(tag, node) {
+                let _ = write!(self.out, " {tag}");
+                written = true;
+            }
+        }
+        written
+    }
+
+    fn emit_root(&mut self, root: &Yaml) {
+        let path = vec![];
+        if self.emit_properties(root, Some(&path)) {
+            self.out.remove(0);
+            if is_block_collection(root) {
+                self.out.push('\n');
+            } else {
+                self.out.push(' ');
+            }
+        }
+        self.emit_node(root, Some(&path));
+    }
+
+    fn emit_node(&mut self, node: &Yaml, path: Option<&Vec<String>>) {
+        match node {
+            Yaml::Array(items) => self.emit_array(items, path),
+            Yaml::Hash(map) => self.emit_hash(map, path),
+            Yaml::String(s) => {
+                if need_quotes(s) {
+                    escape_str(&mut self.out, s);
+                } else {
+                    self.out.push_str(s);
+                }
+            }
+            Yaml::Boolean(b) => self.out.push_str(if *b { "true" } else { "false" }),
+            Yaml::Integer(i) => {
+                let _ = write!(self.out, "{i}");
+            }
+            Yaml::Real(s) => self.out.push_str(s),
+            Yaml::Null | Yaml::BadValue => self.out.push('~'),
+            Yaml::Alias(id) => self.emit_alias(*id),
+        }
+    }
+
+    fn emit_alias(&mut self, id: usize) {
+        if self.emitted.contains(&id) {
+            let name = self.meta.anchor_name(id);
+            let _ = write!(self.out, "*{name}");
+        } else if let Some(value) = self.meta.anchored_values.get(&id) {
+            // The anchor was removed or comes later; inline its value rather than leave a dangling alias
+            let value = value.clone();
+            self.emit_node(&value, None);
+        } else {
+            self.out.push('~');
+        }
+    }
+
+    fn emit_array(&mut self, items: &[Yaml], path: Option<&Vec<String>>) {
+        if items.is_empty() {
+            self.out.push_str("[]");
+            return;
+        }
+        self.level += 1;
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+                self.write_indent();
+            }
+            self.out.push('-');
+            let child = path.map(|p| child_path(p, i.to_string()));
+            self.emit_val(true, item, child.as_ref());
+        }
+        self.level -= 1;
+    }
+
+    fn emit_hash(&mut self, map: &Hash, path: Option<&Vec<String>>) {
+        if map.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.level += 1;
+        for (i, (k, v)) in map.iter().enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+                self.write_indent();
+            }
+            let child = path.map(|p| child_path(p, key_segment(k)));
+            if matches!(k, Yaml::Hash(_) | Yaml::Array(_)) {
+                self.out.push('?');
+                self.emit_val(true, k, None);
+                self.out.push('\n');
+                self.write_indent();
+                self.out.push(':');
+                self.emit_val(true, v, child.as_ref());
+            } else {
+                if is_merge_key(k) {
+                    self.out.push_str(MERGE_KEY);
+                } else {
+                    self.emit_node(k, None);
+                }
+                self.out.push(':');
+                self.emit_val(false, v, child.as_ref());
+            }
+        }
+        self.level -= 1;
+    }
+
+    /// Emits a value following `:` or `-`. `inline` values may use the compact `- key: v` form,
+    /// unless they carry an anchor, which has to go on the line of the indicator.
+    fn emit_val(&mut self, inline: bool, val: &Yaml, path: Option<&Vec<String>>) {
+        if let Yaml::Alias(id) = val {
+            if !self.emitted.contains(id) {
+                if let Some(value) = self.meta.anchored_values.get(id).cloned() {
+                    // Written out in place, laid out like any other value.
+                    return self.emit_val(inline, &value, None);
+                }
+            }
+        }
+        let has_properties = self.emit_properties(val, path);
+        if let (Yaml::String(s), Some(style)) = (val, path.and_then(|p| self.meta.styles.get(p))) {
+            self.out.push(' ');
+            self.emit_styled_string(s, *style);
+            return;
+        }
+        if is_block_collection(val) {
+            if inline && !has_properties {
+                self.out.push(' ');
+            } else {
+                self.out.push('\n');
+                self.level += 1;
+                self.write_indent();
+                self.level -= 1;
+            }
+        } else {
+            self.out.push(' ');
+        }
+        self.emit_node(val, path);
+    }
+}
+
+impl Emitter<'_> {
+    /// Writes a string value in the style it was read with, falling back to the default
+    /// quoting when the text can no longer be expressed in that style.
+    fn emit_styled_string(&mut self, s: &str, style: TScalarStyle) {
+        match style {
+            TScalarStyle::SingleQuoted if !s.contains(|c: char| c.is_control()) => {
+                let _ = write!(self.out, "'{}'", s.replace('\'', "''"));
+            }
+            TScalarStyle::DoubleQuoted => escape_str(&mut self.out, s),
+            // The tag says how to read the text, so it may look like a number or boolean.
+            TScalarStyle::Plain if !breaks_plain(s) => self.out.push_str(s),
+            TScalarStyle::Literal if can_be_block(s) => self.emit_block_scalar(s, '|'),
+            TScalarStyle::Foled if can_be_block(s) && !s.lines().any(|l| l.starts_with([' ', '\t'])) => {
+                self.emit_block_scalar(s, '>');
+            }
+            _ => self.emit_node(&Yaml::String(s.to_string()), None),
+        }
+    }
+
+    /// Writes `|`/`>` with the chomping indicator that reproduces `s`'s trailing newlines,
+    /// then the text indented one level below the current one.
+    fn emit_block_scalar(&mut self, s: &str, indicator: char) {
+        let content = s.trim_end_matches('\n');
+        let chomping = match s.len() - content.len() {
+            0 => "-",
+            1 => "",
+            _ => "+",
+        };
+        self.out.push(indicator);
+        self.out.push_str(chomping);
+        let indent = " ".repeat(((self.level + 1).max(0) * 2).max(2) as usize);
+        let body = if indicator == '>' { fold(content) } else { content.to_string() };
+        for line in body.split('\n') {
+            self.out.push('\n');
+            if !line.is_empty() {
+                self.out.push_str(&indent);
+                self.out.push_str(line);
+            }
+        }
+        for _ in 1..(s.len() - content.len()) {
+            self.out.push('\n');
+        }
+    }
+}
+
+/// Block scalars cannot hold control characters, and text starting with whitespace or a blank
+/// line would need an explicit indentation indicator.
+fn can_be_block(s: &str) -> bool {
+    !s.is_empty()
+        && !s.contains(|c: char| c.is_control() && c != '\n' && c != '\t')
+        && !s.starts_with([' ', '\t', '\n'])
+}
+
+/// Source text of a folded scalar reading back as `s`: every run of line breaks gains one
+/// extra break, since a single break between lines folds into a space.
+fn fold(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        out.push(c);
+        if c == '\n' && chars.peek() != Some(&'\n') {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Core `!!` tags are only written back while the value still has that type;
+/// custom tags are always kept.
+fn tag_fits(tag: &str, node: &Yaml) -> bool {
+    match tag {
+        "!!str" => matches!(node, Yaml::String(_)),
+        "!!int" => matches!(node, Yaml::Integer(_)),
+        "!!float" => matches!(node, Yaml::Real(_)),
+        "!!bool" => matches!(node, Yaml::Boolean(_)),
+        "!!null" => matches!(node, Yaml::Null),
+        "!!map" => matches!(node, Yaml::Hash(_)),
+        "!!seq" => matches!(node, Yaml::Array(_)),
+        _ => true,
+    }
+}
+
+fn is_block_collection(value: &Yaml) -> bool {
+    match value {
+        Yaml::Hash(map) => !map.is_empty(),
+        Yaml::Array(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+fn child_path(path: &[String], segment: String) -> Vec<String> {
+    let mut child = path.to_vec();
+    child.push(segment);
+    child
+}
+
+fn escape_str(out: &mut String, v: &str) {
+    out.push('"');
+    for c in v.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\x08' => out.push_str("\\b"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\x0c' => out.push_str("\\f"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Same quoting rules as `YamlEmitter`.
+fn need_quotes(string: &str) -> bool {
+    breaks_plain(string)
+        || [
+            "yes", "Yes", "YES", "no", "No", "NO", "True", "TRUE", "true", "False", "FALSE",
+            "false", "on", "On", "ON", "off", "Off", "OFF", "null", "Null", "NULL", "~",
+        ]
+        .contains(&string)
+        || string.starts_with('.')
+        || string.starts_with("0x")
+        || string.parse::<i64>().is_ok()
+        || string.parse::<f64>().is_ok()
+}
+
+/// Whether `string` cannot be written as a plain scalar at all, whatever type it would read as.
+fn breaks_plain(string: &str) -> bool {
+    string.is_empty()
+        || string.starts_with(' ')
+        || string.ends_with(' ')
+        || string.starts_with(['&', '*', '?', '|', '-', '<', '>', '=', '!', '%', '@'])
+        || string.contains(|c: char| {
+            matches!(
+                c,
+                ':' | '{' | '}' | '[' | ']' | ',' | '#' | '`' | '"' | '\'' | '\\'
+                    | '\0'..='\x06' | '\t' | '\n' | '\r' | '\x0e'..='\x1a' | '\x1c'..='\x1f'
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(source: &str) -> String {
+        emit_yaml(&load_yaml(source).unwrap().unwrap())
+    }
+
+    /// Already in the emitter's layout, so it must come back unchanged.
+    const FORMATTED: &str = "\
+base: &base
+  timeout: 30
+  tags: !!set
+    a: ~
+dev:
+  <<: *base
+  name: !env DEV_NAME
+  ramp: !ramp 0.5
+  include: !include 12
+  flag: !!str true
+  script: |
+    echo one
+    echo two
+  summary: >
+    folded text continues
+  quoted: 'single'
+  double: \"two words\"
+list:
+  - &first x
+  - *first
+";
+
+    #[test]
+    fn emit_keeps_anchors_tags_and_block_scalars() {
+        assert_eq!(round_trip(FORMATTED), FORMATTED);
+    }
+
+    #[test]
+    fn emit_keeps_values() {
+        let doc = load_yaml(FORMATTED).unwrap().unwrap();
+        let again = load_yaml(&emit_yaml(&doc)).unwrap().unwrap();
+        assert_eq!(resolve(&again.root, &again.meta), resolve(&doc.root, &doc.meta));
+        let script = lookup(&again.root, &["dev".to_string(), "script".to_string()]);
+        assert_eq!(script, Some(&Yaml::String("echo one\necho two\n".to_string())));
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let source = "a:   1\nb: [x,   y]\nc: {k: v}\nd:\n- 1\n-   2\ne: &e\n    f: >-\n      long\n      line\ng: *e\n";
+        let once = round_trip(source);
+        assert_eq!(round_trip(&once), once);
+    }
+
+    #[test]
+    fn keys_sharing_a_path_are_rejected() {
+        let error = load_yaml("1: a\n\"1\": b\n").err().expect("clashing keys should not load");
+        assert!(error.to_string().contains("cannot be told apart"), "{error}");
+        assert!(load_yaml("1: a\n\"2\": b\n").is_ok());
+    }
+}